@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::client::{aggregations::Aggregation};
+use crate::client::aggregations::{Aggregation, StateAggregation};
 use crate::common::model::Value;
 
 pub fn build_integer_aggregates(mut values: Vec<i128>) -> Aggregation {
@@ -39,12 +39,14 @@ pub fn build_integer_aggregates(mut values: Vec<i128>) -> Aggregation {
     let ammount = values.len() as u64;
 
     Aggregation {
-        average,
-        median,
-        moda,
-        min,
-        max,
+        average: Some(average),
+        median: Some(median),
+        moda: Some(moda),
+        min: Some(min),
+        max: Some(max),
         ammount,
+        excluded: 0,
+        states: None,
     }
 }
 
@@ -78,19 +80,22 @@ pub fn build_floatin_point_aggregates(mut values: Vec<f64>) -> Aggregation {
 
     let average = Value::FloatingPoint(average);
     let median = Value::FloatingPoint(median);
-    let moda = Value::Integer(moda);
+    //Stored with the rest so it can be read back as a floating point
+    let moda = Value::FloatingPoint(moda as f64);
     let min = Value::FloatingPoint(min);
     let max = Value::FloatingPoint(max);
 
     let ammount = values.len() as u64;
 
     Aggregation {
-        average,
-        median,
-        moda,
-        min,
-        max,
+        average: Some(average),
+        median: Some(median),
+        moda: Some(moda),
+        min: Some(min),
+        max: Some(max),
         ammount,
+        excluded: 0,
+        states: None,
     }
 }
 
@@ -127,11 +132,106 @@ pub fn build_boolean_aggregates(values: Vec<bool>) -> Aggregation {
     let ammount = values.len() as u64;
 
     Aggregation {
-        average,
-        median,
+        average: Some(average),
+        median: Some(median),
+        moda: Some(moda),
+        min: Some(min),
+        max: Some(max),
+        ammount,
+        excluded: 0,
+        states: None,
+    }
+}
+
+//Only the most repeated text makes sense, ties go to the latest one
+pub fn build_text_aggregates(values: Vec<String>) -> Aggregation {
+    let ammount = values.len() as u64;
+
+    let mut frequency: HashMap<String, (u64, usize)> = HashMap::new();
+    for (index, value) in values.into_iter().enumerate() {
+        let entry = frequency.entry(value).or_insert((0, index));
+        entry.0 += 1;
+        entry.1 = index;
+    }
+
+    let moda = frequency
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(val, _)| val)
+        .unwrap();
+
+    Aggregation {
+        average: None,
+        median: None,
+        moda: Some(Value::Text(moda)),
+        min: None,
+        max: None,
+        ammount,
+        excluded: 0,
+        states: None,
+    }
+}
+
+//Each poll holds its state until the next one, the last until the end of the period
+pub fn build_state_aggregates(
+    polls: Vec<(u64, i128)>,
+    finish_millis: u64,
+    states: &BTreeMap<i64, String>,
+) -> Aggregation {
+    let ammount = polls.len() as u64;
+
+    //Every configured state is reported, even if it never happened
+    let mut aggregates: BTreeMap<i128, StateAggregation> = states
+        .iter()
+        .map(|(value, label)| {
+            (
+                *value as i128,
+                StateAggregation {
+                    value: *value as i128,
+                    label: Some(label.clone()),
+                    millis_in_state: 0,
+                    transitions: 0,
+                },
+            )
+        })
+        .collect();
+
+    let mut previous: Option<i128> = None;
+    for (index, (millis, value)) in polls.iter().enumerate() {
+        let until = polls
+            .get(index + 1)
+            .map(|(next_millis, _)| *next_millis)
+            .unwrap_or(finish_millis);
+
+        let aggregate = aggregates.entry(*value).or_insert(StateAggregation {
+            value: *value,
+            label: None,
+            millis_in_state: 0,
+            transitions: 0,
+        });
+
+        aggregate.millis_in_state += until.saturating_sub(*millis);
+        if previous.is_some_and(|previous| previous != *value) {
+            aggregate.transitions += 1;
+        }
+        previous = Some(*value);
+    }
+
+    //The state the value spent the most time in, ties go to the lowest value
+    let moda = aggregates
+        .values()
+        .rev()
+        .max_by_key(|aggregate| aggregate.millis_in_state)
+        .map(|aggregate| Value::Integer(aggregate.value));
+
+    Aggregation {
+        average: None,
+        median: None,
         moda,
-        min,
-        max,
+        min: None,
+        max: None,
         ammount,
+        excluded: 0,
+        states: Some(aggregates.into_values().collect()),
     }
 }
@@ -4,11 +4,12 @@ use r2d2_sqlite::SqliteConnectionManager;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
 use crate::client::data;
 use crate::client::data::read::get_polls_between;
 use crate::client::model::PolledConnection;
-use crate::common::model::{DataType, Value};
+use crate::common::model::{Value, ValueFormattingParams};
 
 mod build_aggregates;
 
@@ -43,21 +44,38 @@ pub struct AggregationInfo {
     pub aggregation: Aggregation,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Aggregation {
-    pub average: Value,
-    pub median: Value,
-    pub moda: Value,
-    pub min: Value,
-    pub max: Value,
+    //Text values only have a moda, and periods without good polls have nothing but the excluded
+    pub average: Option<Value>,
+    pub median: Option<Value>,
+    pub moda: Option<Value>,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
     pub ammount: u64,
+    //Polls with a bad quality inside the period, not taken into account
+    #[serde(default)]
+    pub excluded: u64,
+    //Enumerated values, replaces the average, median, min and max
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub states: Option<Vec<StateAggregation>>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StateAggregation {
+    pub value: i128,
+    //Missing for values that aren't in the states map
+    pub label: Option<String>,
+    pub millis_in_state: u64,
+    //Times the value changed into this state during the period
+    pub transitions: u64,
 }
 
 pub struct OnGoingAggregationInfo {
     last_min_aggregated: std::time::SystemTime,
     last_hour_aggregated: std::time::SystemTime,
     last_day_aggregated: std::time::SystemTime,
-    data_type: DataType,
+    formatting_params: ValueFormattingParams,
 
     max_polls: Option<u64>,
     max_min_aggregations: Option<u64>,
@@ -71,7 +89,7 @@ impl OnGoingAggregationInfo {
         max_min_aggregations: Option<u64>,
         max_hour_aggregations: Option<u64>,
         max_day_aggregations: Option<u64>,
-        data_type: DataType,
+        formatting_params: ValueFormattingParams,
     ) -> Self {
         OnGoingAggregationInfo {
             last_min_aggregated: std::time::SystemTime::now(),
@@ -81,7 +99,7 @@ impl OnGoingAggregationInfo {
             max_min_aggregations,
             max_hour_aggregations,
             max_day_aggregations,
-            data_type,
+            formatting_params,
         }
     }
 }
@@ -137,22 +155,80 @@ fn create_single_aggregate(
     id: &String,
     start_time: std::time::SystemTime,
     finish_time: std::time::SystemTime,
-    data_type: &DataType,
+    formatting_params: &ValueFormattingParams,
     period: Period,
     conn: &r2d2::PooledConnection<SqliteConnectionManager>,
 ) {
-    let values = get_polls_between(conn, id, data_type, start_time, finish_time).unwrap();
+    //Engineering values, scaled values are all floating point
+    let values = get_polls_between(conn, id, formatting_params, start_time, finish_time).unwrap();
+
+    let (values, bad_polls): (Vec<_>, Vec<_>) = values
+        .into_iter()
+        .partition(|poll| poll.value.is_some());
 
     if values.is_empty() {
+        //Periods where every poll failed still get a row, so the outage shows in the aggregations
+        if !bad_polls.is_empty() {
+            let aggregate_info = AggregationInfo {
+                value_id: id.clone(),
+                start_time,
+                end_time: finish_time,
+                period,
+                aggregation: Aggregation {
+                    average: None,
+                    median: None,
+                    moda: None,
+                    min: None,
+                    max: None,
+                    ammount: 0,
+                    excluded: bad_polls.len() as u64,
+                    states: formatting_params.states.as_ref().map(|_| vec![]),
+                },
+            };
+
+            data::write::insert_modbus_aggregate(conn, aggregate_info).unwrap();
+        }
+
+        return;
+    }
+
+    if let Some(states) = &formatting_params.states {
+        let polls = values
+            .iter()
+            .filter_map(|poll| match poll.value {
+                Some(Value::Integer(value)) => Some((poll.millis_since_epoch, value)),
+                _ => None,
+            })
+            .collect();
+
+        let finish_millis = finish_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let mut aggregate = build_aggregates::build_state_aggregates(polls, finish_millis, states);
+        aggregate.excluded = bad_polls.len() as u64;
+
+        let aggregate_info = AggregationInfo {
+            value_id: id.clone(),
+            start_time,
+            end_time: finish_time,
+            period,
+            aggregation: aggregate,
+        };
+
+        data::write::insert_modbus_aggregate(conn, aggregate_info).unwrap();
         return;
     }
 
-    let aggregate = match values.first().unwrap().value {
+    let values: Vec<Value> = values.into_iter().filter_map(|poll| poll.value).collect();
+
+    let mut aggregate = match values.first().unwrap() {
         Value::Integer(_) => {
             let integers: Vec<i128> = values
                 .into_iter()
                 .filter_map(|n| {
-                    if let Value::Integer(v) = n.value {
+                    if let Value::Integer(v) = n {
                         Some(v)
                     } else {
                         None
@@ -165,7 +241,7 @@ fn create_single_aggregate(
             let floating_points: Vec<f64> = values
                 .into_iter()
                 .filter_map(|n| {
-                    if let Value::FloatingPoint(v) = n.value {
+                    if let Value::FloatingPoint(v) = n {
                         Some(v)
                     } else {
                         None
@@ -178,7 +254,7 @@ fn create_single_aggregate(
             let booleans: Vec<bool> = values
                 .into_iter()
                 .filter_map(|n| {
-                    if let Value::Boolean(v) = n.value {
+                    if let Value::Boolean(v) = n {
                         Some(v)
                     } else {
                         None
@@ -188,8 +264,24 @@ fn create_single_aggregate(
 
             build_aggregates::build_boolean_aggregates(booleans)
         }
+        Value::Text(_) => {
+            let texts: Vec<String> = values
+                .into_iter()
+                .filter_map(|n| {
+                    if let Value::Text(v) = n {
+                        Some(v)
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            build_aggregates::build_text_aggregates(texts)
+        }
     };
 
+    aggregate.excluded = bad_polls.len() as u64;
+
     let aggregate_info = AggregationInfo {
         value_id: id.clone(),
         start_time,
@@ -221,7 +313,7 @@ fn create_aggregates(
                 id,
                 start_time,
                 finish_time,
-                &info.data_type,
+                &info.formatting_params,
                 Period::Minute,
                 &db_access,
             );
@@ -245,7 +337,7 @@ fn create_aggregates(
                 id,
                 start_time,
                 finish_time,
-                &info.data_type,
+                &info.formatting_params,
                 Period::Hour,
                 &db_access,
             );
@@ -269,7 +361,7 @@ fn create_aggregates(
                 id,
                 start_time,
                 finish_time,
-                &info.data_type,
+                &info.formatting_params,
                 Period::Day,
                 &db_access,
             );
@@ -316,7 +408,7 @@ pub async fn start_aggregation_building(
                         value.max_minute_aggregations_to_keep,
                         value.max_hour_aggregations_to_keep,
                         value.max_day_aggregations_to_keep,
-                        value.formatting_params.data_type.clone(),
+                        value.formatting_params.clone(),
                     ),
                 );
             }
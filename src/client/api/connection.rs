@@ -0,0 +1,41 @@
+use crate::client::api::ApiState;
+use crate::client::comm::{ConnectionHealth, SlaveHealth};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+pub async fn list_connections(State(state): State<Arc<ApiState>>) -> Json<Vec<ConnectionHealth>> {
+    let mut connections = vec![];
+
+    for connection in &state.health {
+        connections.push(connection.lock().await.clone());
+    }
+
+    Json(connections)
+}
+
+pub async fn get_slave_status(
+    State(state): State<Arc<ApiState>>,
+    Path((connection_name, slave_id)): Path<(String, u8)>,
+) -> Result<Json<SlaveHealth>, Response> {
+    //Serial devices have slashes in their names, so connections can also be picked by position
+    for (index, connection) in state.health.iter().enumerate() {
+        let connection = connection.lock().await;
+
+        if connection.name != connection_name && index.to_string() != connection_name {
+            continue;
+        }
+
+        return match connection.get_slave(slave_id) {
+            Some(slave) => Ok(Json(slave.clone())),
+            None => Err((StatusCode::NOT_FOUND, "Slave not found").into_response()),
+        };
+    }
+
+    Err((StatusCode::NOT_FOUND, "Connection not found").into_response())
+}
@@ -0,0 +1,9 @@
+use crate::client::api::ApiState;
+use crate::client::data::DbMetrics;
+
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+pub async fn get_metrics(State(state): State<Arc<ApiState>>) -> Json<DbMetrics> {
+    Json(state.db_metrics.lock().await.clone())
+}
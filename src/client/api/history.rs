@@ -13,12 +13,13 @@ use crate::client::{
     data::ModbusPoll,
 };
 
-use crate::common::model::DataType;
-
 #[derive(Debug, Deserialize)]
 pub struct HistoryParams {
     start_date: Option<u64>,
     end_date: Option<u64>,
+    //Take precedence over the dates in seconds
+    start_date_ms: Option<u64>,
+    end_date_ms: Option<u64>,
     max_group: Option<Period>,
     min_group: Option<Period>,
 }
@@ -35,14 +36,19 @@ pub async fn get_history(
     Query(params): Query<HistoryParams>,
     State(state): State<Arc<ApiState>>,
 ) -> Result<Json<Vec<HistoryResult>>, Response> {
-    let start_date = if let Some(start_date) = params.start_date {
+    let start_date = if let Some(start_date) = params.start_date_ms {
+        UNIX_EPOCH + std::time::Duration::from_millis(start_date)
+    } else if let Some(start_date) = params.start_date {
         UNIX_EPOCH + std::time::Duration::from_secs(start_date)
     } else {
         UNIX_EPOCH
     };
 
-    let end_date = if let Some(end_date) = params.end_date {
-        UNIX_EPOCH + std::time::Duration::from_secs(end_date)
+    let end_date = if let Some(end_date) = params.end_date_ms {
+        UNIX_EPOCH + std::time::Duration::from_millis(end_date)
+    } else if let Some(end_date) = params.end_date {
+        //Whole second, so polls inside the last second are still returned
+        UNIX_EPOCH + std::time::Duration::from_millis(end_date.saturating_mul(1000).saturating_add(999))
     } else {
         UNIX_EPOCH + std::time::Duration::from_secs(i64::MAX as u64)
     };
@@ -50,24 +56,22 @@ pub async fn get_history(
     let max_group = params.max_group.unwrap_or(Period::Day);
     let min_group = params.min_group.unwrap_or(Period::NoGrouping);
 
-    let mut found = false;
-    let mut data_type = DataType::Boolean;
+    let mut formatting_params = None;
 
     'search_loop: for connection in &state.config {
         for slave in &connection.slaves {
             for value in &slave.values {
                 if value.id == value_id {
-                    found = true;
-                    data_type = value.formatting_params.data_type.clone();
+                    formatting_params = Some(value.formatting_params.clone());
                     break 'search_loop;
                 }
             }
         }
     }
 
-    if !found {
+    let Some(formatting_params) = formatting_params else {
         return Err((StatusCode::NOT_FOUND, "Value was not configured").into_response());
-    }
+    };
 
     let conn = state.db.get().or_else(|_| {
         Err((StatusCode::INTERNAL_SERVER_ERROR, "Access to db failed").into_response())
@@ -76,7 +80,7 @@ pub async fn get_history(
     let mut result = vec![];
 
     let aggregations = crate::client::data::read::get_aggregates_between(
-        &conn, &value_id, &data_type, start_date, end_date, max_group, min_group,
+        &conn, &value_id, &formatting_params, start_date, end_date, max_group, min_group,
     )
     .or_else(|_| Err((StatusCode::INTERNAL_SERVER_ERROR, "Access to db failed").into_response()))?;
 
@@ -86,7 +90,7 @@ pub async fn get_history(
 
     if min_group == Period::NoGrouping {
         let polls = crate::client::data::read::get_polls_between(
-            &conn, &value_id, &data_type, start_date, end_date,
+            &conn, &value_id, &formatting_params, start_date, end_date,
         )
         .or_else(|_| {
             Err((StatusCode::INTERNAL_SERVER_ERROR, "Access to db failed").into_response())
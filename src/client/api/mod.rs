@@ -3,30 +3,59 @@ use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use std::net::SocketAddr;
 
+use crate::client::comm::{ConnectionHealth, ModbusWriter};
+use crate::client::data::{DbMetrics, ModbusPoll};
 use crate::client::model::PolledConnection;
 use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
 
 mod common;
 mod config;
+mod connection;
+mod db;
 mod history;
+mod stream;
 mod value;
 
 pub struct ApiState {
     pub config: Vec<PolledConnection>,
     pub db: Arc<Pool<SqliteConnectionManager>>,
+    pub writer: ModbusWriter,
+    pub health: Vec<Arc<Mutex<ConnectionHealth>>>,
+    pub db_metrics: Arc<Mutex<DbMetrics>>,
+    pub stream: broadcast::Sender<ModbusPoll>,
 }
 
 pub async fn serve_api(
     config: Vec<PolledConnection>,
     db: Arc<Pool<SqliteConnectionManager>>,
+    writer: ModbusWriter,
+    health: Vec<Arc<Mutex<ConnectionHealth>>>,
+    db_metrics: Arc<Mutex<DbMetrics>>,
+    stream: broadcast::Sender<ModbusPoll>,
     port: u16,
 ) {
-    let state = Arc::new(ApiState { config, db });
+    let state = Arc::new(ApiState {
+        config,
+        db,
+        writer,
+        health,
+        db_metrics,
+        stream,
+    });
     let api = Router::new()
         .route("/values", get(common::list_values))
-        .route("/values/{id}", get(value::get_value))
+        .route("/values/{id}", get(value::get_value).put(value::set_value))
         .route("/values/{id}/config", get(config::get_config))
         .route("/values/{id}/history", get(history::get_history))
+        .route("/connections", get(connection::list_connections))
+        .route(
+            "/connections/{connection}/slaves/{id}/status",
+            get(connection::get_slave_status),
+        )
+        .route("/db/metrics", get(db::get_metrics))
+        .route("/api/v1/stream", get(stream::get_sse_stream))
+        .route("/api/v1/stream/ws", get(stream::get_ws_stream))
         .with_state(state);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
@@ -0,0 +1,222 @@
+use crate::client::{api::ApiState, data::ModbusPoll};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::{collections::HashSet, convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    //Comma separated value ids
+    values: Option<String>,
+    slave: Option<u8>,
+    connection: Option<String>,
+    tag: Option<String>,
+}
+
+//Every filter given has to match, no filters subscribes to every value
+fn get_subscribed_values(
+    state: &ApiState,
+    params: &StreamParams,
+) -> Result<HashSet<String>, Response> {
+    let requested_values: Option<HashSet<String>> = params.values.as_ref().map(|values| {
+        values
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    });
+
+    let mut subscribed_values = HashSet::new();
+    let mut configured_values = HashSet::new();
+
+    for connection in &state.config {
+        for slave in &connection.slaves {
+            for value in &slave.values {
+                configured_values.insert(value.id.clone());
+
+                if let Some(requested_values) = &requested_values {
+                    if !requested_values.contains(&value.id) {
+                        continue;
+                    }
+                }
+
+                if params.slave.is_some_and(|slave_id| slave_id != slave.id) {
+                    continue;
+                }
+
+                if let Some(connection_name) = &params.connection {
+                    if *connection_name != connection.get_name() {
+                        continue;
+                    }
+                }
+
+                if let Some(tag) = &params.tag {
+                    if !value.tags.contains(tag) {
+                        continue;
+                    }
+                }
+
+                subscribed_values.insert(value.id.clone());
+            }
+        }
+    }
+
+    if let Some(requested_values) = &requested_values {
+        for value in requested_values {
+            if !configured_values.contains(value) {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("Value {} was not configured", value),
+                )
+                    .into_response());
+            }
+        }
+    }
+
+    Ok(subscribed_values)
+}
+
+//Last stored poll of every subscribed value, values never polled are left out
+fn get_snapshot(state: &ApiState, subscribed_values: &HashSet<String>) -> Vec<ModbusPoll> {
+    let mut snapshot = vec![];
+
+    let conn = match state.db.get() {
+        Ok(conn) => conn,
+        Err(err) => {
+            warn!("Couldn't access db for the stream snapshot: {}", err);
+            return snapshot;
+        }
+    };
+
+    for connection in &state.config {
+        for slave in &connection.slaves {
+            for value in &slave.values {
+                if !subscribed_values.contains(&value.id) {
+                    continue;
+                }
+
+                if let Ok(poll) = crate::client::data::read::get_last_poll(
+                    &conn,
+                    value.id.clone(),
+                    &value.formatting_params,
+                ) {
+                    snapshot.push(poll);
+                }
+            }
+        }
+    }
+
+    snapshot
+}
+
+//Next poll of a subscribed value, None once the stream is closed
+async fn next_poll(
+    receiver: &mut broadcast::Receiver<ModbusPoll>,
+    subscribed_values: &HashSet<String>,
+) -> Option<ModbusPoll> {
+    loop {
+        match receiver.recv().await {
+            Ok(poll) => {
+                if subscribed_values.contains(&poll.value_id) {
+                    return Some(poll);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Live subscriber fell behind, {} polls skipped", skipped);
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+fn poll_to_event(poll: &ModbusPoll) -> Event {
+    Event::default()
+        .event("poll")
+        .json_data(poll)
+        .unwrap_or_else(|_| Event::default().event("error"))
+}
+
+pub async fn get_sse_stream(
+    State(state): State<Arc<ApiState>>,
+    Query(params): Query<StreamParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let subscribed_values = get_subscribed_values(&state, &params)?;
+
+    //Subscribing before the snapshot so no poll is lost in between
+    let receiver = state.stream.subscribe();
+
+    let snapshot = get_snapshot(&state, &subscribed_values);
+
+    let snapshot = stream::iter(snapshot).map(|poll| Ok(poll_to_event(&poll)));
+
+    let live = stream::unfold(
+        (receiver, subscribed_values),
+        |(mut receiver, subscribed_values)| async move {
+            let poll = next_poll(&mut receiver, &subscribed_values).await?;
+            Some((Ok(poll_to_event(&poll)), (receiver, subscribed_values)))
+        },
+    );
+
+    Ok(Sse::new(snapshot.chain(live)).keep_alive(KeepAlive::default()))
+}
+
+pub async fn get_ws_stream(
+    State(state): State<Arc<ApiState>>,
+    Query(params): Query<StreamParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, Response> {
+    let subscribed_values = get_subscribed_values(&state, &params)?;
+
+    Ok(ws.on_upgrade(move |socket| handle_ws(socket, state, subscribed_values)))
+}
+
+async fn handle_ws(mut socket: WebSocket, state: Arc<ApiState>, subscribed_values: HashSet<String>) {
+    let mut receiver = state.stream.subscribe();
+
+    for poll in get_snapshot(&state, &subscribed_values) {
+        if send_poll(&mut socket, &poll).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            poll = next_poll(&mut receiver, &subscribed_values) => {
+                let Some(poll) = poll else {
+                    break;
+                };
+
+                if send_poll(&mut socket, &poll).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                //Anything sent by the client is ignored, we only care about it leaving
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    debug!("Live subscriber disconnected");
+}
+
+async fn send_poll(socket: &mut WebSocket, poll: &ModbusPoll) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(poll).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text.into())).await
+}
@@ -1,5 +1,6 @@
+use crate::client::comm::WriteError;
 use crate::client::{api::ApiState, data::ModbusPoll};
-use crate::common::model::DataType;
+use crate::common::model::Value;
 
 use axum::{
     extract::{Path, State},
@@ -14,30 +15,28 @@ pub async fn get_value(
     State(state): State<Arc<ApiState>>,
     Path(id): Path<String>,
 ) -> Result<Json<ModbusPoll>, Response> {
-    let mut found = false;
-    let mut data_type = DataType::Boolean;
+    let mut formatting_params = None;
 
     'search_loop: for connection in &state.config {
         for slave in &connection.slaves {
             for value in &slave.values {
                 if value.id == id {
-                    found = true;
-                    data_type = value.formatting_params.data_type.clone();
+                    formatting_params = Some(value.formatting_params.clone());
                     break 'search_loop;
                 }
             }
         }
     }
 
-    if !found {
+    let Some(formatting_params) = formatting_params else {
         return Err((StatusCode::NOT_FOUND, "Value was not configured").into_response());
-    }
+    };
 
     let db_conn = state.db.get().or_else(|_| {
         Err((StatusCode::INTERNAL_SERVER_ERROR, "Access to db failed").into_response())
     })?;
 
-    let poll = crate::client::data::read::get_last_poll(&db_conn, id, data_type);
+    let poll = crate::client::data::read::get_last_poll(&db_conn, id, &formatting_params);
 
     if let Ok(poll) = poll {
         Ok(Json(poll))
@@ -45,3 +44,29 @@ pub async fn get_value(
         Err((StatusCode::NOT_FOUND, "Value not found").into_response())
     }
 }
+
+pub async fn set_value(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    Json(value): Json<Value>,
+) -> Result<Json<Value>, Response> {
+    match state.writer.write_value(&id, value.clone()).await {
+        Ok(()) => Ok(Json(value)),
+        Err(WriteError::NotFound) => {
+            Err((StatusCode::NOT_FOUND, "Value was not configured").into_response())
+        }
+        Err(WriteError::NotWritable(err)) | Err(WriteError::InvalidValue(err)) => {
+            Err((StatusCode::BAD_REQUEST, err).into_response())
+        }
+        Err(WriteError::Exception(exception_code)) => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Slave answered with exception code {:?}", exception_code),
+        )
+            .into_response()),
+        Err(WriteError::Connection(err)) => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Couldn't reach the slave: {}", err),
+        )
+            .into_response()),
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use tweakable_modbus::{ModbusAddress, ModbusMasterConnectionParams, ModbusResult};
+
+use crate::client::comm::framed::FramedMasterConnection;
+use crate::client::model::PolledConnection;
+use crate::common::protocol::pdu::Request;
+
+pub struct MasterConnection {
+    conn: FramedMasterConnection,
+}
+
+//Context of the errors that happened while opening the link, the only ones worth backing off for
+#[derive(Debug)]
+pub struct ConnectFailure;
+
+impl Display for ConnectFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Couldn't connect to the slave")
+    }
+}
+
+impl MasterConnection {
+    pub fn new(config: &PolledConnection) -> Self {
+        let socket = SocketAddr::new(config.ip, config.get_port());
+
+        MasterConnection {
+            conn: FramedMasterConnection::new(config.transport.clone(), socket, config.tls.clone()),
+        }
+    }
+
+    pub fn add_query(&mut self, slave_id: u8, request: Request) {
+        self.conn.add_query(slave_id, request);
+    }
+
+    pub async fn query_with_params(
+        &mut self,
+        params: ModbusMasterConnectionParams,
+    ) -> Result<HashMap<ModbusAddress, ModbusResult>> {
+        self.conn.query(params.max_response_time).await
+    }
+}
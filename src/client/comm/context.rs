@@ -1,14 +1,20 @@
 use anyhow::Result;
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
 use tracing::{debug, info, info_span, warn, Instrument};
-use tweakable_modbus::{ModbusAddress, ModbusMasterConnection, ModbusResult, ModbusTable};
-
-use crate::client::data::InsertValueMessage;
+use tweakable_modbus::{
+    ModbusAddress, ModbusDataType, ModbusMasterConnectionParams, ModbusResult, ModbusTable,
+};
+
+use crate::client::comm::connection::{ConnectFailure, MasterConnection};
+use crate::client::comm::health::{ConnectionHealth, SlaveOutcome};
+use crate::client::comm::writer::{WriteError, WriteTarget};
+use crate::client::data::{InsertValueMessage, PollQuality};
 use crate::client::model::{PolledConnection, PolledValue};
+use crate::common::model::Value;
+use crate::common::protocol::pdu::{self, Request};
 use crate::common::value_processing;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -30,6 +36,9 @@ pub struct ModbusCommContext {
     value_bindings: Arc<HashMap<ModbusAddress, Vec<ValueBinding>>>,
     config: PolledConnection,
     insert_channel: Sender<InsertValueMessage>,
+    master_connection: Arc<Mutex<MasterConnection>>,
+    params: ModbusMasterConnectionParams,
+    health: Arc<Mutex<ConnectionHealth>>,
 }
 
 impl ModbusCommContext {
@@ -38,117 +47,185 @@ impl ModbusCommContext {
 
         let value_bindings = Arc::new(Self::build_value_bindings(&config));
 
+        let master_connection = Arc::new(Mutex::new(MasterConnection::new(&config)));
+
+        let params = ModbusMasterConnectionParams {
+            max_response_time: config.config.max_response_time,
+            max_simultaneous_transactions: config.config.max_simultaneous_connections,
+        };
+
+        let health = Arc::new(Mutex::new(ConnectionHealth::new(
+            config.get_name(),
+            config.slaves.iter().map(|slave| slave.id).collect(),
+            config.config.min_reconnect_delay,
+            config.config.max_reconnect_delay,
+        )));
+
         ModbusCommContext {
             config,
             queries,
             value_bindings,
             insert_channel,
+            master_connection,
+            params,
+            health,
+        }
+    }
+
+    pub fn get_health(&self) -> Arc<Mutex<ConnectionHealth>> {
+        self.health.clone()
+    }
+
+    pub fn get_write_targets(&self) -> Vec<WriteTarget> {
+        let mut targets = vec![];
+
+        for slave in &self.config.slaves {
+            for value in &slave.values {
+                targets.push(WriteTarget {
+                    slave_id: slave.id,
+                    config: value.clone(),
+                    master_connection: self.master_connection.clone(),
+                    params: self.params,
+                });
+            }
         }
+
+        targets
     }
 
-    fn load_queries(modbus_conn: &mut ModbusMasterConnection, queries: &Vec<Query>) {
+    fn load_queries(modbus_conn: &mut MasterConnection, queries: &Vec<Query>) {
         for query in queries {
-            match query.table {
-                ModbusTable::Coils => {
-                    modbus_conn
-                        .add_read_coils_query(query.slave_id, query.starting_address, query.ammount)
-                        .unwrap();
-                }
-                ModbusTable::DiscreteInput => {
-                    modbus_conn
-                        .add_read_discrete_inputs_query(
-                            query.slave_id,
-                            query.starting_address,
-                            query.ammount,
-                        )
-                        .unwrap();
-                }
-                ModbusTable::InputRegisters => {
-                    modbus_conn
-                        .add_read_input_registers_query(
-                            query.slave_id,
-                            query.starting_address,
-                            query.ammount,
-                        )
-                        .unwrap();
-                }
-                ModbusTable::HoldingRegisters => {
-                    modbus_conn
-                        .add_read_holding_registers_query(
-                            query.slave_id,
-                            query.starting_address,
-                            query.ammount,
-                        )
-                        .unwrap();
+            let request = match query.table {
+                ModbusTable::Coils => Request::ReadCoils {
+                    address: query.starting_address,
+                    ammount: query.ammount,
+                },
+                ModbusTable::DiscreteInput => Request::ReadDiscreteInputs {
+                    address: query.starting_address,
+                    ammount: query.ammount,
+                },
+                ModbusTable::InputRegisters => Request::ReadInputRegisters {
+                    address: query.starting_address,
+                    ammount: query.ammount,
+                },
+                ModbusTable::HoldingRegisters => Request::ReadHoldingRegisters {
+                    address: query.starting_address,
+                    ammount: query.ammount,
+                },
+            };
+
+            modbus_conn.add_query(query.slave_id, request);
+        }
+    }
+
+    //Addresses of the queries that start a value, in query order
+    fn get_query_value_addresses(
+        queries: &Vec<Query>,
+        bindings: &HashMap<ModbusAddress, Vec<ValueBinding>>,
+    ) -> Vec<ModbusAddress> {
+        let mut addresses = vec![];
+
+        for query in queries {
+            for offset in 0..query.ammount {
+                let address = ModbusAddress {
+                    slave_id: query.slave_id,
+                    table: query.table,
+                    address: query.starting_address + offset,
+                };
+
+                if bindings.contains_key(&address) {
+                    addresses.push(address);
                 }
             }
         }
+
+        addresses
     }
 
     async fn handle_results(
+        queries: &Vec<Query>,
         results: HashMap<ModbusAddress, ModbusResult>,
         bindings: Arc<HashMap<ModbusAddress, Vec<ValueBinding>>>,
         tx: Sender<InsertValueMessage>,
     ) {
-        for address in results.keys() {
-            if !bindings.contains_key(address) {
-                continue;
-            }
-
-            let address_bindings = bindings.get(address).unwrap();
+        for address in Self::get_query_value_addresses(queries, &bindings) {
+            let address_bindings = bindings.get(&address).unwrap();
 
             for address_binding in address_bindings {
                 let mut value_registers = vec![];
                 let mut address_pointer = address.clone();
-                let mut success = true;
+                let mut quality = PollQuality::Good;
 
                 for _i in 0..address_binding.needed_ammount {
-                    if !results.contains_key(&address_pointer) {
-                        success = false;
-                        break;
-                    }
-
-                    let result = results.get(&address_pointer).unwrap();
-
-                    if let ModbusResult::Error(exception_code) = result {
-                        warn!(
-                            "Exception code {:?} was received when querying for value {}",
-                            exception_code, address_binding.config.id
-                        );
-                    }
-
-                    if let ModbusResult::ReadResult(value) = results.get(&address_pointer).unwrap()
-                    {
-                        value_registers.push(value.clone());
-                    } else {
-                        success = false;
-                        break;
+                    match results.get(&address_pointer) {
+                        Some(ModbusResult::ReadResult(value)) => value_registers.push(value.clone()),
+                        Some(ModbusResult::Error(exception_code)) => {
+                            warn!(
+                                "Exception code {:?} was received when querying for value {}",
+                                exception_code, address_binding.config.id
+                            );
+                            quality =
+                                PollQuality::Exception(pdu::exception_code_to_u8(exception_code));
+                            break;
+                        }
+                        _ => {
+                            warn!(
+                                "Registers were missing to build value {}",
+                                address_binding.config.id
+                            );
+                            quality = PollQuality::Timeout;
+                            break;
+                        }
                     }
 
                     address_pointer.address += 1;
                 }
 
-                if !success {
-                    warn!(
-                        "Registers were missing to build value {}",
-                        address_binding.config.id
+                let value = if quality == PollQuality::Good {
+                    let value = value_processing::registers_to_bytes(
+                        value_registers,
+                        &address_binding.config.formatting_params,
                     );
-                    continue;
-                }
 
-                let value =
-                    value_processing::registers_to_bytes(value_registers, &address_binding.config.formatting_params);
+                    info!(
+                        "Value {} received poll {:?}",
+                        address_binding.config.id.clone(),
+                        value
+                    );
 
-                info!(
-                    "Value {} received poll {:?}",
-                    address_binding.config.id.clone(),
                     value
-                );
+                } else {
+                    vec![]
+                };
 
                 let insert = InsertValueMessage {
                     name: address_binding.config.id.clone(),
                     timestamp: std::time::SystemTime::now(),
-                    value: value.clone(),
+                    value,
+                    quality,
+                };
+
+                tx.send(insert).await.expect("Couldn't send message to db");
+            }
+        }
+    }
+
+    //Every value of the failed queries gets a sample, so gaps are visible in the history
+    async fn handle_failed_queries(
+        queries: &Vec<Query>,
+        quality: PollQuality,
+        bindings: Arc<HashMap<ModbusAddress, Vec<ValueBinding>>>,
+        tx: Sender<InsertValueMessage>,
+    ) {
+        let timestamp = std::time::SystemTime::now();
+
+        for address in Self::get_query_value_addresses(queries, &bindings) {
+            for address_binding in bindings.get(&address).unwrap() {
+                let insert = InsertValueMessage {
+                    name: address_binding.config.id.clone(),
+                    timestamp,
+                    value: vec![],
+                    quality: quality.clone(),
                 };
 
                 tx.send(insert).await.expect("Couldn't send message to db");
@@ -156,19 +233,96 @@ impl ModbusCommContext {
         }
     }
 
+    fn get_error_quality(err: &anyhow::Error) -> PollQuality {
+        let timed_out = err.chain().any(|cause| {
+            cause.is::<tokio::time::error::Elapsed>()
+                || cause
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|err| err.kind() == std::io::ErrorKind::TimedOut)
+        });
+
+        if timed_out {
+            PollQuality::Timeout
+        } else {
+            PollQuality::ConnectionError
+        }
+    }
+
+    //Worst outcome of the queries sent to each slave
+    fn get_slave_outcomes(
+        queries: &Vec<Query>,
+        results: &HashMap<ModbusAddress, ModbusResult>,
+    ) -> Vec<(u8, SlaveOutcome)> {
+        let mut outcomes: HashMap<u8, SlaveOutcome> = HashMap::new();
+
+        for query in queries {
+            let mut outcome = SlaveOutcome::Success;
+
+            for offset in 0..query.ammount {
+                let address = ModbusAddress {
+                    slave_id: query.slave_id,
+                    table: query.table,
+                    address: query.starting_address + offset,
+                };
+
+                match results.get(&address) {
+                    Some(ModbusResult::ReadResult(_)) => {}
+                    Some(ModbusResult::Error(exception_code)) => {
+                        outcome =
+                            SlaveOutcome::Exception(pdu::exception_code_to_u8(exception_code));
+                        break;
+                    }
+                    _ => {
+                        outcome = SlaveOutcome::NoResponse;
+                        break;
+                    }
+                }
+            }
+
+            let previous = outcomes.entry(query.slave_id).or_insert(SlaveOutcome::Success);
+
+            let replace = match (&*previous, &outcome) {
+                (SlaveOutcome::NoResponse, _) => false,
+                (_, SlaveOutcome::NoResponse) => true,
+                (SlaveOutcome::Success, SlaveOutcome::Exception(_)) => true,
+                _ => false,
+            };
+
+            if replace {
+                *previous = outcome;
+            }
+        }
+
+        outcomes.into_iter().collect()
+    }
+
     pub async fn query_loop(
         duration: std::time::Duration,
         queries: Vec<Query>,
-        params: tweakable_modbus::ModbusMasterConnectionParams,
-        master_connection: Arc<Mutex<ModbusMasterConnection>>,
+        params: ModbusMasterConnectionParams,
+        master_connection: Arc<Mutex<MasterConnection>>,
         tx: Sender<InsertValueMessage>,
         bindings: Arc<HashMap<ModbusAddress, Vec<ValueBinding>>>,
+        health: Arc<Mutex<ConnectionHealth>>,
     ) {
         let mut interval = tokio::time::interval(duration);
 
         loop {
             interval.tick().await;
 
+            //The loops share the connection, so any of them failing delays all of them
+            if health.lock().await.is_backing_off() {
+                //The skipped polls are stored too, or the backoff would leave a gap
+                Self::handle_failed_queries(
+                    &queries,
+                    PollQuality::ConnectionError,
+                    bindings.clone(),
+                    tx.clone(),
+                )
+                .await;
+                continue;
+            }
+
             let mut modbus_conn = master_connection.lock().await;
 
             Self::load_queries(&mut modbus_conn, &queries);
@@ -178,16 +332,48 @@ impl ModbusCommContext {
             debug!("Modbus queries sent");
 
             if let Err(err) = results {
-                warn!(
-                    "Modbus query error: \"{}\", proceeding to next query",
-                    err.to_string()
-                );
+                //Timeouts and garbled answers come from a slave that is there, only failing to
+                //connect is worth waiting for
+                if err.downcast_ref::<ConnectFailure>().is_some() {
+                    let backoff = health
+                        .lock()
+                        .await
+                        .record_connection_failure(format!("{:#}", err));
+
+                    warn!(
+                        "Modbus query error: \"{:#}\", retrying in {:?}",
+                        err, backoff
+                    );
+                } else {
+                    health
+                        .lock()
+                        .await
+                        .record_query_failure(format!("{:#}", err));
+
+                    warn!(
+                        "Modbus query error: \"{:#}\", proceeding to next query",
+                        err
+                    );
+                }
+
+                Self::handle_failed_queries(
+                    &queries,
+                    Self::get_error_quality(&err),
+                    bindings.clone(),
+                    tx.clone(),
+                )
+                .await;
                 continue;
             }
 
             let results = results.unwrap();
 
-            Self::handle_results(results, bindings.clone(), tx.clone()).await;
+            health
+                .lock()
+                .await
+                .record_poll(Self::get_slave_outcomes(&queries, &results));
+
+            Self::handle_results(&queries, results, bindings.clone(), tx.clone()).await;
         }
     }
 
@@ -202,20 +388,15 @@ impl ModbusCommContext {
                 .push(query.clone());
         }
 
-        let socket = SocketAddr::new(self.config.ip, self.config.port);
-        let master_connection = Arc::new(Mutex::new(ModbusMasterConnection::new_tcp(socket)));
-
-        let params = tweakable_modbus::ModbusMasterConnectionParams {
-            max_response_time: self.config.config.max_response_time,
-            max_simultaneous_transactions: self.config.config.max_simultaneous_connections,
-        };
+        let params = self.params;
 
-        let span = info_span!("Modbus connection", ip = %self.config.ip.to_string(), port = %self.config.port.to_string());
+        let span = info_span!("Modbus connection", address = %self.config.get_name());
 
         for (interval, queries) in queries_ordered_by_poll_time {
-            let master_connection = master_connection.clone();
+            let master_connection = self.master_connection.clone();
             let bindings = self.value_bindings.clone();
             let tx = self.insert_channel.clone();
+            let health = self.health.clone();
 
             tokio::task::spawn(
                 async move {
@@ -226,6 +407,7 @@ impl ModbusCommContext {
                         master_connection,
                         tx,
                         bindings,
+                        health,
                     )
                     .await;
                 }
@@ -235,6 +417,154 @@ impl ModbusCommContext {
         Ok(())
     }
 
+    async fn read_holding_registers(
+        modbus_conn: &mut MasterConnection,
+        params: ModbusMasterConnectionParams,
+        slave_id: u8,
+        starting_address: u16,
+        ammount: u16,
+    ) -> Result<Vec<ModbusDataType>, WriteError> {
+        let request = Request::ReadHoldingRegisters {
+            address: starting_address,
+            ammount,
+        };
+
+        modbus_conn.add_query(slave_id, request);
+
+        let mut results = modbus_conn
+            .query_with_params(params)
+            .await
+            .map_err(|err| WriteError::Connection(format!("{:#}", err)))?;
+
+        let mut registers = vec![];
+
+        for offset in 0..ammount {
+            let address = ModbusAddress {
+                slave_id,
+                table: ModbusTable::HoldingRegisters,
+                address: starting_address + offset,
+            };
+
+            match results.remove(&address) {
+                Some(ModbusResult::ReadResult(register)) => registers.push(register),
+                Some(ModbusResult::Error(exception_code)) => {
+                    return Err(WriteError::Exception(exception_code))
+                }
+                _ => {
+                    return Err(WriteError::Connection(format!(
+                        "Register {} was missing in the response",
+                        address.address
+                    )))
+                }
+            }
+        }
+
+        Ok(registers)
+    }
+
+    pub async fn write_value(target: &WriteTarget, value: Value) -> Result<(), WriteError> {
+        let config = &target.config;
+        let formatting_params = &config.formatting_params;
+
+        if config.table == crate::common::model::ModbusTable::InputRegisters
+            || config.table == crate::common::model::ModbusTable::DiscreteInput
+        {
+            return Err(WriteError::NotWritable(format!(
+                "Values in the {:?} table are read only",
+                config.table
+            )));
+        }
+
+        let registers = value_processing::value_to_registers(value.clone(), formatting_params)
+            .map_err(|err| WriteError::InvalidValue(err.to_string()))?;
+
+        //Both the read and the write must happen while we hold the connection, so the poll loops can't interleave
+        let mut modbus_conn = target.master_connection.lock().await;
+
+        if config.table == crate::common::model::ModbusTable::Coils {
+            let coil = match registers.first() {
+                Some(ModbusDataType::Coil(coil)) => *coil,
+                _ => {
+                    return Err(WriteError::InvalidValue(
+                        "Coils can only be written with boolean values".to_string(),
+                    ))
+                }
+            };
+
+            let request = Request::WriteSingleCoil {
+                address: config.starting_address,
+                value: coil,
+            };
+
+            modbus_conn.add_query(target.slave_id, request);
+        } else {
+            let ending_bit = formatting_params.starting_bit as u16 + formatting_params.bit_length;
+            let register_ammount = ending_bit.div_ceil(16);
+
+            let registers = if formatting_params.starting_bit != 0 || ending_bit % 16 != 0 {
+                //The value doesn't fill its registers, we must keep the neighbouring bits as they are
+                let current_registers = Self::read_holding_registers(
+                    &mut modbus_conn,
+                    target.params,
+                    target.slave_id,
+                    config.starting_address,
+                    register_ammount,
+                )
+                .await?;
+
+                value_processing::merge_value_into_registers(
+                    current_registers,
+                    value.clone(),
+                    formatting_params,
+                )
+                .map_err(|err| WriteError::InvalidValue(err.to_string()))?
+            } else {
+                registers
+            };
+
+            let mut register_values = vec![];
+
+            for register in registers {
+                if let ModbusDataType::Register(register) = register {
+                    register_values.push(register);
+                } else {
+                    return Err(WriteError::InvalidValue(
+                        "Registers can't be written with boolean values".to_string(),
+                    ));
+                }
+            }
+
+            let request = if register_values.len() == 1 {
+                Request::WriteSingleRegister {
+                    address: config.starting_address,
+                    value: register_values[0],
+                }
+            } else {
+                Request::WriteMultipleRegisters {
+                    address: config.starting_address,
+                    values: register_values,
+                }
+            };
+
+            modbus_conn.add_query(target.slave_id, request);
+        }
+
+        let results = modbus_conn
+            .query_with_params(target.params)
+            .await
+            .map_err(|err| WriteError::Connection(format!("{:#}", err)))?;
+
+        for result in results.into_values() {
+            if let ModbusResult::Error(exception_code) = result {
+                return Err(WriteError::Exception(exception_code));
+            }
+        }
+
+        info!("Value {} was written with {:?}", config.id, value);
+
+        Ok(())
+    }
+
     fn build_value_bindings(
         config: &PolledConnection,
     ) -> HashMap<ModbusAddress, Vec<ValueBinding>> {
@@ -0,0 +1,365 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+use tokio_serial::SerialStream;
+use tracing::warn;
+use tweakable_modbus::{ModbusAddress, ModbusDataType, ModbusResult};
+
+use crate::client::comm::connection::ConnectFailure;
+use crate::client::model::PolledTlsConfig;
+use crate::common::model::Transport;
+use crate::common::protocol::pdu::{self, Request, Response};
+use crate::common::protocol::{mbap, rtu, tls};
+
+enum Link {
+    Serial(SerialStream),
+    RtuOverTcp(TcpStream),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+//Master for every transport. We open the links ourselves, so failing to connect keeps its io::Error
+pub struct FramedMasterConnection {
+    transport: Transport,
+    address: SocketAddr,
+    tls: Option<PolledTlsConfig>,
+    link: Option<Link>,
+    transaction_id: u16,
+    queries: Vec<(u8, Request)>,
+}
+
+impl FramedMasterConnection {
+    pub fn new(transport: Transport, address: SocketAddr, tls: Option<PolledTlsConfig>) -> Self {
+        FramedMasterConnection {
+            transport,
+            address,
+            tls,
+            link: None,
+            transaction_id: 0,
+            queries: vec![],
+        }
+    }
+
+    pub fn add_query(&mut self, slave_id: u8, request: Request) {
+        self.queries.push((slave_id, request));
+    }
+
+    //Queries are sent one after the other, serial lines are half duplex and gateways rarely pipeline
+    pub async fn query(
+        &mut self,
+        max_response_time: Duration,
+    ) -> Result<HashMap<ModbusAddress, ModbusResult>> {
+        let queries = std::mem::take(&mut self.queries);
+
+        let mut results = HashMap::new();
+        let mut last_error = None;
+
+        for (slave_id, request) in queries {
+            match self.transaction(slave_id, &request, max_response_time).await {
+                Ok(response) => Self::add_results(&mut results, slave_id, &request, response)?,
+                Err(err) => {
+                    warn!("Query to slave {} failed: {}", slave_id, err);
+                    //Whatever is left in the link belongs to the failed transaction
+                    self.link = None;
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(err) if results.is_empty() => Err(err),
+            _ => Ok(results),
+        }
+    }
+
+    async fn connect(&self) -> Result<Link> {
+        let link = match &self.transport {
+            Transport::Rtu(serial_config) => Link::Serial(rtu::open_serial_port(serial_config)?),
+            Transport::RtuOverTcp => Link::RtuOverTcp(TcpStream::connect(self.address).await?),
+            Transport::Udp => {
+                let local_ip = match self.address.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                };
+
+                let socket = UdpSocket::bind(SocketAddr::new(local_ip, 0)).await?;
+                socket.connect(self.address).await?;
+
+                Link::Udp(socket)
+            }
+            Transport::Tcp => match &self.tls {
+                Some(tls_config) => Link::Tls(Box::new(self.connect_tls(tls_config).await?)),
+                None => Link::Tcp(TcpStream::connect(self.address).await?),
+            },
+        };
+
+        Ok(link)
+    }
+
+    async fn connect_tls(&self, tls_config: &PolledTlsConfig) -> Result<TlsStream<TcpStream>> {
+        let client_config = tls::build_client_config(
+            &tls_config.certificate,
+            &tls_config.private_key,
+            &tls_config.ca_certificate,
+        )?;
+
+        let server_name = match &tls_config.server_name {
+            Some(server_name) => ServerName::try_from(server_name.clone())?,
+            None => ServerName::IpAddress(self.address.ip().into()),
+        };
+
+        let stream = TcpStream::connect(self.address).await?;
+
+        let stream = TlsConnector::from(Arc::new(client_config))
+            .connect(server_name, stream)
+            .await?;
+
+        Ok(stream)
+    }
+
+    async fn transaction(
+        &mut self,
+        slave_id: u8,
+        request: &Request,
+        max_response_time: Duration,
+    ) -> Result<Response> {
+        if self.link.is_none() {
+            //Unreachable hosts may not refuse the connection for minutes
+            let link = tokio::time::timeout(max_response_time, self.connect())
+                .await
+                .map_err(anyhow::Error::new)
+                .and_then(|link| link)
+                .context(ConnectFailure)?;
+
+            self.link = Some(link);
+        }
+
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let transaction_id = self.transaction_id;
+
+        let silent_interval = match &self.transport {
+            Transport::Rtu(serial_config) => serial_config.silent_interval(),
+            _ => Duration::ZERO,
+        };
+
+        let link = self.link.as_mut().unwrap();
+
+        let exchange = Self::exchange(link, slave_id, transaction_id, request, silent_interval);
+
+        let response = tokio::time::timeout(max_response_time, exchange)
+            .await
+            .with_context(|| {
+                format!(
+                    "Slave {} didn't answer in {:?}",
+                    slave_id, max_response_time
+                )
+            })??;
+
+        Response::decode(request, &response)
+    }
+
+    async fn exchange(
+        link: &mut Link,
+        slave_id: u8,
+        transaction_id: u16,
+        request: &Request,
+        silent_interval: Duration,
+    ) -> Result<Vec<u8>> {
+        match link {
+            Link::Serial(port) => {
+                port.write_all(&rtu::encode_frame(slave_id, &request.encode()))
+                    .await?;
+                let frame = rtu::read_frame(port, silent_interval).await?;
+                Self::check_rtu_response(slave_id, &frame)
+            }
+            Link::RtuOverTcp(stream) => {
+                stream
+                    .write_all(&rtu::encode_frame(slave_id, &request.encode()))
+                    .await?;
+                let frame = rtu::read_stream_frame(stream, false).await?;
+                Self::check_rtu_response(slave_id, &frame)
+            }
+            Link::Tcp(stream) => {
+                Self::exchange_mbap(stream, slave_id, transaction_id, request).await
+            }
+            Link::Tls(stream) => {
+                Self::exchange_mbap(stream, slave_id, transaction_id, request).await
+            }
+            Link::Udp(socket) => {
+                socket
+                    .send(&mbap::encode_frame(transaction_id, slave_id, &request.encode()))
+                    .await?;
+
+                let mut buffer = [0u8; mbap::MAX_FRAME_SIZE];
+
+                //Late answers to previous transactions or stray datagrams may still be around, they are
+                //skipped until the transaction times out
+                loop {
+                    let read = socket.recv(&mut buffer).await?;
+
+                    let frame = match mbap::decode_frame(&buffer[..read]) {
+                        Ok(frame) => frame,
+                        Err(err) => {
+                            warn!("Discarding UDP datagram from slave {}: {}", slave_id, err);
+                            continue;
+                        }
+                    };
+
+                    if frame.transaction_id == transaction_id && frame.unit_id == slave_id {
+                        return Ok(frame.pdu);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn exchange_mbap<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        slave_id: u8,
+        transaction_id: u16,
+        request: &Request,
+    ) -> Result<Vec<u8>> {
+        stream
+            .write_all(&mbap::encode_frame(
+                transaction_id,
+                slave_id,
+                &request.encode(),
+            ))
+            .await?;
+        let frame = mbap::decode_frame(&mbap::read_stream_frame(stream).await?)?;
+
+        if frame.transaction_id != transaction_id || frame.unit_id != slave_id {
+            return Err(anyhow!(
+                "Answer doesn't match transaction {} to slave {}",
+                transaction_id,
+                slave_id
+            ));
+        }
+
+        Ok(frame.pdu)
+    }
+
+    fn check_rtu_response(slave_id: u8, frame: &[u8]) -> Result<Vec<u8>> {
+        let (response_slave_id, response) = rtu::decode_frame(frame)?;
+
+        if response_slave_id != slave_id {
+            return Err(anyhow!(
+                "Expected answer from slave {} but slave {} answered",
+                slave_id,
+                response_slave_id
+            ));
+        }
+
+        Ok(response)
+    }
+
+    fn add_results(
+        results: &mut HashMap<ModbusAddress, ModbusResult>,
+        slave_id: u8,
+        request: &Request,
+        response: Response,
+    ) -> Result<()> {
+        let address = |offset: usize| ModbusAddress {
+            slave_id,
+            table: request.table(),
+            address: request.address() + offset as u16,
+        };
+
+        match response {
+            Response::Coils(coils) => {
+                for (offset, coil) in coils.into_iter().enumerate() {
+                    results.insert(
+                        address(offset),
+                        ModbusResult::ReadResult(ModbusDataType::Coil(coil)),
+                    );
+                }
+            }
+            Response::Registers(registers) => {
+                for (offset, register) in registers.into_iter().enumerate() {
+                    results.insert(
+                        address(offset),
+                        ModbusResult::ReadResult(ModbusDataType::Register(register)),
+                    );
+                }
+            }
+            Response::Exception(exception_code) => {
+                for offset in 0..request.ammount() as usize {
+                    results.insert(
+                        address(offset),
+                        ModbusResult::Error(pdu::exception_code_from_u8(exception_code)?),
+                    );
+                }
+            }
+            Response::Written => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn malformed_udp_datagrams_are_skipped() {
+        let slave = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = slave.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; mbap::MAX_FRAME_SIZE];
+            let (read, master) = slave.recv_from(&mut buffer).await.unwrap();
+            let request = mbap::decode_frame(&buffer[..read]).unwrap();
+
+            let response =
+                Response::Registers(vec![0x1234]).encode(&Request::decode(&request.pdu).unwrap());
+
+            //Garbage, a late answer to another transaction and then the real answer
+            slave.send_to(&[0xFF, 0x00, 0x01], master).await.unwrap();
+            slave
+                .send_to(
+                    &mbap::encode_frame(request.transaction_id.wrapping_sub(1), 1, &response),
+                    master,
+                )
+                .await
+                .unwrap();
+            slave
+                .send_to(
+                    &mbap::encode_frame(request.transaction_id, 1, &response),
+                    master,
+                )
+                .await
+                .unwrap();
+        });
+
+        let mut connection = FramedMasterConnection::new(Transport::Udp, address, None);
+        connection.add_query(
+            1,
+            Request::ReadHoldingRegisters {
+                address: 10,
+                ammount: 1,
+            },
+        );
+
+        let results = connection.query(Duration::from_secs(2)).await.unwrap();
+
+        let address = ModbusAddress {
+            slave_id: 1,
+            table: tweakable_modbus::ModbusTable::HoldingRegisters,
+            address: 10,
+        };
+
+        assert!(matches!(
+            results.get(&address),
+            Some(ModbusResult::ReadResult(ModbusDataType::Register(0x1234)))
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+//Consecutive failed polls after which a connection or slave is considered down
+const DOWN_THRESHOLD: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum HealthState {
+    Connected,
+    Degraded,
+    Down,
+}
+
+impl HealthState {
+    fn from_failures(consecutive_failures: u32) -> Self {
+        match consecutive_failures {
+            0 => HealthState::Connected,
+            failures if failures < DOWN_THRESHOLD => HealthState::Degraded,
+            _ => HealthState::Down,
+        }
+    }
+}
+
+pub enum SlaveOutcome {
+    Success,
+    Exception(u8),
+    NoResponse,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlaveHealth {
+    pub slave_id: u8,
+    pub state: HealthState,
+    pub consecutive_failures: u32,
+    pub last_success: Option<SystemTime>,
+    pub last_exception_code: Option<u8>,
+}
+
+impl SlaveHealth {
+    fn new(slave_id: u8) -> Self {
+        SlaveHealth {
+            slave_id,
+            state: HealthState::Down,
+            consecutive_failures: 0,
+            last_success: None,
+            last_exception_code: None,
+        }
+    }
+
+    fn record(&mut self, outcome: SlaveOutcome) {
+        match outcome {
+            SlaveOutcome::Success => {
+                self.consecutive_failures = 0;
+                self.state = HealthState::Connected;
+                self.last_success = Some(SystemTime::now());
+            }
+            SlaveOutcome::Exception(exception_code) => {
+                //The slave is answering, it just doesn't like our queries
+                self.consecutive_failures += 1;
+                self.state = HealthState::Degraded;
+                self.last_exception_code = Some(exception_code);
+            }
+            SlaveOutcome::NoResponse => {
+                self.consecutive_failures += 1;
+                self.state = HealthState::from_failures(self.consecutive_failures);
+            }
+        }
+    }
+
+    //Nothing can reach the slave while its connection is down
+    fn record_unreachable(&mut self) {
+        self.consecutive_failures += 1;
+        self.state = HealthState::Down;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionHealth {
+    pub name: String,
+    pub state: HealthState,
+    pub consecutive_failures: u32,
+    pub last_success: Option<SystemTime>,
+    pub last_error: Option<String>,
+    pub next_attempt: Option<SystemTime>,
+    pub slaves: Vec<SlaveHealth>,
+
+    //Only failing to connect grows the backoff, failed queries in between don't
+    #[serde(skip)]
+    connect_failures: u32,
+    #[serde(skip)]
+    min_reconnect_delay: Duration,
+    #[serde(skip)]
+    max_reconnect_delay: Duration,
+}
+
+impl ConnectionHealth {
+    pub fn new(
+        name: String,
+        slave_ids: Vec<u8>,
+        min_reconnect_delay: Duration,
+        max_reconnect_delay: Duration,
+    ) -> Self {
+        ConnectionHealth {
+            name,
+            state: HealthState::Down,
+            consecutive_failures: 0,
+            last_success: None,
+            last_error: None,
+            next_attempt: None,
+            slaves: slave_ids.into_iter().map(SlaveHealth::new).collect(),
+            connect_failures: 0,
+            min_reconnect_delay,
+            max_reconnect_delay,
+        }
+    }
+
+    pub fn is_backing_off(&self) -> bool {
+        self.next_attempt
+            .is_some_and(|next_attempt| SystemTime::now() < next_attempt)
+    }
+
+    //Returns how long we'll wait before trying again, the connection is down until then
+    pub fn record_connection_failure(&mut self, error: String) -> Duration {
+        self.consecutive_failures += 1;
+        self.connect_failures += 1;
+        self.state = HealthState::Down;
+        self.last_error = Some(error);
+
+        let backoff = self
+            .min_reconnect_delay
+            .saturating_mul(2u32.saturating_pow(self.connect_failures - 1))
+            .min(self.max_reconnect_delay);
+
+        self.next_attempt = Some(SystemTime::now() + backoff);
+
+        for slave in &mut self.slaves {
+            slave.record_unreachable();
+        }
+
+        backoff
+    }
+
+    //The link is up but the queries failed, so there's no point in backing off
+    pub fn record_query_failure(&mut self, error: String) {
+        self.consecutive_failures += 1;
+        self.connect_failures = 0;
+        self.state = HealthState::from_failures(self.consecutive_failures);
+        self.last_error = Some(error);
+        self.next_attempt = None;
+
+        for slave in &mut self.slaves {
+            slave.record(SlaveOutcome::NoResponse);
+        }
+    }
+
+    pub fn record_poll(&mut self, outcomes: Vec<(u8, SlaveOutcome)>) {
+        self.consecutive_failures = 0;
+        self.connect_failures = 0;
+        self.state = HealthState::Connected;
+        self.last_success = Some(SystemTime::now());
+        self.next_attempt = None;
+
+        for (slave_id, outcome) in outcomes {
+            if let Some(slave) = self.slaves.iter_mut().find(|slave| slave.slave_id == slave_id) {
+                slave.record(outcome);
+            }
+        }
+    }
+
+    pub fn get_slave(&self, slave_id: u8) -> Option<&SlaveHealth> {
+        self.slaves.iter().find(|slave| slave.slave_id == slave_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_health() -> ConnectionHealth {
+        ConnectionHealth::new(
+            "127.0.0.1:502".to_string(),
+            vec![1, 2],
+            Duration::from_secs(1),
+            Duration::from_secs(8),
+        )
+    }
+
+    fn get_slave_state(health: &ConnectionHealth, slave_id: u8) -> HealthState {
+        health.get_slave(slave_id).unwrap().state
+    }
+
+    #[test]
+    fn failed_queries_degrade_and_then_bring_down_the_connection() {
+        let mut health = build_health();
+        assert_eq!(health.state, HealthState::Down);
+
+        health.record_poll(vec![(1, SlaveOutcome::Success), (2, SlaveOutcome::Success)]);
+        assert_eq!(health.state, HealthState::Connected);
+        assert_eq!(get_slave_state(&health, 1), HealthState::Connected);
+
+        for _ in 1..DOWN_THRESHOLD {
+            health.record_query_failure("Timeout".to_string());
+            assert_eq!(health.state, HealthState::Degraded);
+            assert_eq!(get_slave_state(&health, 1), HealthState::Degraded);
+        }
+
+        health.record_query_failure("Timeout".to_string());
+        assert_eq!(health.state, HealthState::Down);
+        assert_eq!(get_slave_state(&health, 1), HealthState::Down);
+        assert!(!health.is_backing_off());
+
+        health.record_poll(vec![(1, SlaveOutcome::Success), (2, SlaveOutcome::NoResponse)]);
+        assert_eq!(health.state, HealthState::Connected);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(get_slave_state(&health, 1), HealthState::Connected);
+        assert_eq!(get_slave_state(&health, 2), HealthState::Down);
+    }
+
+    #[test]
+    fn connection_failures_bring_everything_down_and_back_off() {
+        let mut health = build_health();
+        health.record_poll(vec![(1, SlaveOutcome::Success), (2, SlaveOutcome::Success)]);
+
+        let backoff = health.record_connection_failure("Connection refused".to_string());
+
+        assert_eq!(backoff, Duration::from_secs(1));
+        assert_eq!(health.state, HealthState::Down);
+        assert_eq!(get_slave_state(&health, 1), HealthState::Down);
+        assert_eq!(get_slave_state(&health, 2), HealthState::Down);
+        assert!(health.is_backing_off());
+
+        assert_eq!(
+            health.record_connection_failure("Connection refused".to_string()),
+            Duration::from_secs(2)
+        );
+
+        health.record_poll(vec![(1, SlaveOutcome::Success), (2, SlaveOutcome::Exception(2))]);
+        assert_eq!(health.state, HealthState::Connected);
+        assert!(!health.is_backing_off());
+        assert_eq!(get_slave_state(&health, 1), HealthState::Connected);
+        assert_eq!(get_slave_state(&health, 2), HealthState::Degraded);
+        assert_eq!(health.get_slave(2).unwrap().last_exception_code, Some(2));
+    }
+
+    #[test]
+    fn timeouts_dont_grow_the_backoff() {
+        let mut health = build_health();
+
+        for _ in 0..5 {
+            health.record_query_failure("Timeout".to_string());
+        }
+
+        assert_eq!(
+            health.record_connection_failure("Connection refused".to_string()),
+            Duration::from_secs(1)
+        );
+        assert_eq!(health.consecutive_failures, 6);
+
+        assert_eq!(
+            health.record_connection_failure("Connection refused".to_string()),
+            Duration::from_secs(2)
+        );
+
+        //The slave was reached again, so the next refusal starts over
+        health.record_query_failure("Timeout".to_string());
+        assert!(!health.is_backing_off());
+
+        assert_eq!(
+            health.record_connection_failure("Connection refused".to_string()),
+            Duration::from_secs(1)
+        );
+        assert_eq!(health.state, HealthState::Down);
+    }
+}
@@ -1,10 +1,19 @@
+use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex;
 
 use crate::client::{comm::context::ModbusCommContext, data::InsertValueMessage, model::PolledConnection};
 
 use anyhow::Result;
 
+mod connection;
 mod context;
+mod framed;
+mod health;
+mod writer;
+
+pub use health::{ConnectionHealth, HealthState, SlaveHealth};
+pub use writer::{ModbusWriter, WriteError};
 
 pub struct ModbusWatcher {
     contexts: Vec<ModbusCommContext>,
@@ -29,4 +38,21 @@ impl ModbusWatcher {
 
         Ok(())
     }
+
+    pub fn get_writer(&self) -> ModbusWriter {
+        let mut targets = vec![];
+
+        for context in &self.contexts {
+            targets.extend(context.get_write_targets());
+        }
+
+        ModbusWriter::new(targets)
+    }
+
+    pub fn get_health(&self) -> Vec<Arc<Mutex<ConnectionHealth>>> {
+        self.contexts
+            .iter()
+            .map(|context| context.get_health())
+            .collect()
+    }
 }
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tweakable_modbus::{ExceptionCode, ModbusMasterConnectionParams};
+
+use crate::client::comm::connection::MasterConnection;
+use crate::client::comm::context::ModbusCommContext;
+use crate::client::model::PolledValue;
+use crate::common::model::Value;
+
+#[derive(Debug)]
+pub enum WriteError {
+    NotFound,
+    NotWritable(String),
+    InvalidValue(String),
+    Exception(ExceptionCode),
+    Connection(String),
+}
+
+pub struct WriteTarget {
+    pub slave_id: u8,
+    pub config: PolledValue,
+    pub master_connection: Arc<Mutex<MasterConnection>>,
+    pub params: ModbusMasterConnectionParams,
+}
+
+//Sends writes through the same connections the poll loops use
+#[derive(Clone)]
+pub struct ModbusWriter {
+    targets: Arc<HashMap<String, WriteTarget>>,
+}
+
+impl ModbusWriter {
+    pub fn new(targets: Vec<WriteTarget>) -> Self {
+        let targets = targets
+            .into_iter()
+            .map(|target| (target.config.id.clone(), target))
+            .collect();
+
+        ModbusWriter {
+            targets: Arc::new(targets),
+        }
+    }
+
+    pub async fn write_value(&self, id: &String, value: Value) -> Result<(), WriteError> {
+        let target = self.targets.get(id).ok_or(WriteError::NotFound)?;
+
+        ModbusCommContext::write_value(target, value).await
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, Transaction};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, info};
+
+use crate::client::data::tables;
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Transaction) -> Result<()>,
+}
+
+//Ordered, a migration must never be changed once released, add a new one instead
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Values, polls and aggregates tables",
+        apply: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        description: "Poll quality and aggregations excluded polls",
+        apply: add_poll_quality,
+    },
+    Migration {
+        version: 3,
+        description: "Poll timestamps in milliseconds",
+        apply: add_poll_millis,
+    },
+    Migration {
+        version: 4,
+        description: "Time in state of enumerated aggregates",
+        apply: add_aggregate_states,
+    },
+];
+
+pub fn get_latest_version() -> u32 {
+    MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0)
+}
+
+pub fn get_current_version(conn: &Connection) -> Result<u32> {
+    conn.execute(tables::SCHEMA_VERSION_TABLE, [])?;
+
+    let version: Option<u32> =
+        conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+
+    Ok(version.unwrap_or(0))
+}
+
+//Returns the version the database was at before migrating
+pub fn migrate(conn: &mut Connection) -> Result<u32> {
+    let current_version = get_current_version(conn)?;
+    let latest_version = get_latest_version();
+
+    if current_version > latest_version {
+        return Err(anyhow!(
+            "Database schema version {} is newer than the latest known version {}, refusing to start",
+            current_version,
+            latest_version
+        ));
+    }
+
+    for migration in MIGRATIONS {
+        if migration.version <= current_version {
+            continue;
+        }
+
+        info!(
+            "Migrating database to version {}: {}",
+            migration.version, migration.description
+        );
+
+        let transaction = conn.transaction()?;
+
+        (migration.apply)(&transaction).map_err(|err| {
+            anyhow!("Migration to version {} failed: {}", migration.version, err)
+        })?;
+
+        let applied_at = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        transaction.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)",
+            params![migration.version, migration.description, applied_at],
+        )?;
+
+        transaction.commit()?;
+    }
+
+    Ok(current_version)
+}
+
+fn create_base_tables(conn: &Transaction) -> Result<()> {
+    conn.execute(tables::VALUE_TABLE, [])?;
+    debug!("Built value table");
+
+    conn.execute(tables::POLL_TABLE, [])?;
+    debug!("Built poll table");
+
+    conn.execute(tables::AGGREGATES_TABLE, [])?;
+    debug!("Built aggregates table");
+
+    Ok(())
+}
+
+fn add_poll_quality(conn: &Transaction) -> Result<()> {
+    add_column_if_missing(conn, "modbus_polls", "quality", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "modbus_polls", "exception_code", "INTEGER")?;
+    add_column_if_missing(conn, "modbus_aggregates", "excluded", "INTEGER NOT NULL DEFAULT 0")?;
+
+    Ok(())
+}
+
+fn add_poll_millis(conn: &Transaction) -> Result<()> {
+    add_column_if_missing(conn, "modbus_polls", "timestamp_ms", "INTEGER")?;
+
+    //Polls stored before millisecond timestamps only have the second
+    let migrated = conn.execute(
+        "UPDATE modbus_polls SET timestamp_ms = timestamp * 1000 WHERE timestamp_ms IS NULL",
+        [],
+    )?;
+    debug!("Migrated {} polls to millisecond timestamps", migrated);
+
+    conn.execute(tables::POLL_TIMESTAMP_INDEX, [])?;
+
+    Ok(())
+}
+
+fn add_aggregate_states(conn: &Transaction) -> Result<()> {
+    //JSON list, only for values with states
+    add_column_if_missing(conn, "modbus_aggregates", "states", "TEXT")?;
+
+    Ok(())
+}
+
+//Databases built before versioning may already have some of the columns
+fn add_column_if_missing(
+    conn: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    drop(stmt);
+
+    if !columns.iter().any(|existing| existing == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+        debug!("Added column {} to {}", column, table);
+    }
+
+    Ok(())
+}
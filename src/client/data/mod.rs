@@ -1,37 +1,111 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::Mutex;
 use tracing::debug;
 use tracing::error;
 use tracing::instrument;
+use tracing::warn;
 use serde::{Serialize, Deserialize};
 
 use crate::client::model::PolledConnection;
-use crate::common::model::Value;
+use crate::common::model::{Value, ValueFormattingParams};
+use crate::common::value_processing;
 
+mod migrations;
 pub mod read;
 pub mod write;
 mod tables;
 
+//Polls a slow live subscriber can fall behind before losing some
+const STREAM_CAPACITY: usize = 4096;
+
 pub struct InsertValueMessage {
     pub name: String,
     pub timestamp: std::time::SystemTime,
     pub value: Vec<u8>,
+    pub quality: PollQuality,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PollQuality {
+    Good,
+    Exception(u8),
+    Timeout,
+    ConnectionError,
+}
+
+impl PollQuality {
+    pub fn to_repr(&self) -> (u8, Option<u8>) {
+        match self {
+            PollQuality::Good => (0, None),
+            PollQuality::Exception(exception_code) => (1, Some(*exception_code)),
+            PollQuality::Timeout => (2, None),
+            PollQuality::ConnectionError => (3, None),
+        }
+    }
+
+    pub fn from_repr(raw_value: u8, exception_code: Option<u8>) -> Result<Self> {
+        match raw_value {
+            0 => Ok(PollQuality::Good),
+            1 => Ok(PollQuality::Exception(exception_code.unwrap_or_default())),
+            2 => Ok(PollQuality::Timeout),
+            3 => Ok(PollQuality::ConnectionError),
+            _ => Err(anyhow!("Value not supported for poll quality!")),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ModbusPoll {
     pub value_id: String,
-    pub value: Value,
-    pub secs_since_epoch: u64
+    //Only present when the quality is good
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    //Label of enumerated values
+    #[serde(default)]
+    pub state: Option<String>,
+    pub quality: PollQuality,
+    pub secs_since_epoch: u64,
+    pub millis_since_epoch: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DbBatchParams {
+    pub max_batch_size: usize,
+    pub max_batch_delay: std::time::Duration,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DbMetrics {
+    pub batches_written: u64,
+    pub polls_written: u64,
+    pub polls_lost: u64,
+    pub last_batch_size: usize,
+    #[serde(with = "humantime_serde")]
+    pub last_insert_latency: std::time::Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_insert_latency: std::time::Duration,
+    //Polls waiting in the channel when the last batch was written
+    pub backlog: usize,
+    pub max_backlog: usize,
+    pub channel_capacity: usize,
 }
 
 pub struct DbManager {
     path: std::path::PathBuf,
     db: Arc<Pool<SqliteConnectionManager>>,
     insert_channel: Receiver<InsertValueMessage>,
+    batch_params: DbBatchParams,
+    metrics: Arc<Mutex<DbMetrics>>,
+    formatting_params: HashMap<String, ValueFormattingParams>,
+    stream: broadcast::Sender<ModbusPoll>,
 }
 
 impl DbManager {
@@ -39,12 +113,35 @@ impl DbManager {
         path: std::path::PathBuf,
         config: &Vec<PolledConnection>,
         insert_channel: Receiver<InsertValueMessage>,
+        batch_params: DbBatchParams,
     ) -> Result<Self> {
         let db = Arc::new(Self::build_db(path.clone())?);
+
+        let metrics = Arc::new(Mutex::new(DbMetrics {
+            channel_capacity: insert_channel.max_capacity(),
+            ..Default::default()
+        }));
+
+        let mut formatting_params = HashMap::new();
+
+        for connection in config {
+            for slave in &connection.slaves {
+                for value in &slave.values {
+                    formatting_params.insert(value.id.clone(), value.formatting_params.clone());
+                }
+            }
+        }
+
+        let (stream, _) = broadcast::channel(STREAM_CAPACITY);
+
         let db_manager = DbManager {
             db,
             insert_channel,
             path,
+            batch_params,
+            metrics,
+            formatting_params,
+            stream,
         };
 
         db_manager.init_db(config)?;
@@ -56,48 +153,170 @@ impl DbManager {
         self.db.clone()
     }
 
+    pub fn get_metrics(&self) -> Arc<Mutex<DbMetrics>> {
+        self.metrics.clone()
+    }
+
+    pub fn get_stream(&self) -> broadcast::Sender<ModbusPoll> {
+        self.stream.clone()
+    }
+
+    //Live subscribers get the polls before they are written to the db
+    fn publish_polls(&self, batch: &Vec<InsertValueMessage>) {
+        if self.stream.receiver_count() == 0 {
+            return;
+        }
+
+        for insert in batch {
+            let Some(formatting_params) = self.formatting_params.get(&insert.name) else {
+                continue;
+            };
+
+            let value = if insert.quality == PollQuality::Good {
+                match value_processing::bytes_to_value(insert.value.clone(), formatting_params) {
+                    Ok(value) => Some(value),
+                    Err(err) => {
+                        warn!("Couldn't decode poll for value {}: {}", insert.name, err);
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let since_epoch = insert
+                .timestamp
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+
+            let poll = ModbusPoll {
+                value_id: insert.name.clone(),
+                state: value
+                    .as_ref()
+                    .and_then(|value| formatting_params.get_state_label(value)),
+                value,
+                unit: formatting_params.unit.clone(),
+                quality: insert.quality.clone(),
+                secs_since_epoch: since_epoch.as_secs(),
+                millis_since_epoch: since_epoch.as_millis() as u64,
+            };
+
+            //Only fails when every subscriber is gone
+            let _ = self.stream.send(poll);
+        }
+    }
+
     pub async fn listen(&mut self) {
         debug!("DB {} started listening", self.path.to_string_lossy());
         loop {
-            let insert = self.insert_channel.recv().await.unwrap();
-            let conn = self.db.get().unwrap();
-            let result = write::insert_modbus_poll(
-                &conn,
-                insert.name.clone(),
-                insert.value.clone(),
-                insert.timestamp,
-            );
+            let batch = match self.receive_batch().await {
+                Some(batch) => batch,
+                None => {
+                    debug!("Insert channel closed, DB stopped listening");
+                    return;
+                }
+            };
+
+            self.publish_polls(&batch);
+
+            let start = std::time::Instant::now();
+
+            let result = match self.db.get() {
+                Ok(mut conn) => write::insert_modbus_polls(&mut conn, &batch),
+                Err(err) => Err(err.into()),
+            };
+
+            let latency = start.elapsed();
+            let backlog = self.insert_channel.len();
+
+            let mut metrics = self.metrics.lock().await;
+
             if let Err(err) = result {
-                error!("error inserting poll into db: {}", err.to_string());
+                error!(
+                    "error inserting batch of {} polls into db: {}",
+                    batch.len(),
+                    err.to_string()
+                );
+                metrics.polls_lost += batch.len() as u64;
+            } else {
+                metrics.batches_written += 1;
+                metrics.polls_written += batch.len() as u64;
             }
 
+            metrics.last_batch_size = batch.len();
+            metrics.last_insert_latency = latency;
+            metrics.max_insert_latency = metrics.max_insert_latency.max(latency);
+            metrics.backlog = backlog;
+            metrics.max_backlog = metrics.max_backlog.max(backlog);
+
             debug!(
-                "Inserted poll {:?} for value {} into db",
-                insert.value, insert.name
+                "Inserted {} polls into db in {:?}, {} waiting",
+                batch.len(),
+                latency,
+                backlog
             );
+
+            if backlog > metrics.channel_capacity / 2 {
+                warn!(
+                    "Poll backlog at {} of {}, the db isn't keeping up",
+                    backlog, metrics.channel_capacity
+                );
+            }
         }
     }
 
+    //Waits for a poll, then keeps collecting until the batch is full or the delay expires
+    async fn receive_batch(&mut self) -> Option<Vec<InsertValueMessage>> {
+        let first = self.insert_channel.recv().await?;
+
+        let deadline = tokio::time::Instant::now() + self.batch_params.max_batch_delay;
+
+        let mut batch = vec![first];
+
+        while batch.len() < self.batch_params.max_batch_size {
+            match tokio::time::timeout_at(deadline, self.insert_channel.recv()).await {
+                Ok(Some(insert)) => batch.push(insert),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        Some(batch)
+    }
+
     #[instrument]
     fn build_db(path: std::path::PathBuf) -> Result<Pool<SqliteConnectionManager>> {
-        let db = SqliteConnectionManager::file(path);
+        //The poll writer, the aggregations and the api share the file
+        let db = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.execute_batch("PRAGMA synchronous = NORMAL; PRAGMA busy_timeout = 5000;")
+        });
 
         let db_pool = Pool::new(db)?;
 
-        let conn = db_pool.get()?;
+        let mut conn = db_pool.get()?;
 
-        conn.execute(tables::VALUE_TABLE, [])?;
-        debug!("Built value table");
+        let journal_mode: String =
+            conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+        debug!("Journal mode set to {}", journal_mode);
 
-        conn.execute(tables::POLL_TABLE, [])?;
-        debug!("Built poll table");
-
-        conn.execute(tables::AGGREGATES_TABLE, [])?;
-        debug!("Built aggregates table");
+        let previous_version = migrations::migrate(&mut conn)?;
+        debug!(
+            "Database at schema version {}, was {}",
+            migrations::get_latest_version(),
+            previous_version
+        );
 
         Ok(db_pool)
     }
 
+    //Brings the database to the latest schema without starting anything else
+    pub fn migrate_only(path: std::path::PathBuf) -> Result<(u32, u32)> {
+        let mut conn = rusqlite::Connection::open(path)?;
+
+        let previous_version = migrations::migrate(&mut conn)?;
+
+        Ok((previous_version, migrations::get_latest_version()))
+    }
+
     fn init_db(&self, config: &Vec<PolledConnection>) -> Result<()> {
         let conn = self.db.get()?;
         for connection_config in config {
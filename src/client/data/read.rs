@@ -1,8 +1,8 @@
 use std::time::UNIX_EPOCH;
 
 use crate::client::aggregations::{Aggregation, AggregationInfo, Period};
-use crate::client::data::ModbusPoll;
-use crate::common::model::DataType;
+use crate::client::data::{ModbusPoll, PollQuality};
+use crate::common::model::{Value, ValueFormattingParams};
 use crate::common::value_processing;
 
 use anyhow::Result;
@@ -12,42 +12,71 @@ use rusqlite::params;
 pub fn get_last_poll(
     conn: &r2d2::PooledConnection<SqliteConnectionManager>,
     value_id: String,
-    data_type: DataType,
+    formatting_params: &ValueFormattingParams,
 ) -> Result<ModbusPoll> {
-    let (secs_since_epoch, value_bytes): (u64, Vec<u8>) = conn.query_row(
-        "SELECT timestamp, value
+    let (millis_since_epoch, value_bytes, quality, exception_code): (
+        u64,
+        Option<Vec<u8>>,
+        u8,
+        Option<u8>,
+    ) = conn.query_row(
+        "SELECT timestamp_ms, value, quality, exception_code
      FROM modbus_polls
      WHERE value_id = ?
-     ORDER BY timestamp DESC
+     ORDER BY timestamp_ms DESC, id DESC
      LIMIT 1;",
         [value_id.clone()],
-        |row| Ok((row.get(0)?, row.get(1)?)),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
     )?;
 
-    let value = value_processing::format_value(value_bytes, &data_type)?;
+    let quality = PollQuality::from_repr(quality, exception_code)?;
+    let value = format_poll_value(value_bytes, &quality, formatting_params)?;
 
     Ok(ModbusPoll {
         value_id,
+        state: value
+            .as_ref()
+            .and_then(|value| formatting_params.get_state_label(value)),
         value,
-        secs_since_epoch,
+        unit: formatting_params.unit.clone(),
+        quality,
+        secs_since_epoch: millis_since_epoch / 1000,
+        millis_since_epoch,
     })
 }
 
+fn format_poll_value(
+    value_bytes: Option<Vec<u8>>,
+    quality: &PollQuality,
+    formatting_params: &ValueFormattingParams,
+) -> Result<Option<Value>> {
+    match value_bytes {
+        Some(value_bytes) if *quality == PollQuality::Good => Ok(Some(
+            value_processing::bytes_to_value(value_bytes, formatting_params)?,
+        )),
+        _ => Ok(None),
+    }
+}
+
 pub fn get_polls_between(
     conn: &r2d2::PooledConnection<SqliteConnectionManager>,
     value_id: &String,
-    data_type: &DataType,
+    formatting_params: &ValueFormattingParams,
     start_time: std::time::SystemTime,
     finish_time: std::time::SystemTime,
 ) -> Result<Vec<ModbusPoll>> {
-    let start_time = start_time.duration_since(UNIX_EPOCH)?.as_secs();
-    let finish_time = finish_time.duration_since(UNIX_EPOCH)?.as_secs();
+    let start_time = start_time.duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let finish_time = finish_time
+        .duration_since(UNIX_EPOCH)?
+        .as_millis()
+        .min(i64::MAX as u128) as u64;
 
     let mut stmt = conn.prepare(
-        "SELECT value, timestamp
+        "SELECT value, timestamp_ms, quality, exception_code
          FROM modbus_polls
          WHERE value_id = ?
-           AND timestamp BETWEEN ? AND ?",
+           AND timestamp_ms BETWEEN ? AND ?
+         ORDER BY timestamp_ms, id",
     )?;
 
     let mut rows = stmt.query(params![value_id.clone(), start_time, finish_time])?;
@@ -55,15 +84,24 @@ pub fn get_polls_between(
     let mut result = vec![];
 
     while let Some(row) = rows.next()? {
-        let value: Vec<u8> = row.get(0)?;
+        let value: Option<Vec<u8>> = row.get(0)?;
         let timestamp: u64 = row.get(1)?;
+        let quality: u8 = row.get(2)?;
+        let exception_code: Option<u8> = row.get(3)?;
 
-        let value = value_processing::format_value(value, data_type)?;
+        let quality = PollQuality::from_repr(quality, exception_code)?;
+        let value = format_poll_value(value, &quality, formatting_params)?;
 
         let poll = ModbusPoll {
             value_id: value_id.clone(),
+            state: value
+                .as_ref()
+                .and_then(|value| formatting_params.get_state_label(value)),
             value,
-            secs_since_epoch: timestamp
+            unit: formatting_params.unit.clone(),
+            quality,
+            secs_since_epoch: timestamp / 1000,
+            millis_since_epoch: timestamp
         };
 
         result.push(poll);
@@ -75,7 +113,7 @@ pub fn get_polls_between(
 pub fn get_aggregates_between(
     conn: &r2d2::PooledConnection<SqliteConnectionManager>,
     value_id: &String,
-    data_type: &DataType,
+    formatting_params: &ValueFormattingParams,
     start_time: std::time::SystemTime,
     finish_time: std::time::SystemTime,
     max_period: Period,
@@ -88,7 +126,7 @@ pub fn get_aggregates_between(
     let max_period = max_period as u8;
 
     let mut stmt = conn.prepare(
-        "SELECT value_id, period, start, finish, average, median, moda, min, max, ammount
+        "SELECT value_id, period, start, finish, average, median, moda, min, max, ammount, excluded, states
          FROM modbus_aggregates
          WHERE start >= ?1
            AND finish <= ?2
@@ -105,25 +143,36 @@ pub fn get_aggregates_between(
         let period: u8 = row.get(1)?;
         let start_time: u64 = row.get(2)?;
         let finish_time: u64 = row.get(3)?;
-        let average: Vec<u8> = row.get(4)?; // BLOB
-        let median: Vec<u8> = row.get(5)?; // BLOB
-        let moda: Vec<u8> = row.get(6)?; // BLOB
-        let min: Vec<u8> = row.get(7)?; // BLOB
-        let max: Vec<u8> = row.get(8)?; // BLOB
+        let average: Option<Vec<u8>> = row.get(4)?; // BLOB
+        let median: Option<Vec<u8>> = row.get(5)?; // BLOB
+        let moda: Option<Vec<u8>> = row.get(6)?; // BLOB
+        let min: Option<Vec<u8>> = row.get(7)?; // BLOB
+        let max: Option<Vec<u8>> = row.get(8)?; // BLOB
         let ammount: u64 = row.get(9)?;
+        let excluded: u64 = row.get(10)?;
+        let states: Option<String> = row.get(11)?;
 
         let start_time = UNIX_EPOCH + std::time::Duration::from_secs(start_time);
         let finish_time = UNIX_EPOCH + std::time::Duration::from_secs(finish_time);
 
         let period = Period::from_repr(period)?;
-        let average = value_processing::format_value(average, data_type)?;
-        let median = value_processing::format_value(median, data_type)?;
-        let moda = value_processing::format_value(moda, data_type)?;
-        let min = value_processing::format_value(min, data_type)?;
-        let max = value_processing::format_value(max, data_type)?;
+        let format = |bytes: Option<Vec<u8>>| {
+            bytes
+                .map(|bytes| value_processing::format_aggregate_value(bytes, formatting_params))
+                .transpose()
+        };
+
+        let average = format(average)?;
+        let median = format(median)?;
+        let moda = format(moda)?;
+        let min = format(min)?;
+        let max = format(max)?;
+        let states = states
+            .map(|states| serde_json::from_str(&states))
+            .transpose()?;
 
         let aggregation = Aggregation {
-            average, median, moda, min, max, ammount
+            average, median, moda, min, max, ammount, excluded, states
         };
 
         let aggregation = AggregationInfo {
@@ -26,3 +26,12 @@ pub const AGGREGATES_TABLE: &str = "CREATE TABLE IF NOT EXISTS modbus_aggregates
                                     max blob,
                                     ammount INTEGER 
                                 );";
+
+pub const SCHEMA_VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+                                        version INTEGER PRIMARY KEY,
+                                        description TEXT NOT NULL,
+                                        applied_at INTEGER NOT NULL
+                                    );";
+
+pub const POLL_TIMESTAMP_INDEX: &str = "CREATE INDEX IF NOT EXISTS modbus_polls_value_timestamp
+                                        ON modbus_polls (value_id, timestamp_ms);";
@@ -1,5 +1,6 @@
 use crate::client::{
     aggregations::{AggregationInfo, Period},
+    data::{InsertValueMessage, PollQuality},
     model::PolledValue,
 };
 
@@ -34,17 +35,46 @@ pub fn insert_modbus_value(
     Ok(())
 }
 
-pub fn insert_modbus_poll(
-    conn: &r2d2::PooledConnection<SqliteConnectionManager>,
-    name: String,
-    value: Vec<u8>,
-    timestamp: std::time::SystemTime,
+//All the polls are written in a single transaction
+pub fn insert_modbus_polls(
+    conn: &mut r2d2::PooledConnection<SqliteConnectionManager>,
+    polls: &[InsertValueMessage],
 ) -> Result<()> {
-    let query = "INSERT INTO modbus_polls (value_id, timestamp, value) VALUES (?, ?, ?)";
-
-    let secs_since_epoch = timestamp.duration_since(UNIX_EPOCH)?.as_secs();
-
-    let _rows = conn.execute(&query, params![name, secs_since_epoch, value])?;
+    let transaction = conn.transaction()?;
+
+    {
+        let mut stmt = transaction.prepare_cached(
+            "INSERT INTO modbus_polls
+        (value_id, timestamp, timestamp_ms, value, quality, exception_code)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        )?;
+
+        for poll in polls {
+            //The seconds column is kept for tools reading the db directly
+            let since_epoch = poll.timestamp.duration_since(UNIX_EPOCH)?;
+            let secs_since_epoch = since_epoch.as_secs();
+            let millis_since_epoch = since_epoch.as_millis() as u64;
+
+            let value = if poll.quality == PollQuality::Good {
+                Some(&poll.value)
+            } else {
+                None
+            };
+
+            let (quality, exception_code) = poll.quality.to_repr();
+
+            stmt.execute(params![
+                poll.name,
+                secs_since_epoch,
+                millis_since_epoch,
+                value,
+                quality,
+                exception_code
+            ])?;
+        }
+    }
+
+    transaction.commit()?;
 
     Ok(())
 }
@@ -64,16 +94,23 @@ pub fn insert_modbus_aggregate(
         .duration_since(UNIX_EPOCH)?
         .as_secs();
 
-    let average = value_processing::value_to_bytes(aggregate_info.aggregation.average);
-    let median = value_processing::value_to_bytes(aggregate_info.aggregation.median);
-    let moda = value_processing::value_to_bytes(aggregate_info.aggregation.moda);
+    let average = aggregate_info.aggregation.average.map(value_processing::value_to_bytes);
+    let median = aggregate_info.aggregation.median.map(value_processing::value_to_bytes);
+    let moda = aggregate_info.aggregation.moda.map(value_processing::value_to_bytes);
+
+    let min = aggregate_info.aggregation.min.map(value_processing::value_to_bytes);
+    let max = aggregate_info.aggregation.max.map(value_processing::value_to_bytes);
 
-    let min = value_processing::value_to_bytes(aggregate_info.aggregation.min);
-    let max = value_processing::value_to_bytes(aggregate_info.aggregation.max);
+    let states = aggregate_info
+        .aggregation
+        .states
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()?;
 
     let query = "INSERT INTO modbus_aggregates 
-    (value_id, period, start, finish, average, median, min, max, moda, ammount)
-    VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    (value_id, period, start, finish, average, median, min, max, moda, ammount, excluded, states)
+    VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
 
     let _rows = conn.execute(
         &query,
@@ -87,7 +124,9 @@ pub fn insert_modbus_aggregate(
             min,
             max,
             moda,
-            aggregate_info.aggregation.ammount
+            aggregate_info.aggregation.ammount,
+            aggregate_info.aggregation.excluded,
+            states
         ],
     )?;
 
@@ -105,7 +144,7 @@ pub fn delete_exceeding_polls(
     WHERE id IN (
         SELECT id FROM modbus_polls
         WHERE value_id = ?
-        ORDER BY timestamp DESC
+        ORDER BY timestamp_ms DESC, id DESC
         LIMIT -1 OFFSET ?
     )",
         [name, max_polls.to_string()],
@@ -1,11 +1,17 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, net::IpAddr, str::FromStr, time::Duration};
+use std::{collections::HashSet, net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
 
 use crate::client::model::slave::PolledSlave;
+use crate::common::model::Transport;
 
-fn default_port() -> u16 {
-    502
+//Modbus/TCP Security has its own well known port
+fn default_port(tls: bool) -> u16 {
+    if tls {
+        802
+    } else {
+        502
+    }
 }
 
 fn default_ip() -> IpAddr {
@@ -18,15 +24,38 @@ pub struct PolledConnection {
     pub config: PolledConnectionConfig,
     #[serde(default = "default_ip")]
     pub ip: IpAddr,
-    #[serde(default = "default_port")]
-    pub port: u16,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub transport: Transport,
+    #[serde(default)]
+    pub tls: Option<PolledTlsConfig>,
     pub slaves: Vec<PolledSlave>,
 }
 
 impl PolledConnection {
+    pub fn get_port(&self) -> u16 {
+        self.port.unwrap_or_else(|| default_port(self.tls.is_some()))
+    }
+
+    pub fn get_name(&self) -> String {
+        match &self.transport {
+            Transport::Rtu(serial_config) => serial_config.device.clone(),
+            _ => format!("{}:{}", self.ip, self.get_port()),
+        }
+    }
+
     pub fn validate(&self) -> Result<()> {
         let mut error_string = String::new();
 
+        if let Err(err) = self.transport.validate() {
+            error_string += &format!("\t{}\n", err);
+        }
+
+        if self.tls.is_some() && self.transport != Transport::Tcp {
+            error_string += "\tTLS is only supported with the tcp transport\n";
+        }
+
         for slave in &self.slaves {
             if let Err(err) = slave.validate() {
                 error_string += &format!("\t{}:\n{}\n", slave.id, err);
@@ -56,11 +85,24 @@ impl PolledConnection {
     }
 }
 
+fn default_min_reconnect_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_reconnect_delay() -> Duration {
+    Duration::from_secs(60)
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PolledConnectionConfig {
     pub max_simultaneous_connections: u32,
     #[serde(with = "humantime_serde")]
     pub max_response_time: Duration,
+    //The delay doubles with every failed attempt until it reaches the max
+    #[serde(with = "humantime_serde", default = "default_min_reconnect_delay")]
+    pub min_reconnect_delay: Duration,
+    #[serde(with = "humantime_serde", default = "default_max_reconnect_delay")]
+    pub max_reconnect_delay: Duration,
 }
 
 impl Default for PolledConnectionConfig {
@@ -68,6 +110,19 @@ impl Default for PolledConnectionConfig {
         PolledConnectionConfig {
             max_simultaneous_connections: 1,
             max_response_time: Duration::from_secs(1),
+            min_reconnect_delay: default_min_reconnect_delay(),
+            max_reconnect_delay: default_max_reconnect_delay(),
         }
     }
 }
+
+//Modbus/TCP Security requires mutual authentication, so a client certificate is mandatory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PolledTlsConfig {
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+    pub ca_certificate: PathBuf,
+    //Name checked against the slave's certificate, the ip is used when missing
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
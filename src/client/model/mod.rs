@@ -3,5 +3,5 @@ mod slave;
 mod connection;
 
 pub use value::PolledValue;
-pub use connection::PolledConnection;
+pub use connection::{PolledConnection, PolledTlsConfig};
 
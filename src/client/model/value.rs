@@ -33,6 +33,10 @@ pub struct PolledValue {
     #[serde(with = "humantime_serde")]
     pub poll_time: std::time::Duration,
 
+    //Free labels to group values, used to filter live subscriptions
+    #[serde(default)]
+    pub tags: Vec<String>,
+
     #[serde(default = "default_max_polls_to_keep")]
     pub max_polls_to_keep: Option<u64>,
     #[serde(default = "default_max_minute_aggregations_to_keep")]
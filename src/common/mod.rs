@@ -1,3 +1,5 @@
 pub mod value_processing;
 pub mod model;
-pub mod logging;
\ No newline at end of file
+pub mod logging;
+pub mod protocol;
+pub mod random;
\ No newline at end of file
@@ -1,11 +1,15 @@
 use anyhow::{anyhow, Result};
+use serde::de::{self, Deserializer, Visitor};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     Boolean,
 
     Byte,
+    SignedByte,
 
     UnsignedInteger16,
     SignedInteger16,
@@ -18,6 +22,25 @@ pub enum DataType {
 
     Float,
     Double,
+
+    //Packed decimal, one digit per nibble
+    Bcd16,
+    Bcd32,
+
+    //Length in characters, two of them per register
+    String {
+        length: u16,
+        #[serde(default)]
+        encoding: StringEncoding,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum StringEncoding {
+    #[default]
+    Ascii,
+    Latin1,
+    Utf8,
 }
 
 impl DataType {
@@ -26,6 +49,9 @@ impl DataType {
         match self {
             DataType::Float => 32,
             DataType::Double => 64,
+            DataType::Bcd16 => 16,
+            DataType::Bcd32 => 32,
+            DataType::String { .. } => self.byte_size() as u16 * 8,
             _ => 1,
         }
     }
@@ -34,6 +60,7 @@ impl DataType {
         match self {
             DataType::Boolean => 1,
             DataType::Byte => 1,
+            DataType::SignedByte => 1,
             
             DataType::SignedInteger16 => 2,
             DataType::UnsignedInteger16 => 2,
@@ -45,16 +72,93 @@ impl DataType {
             DataType::UnsignedInteger64 => 8,
 
             DataType::Float => 4,
-            DataType::Double => 8
+            DataType::Double => 8,
+
+            DataType::Bcd16 => 2,
+            DataType::Bcd32 => 4,
+
+            //Strings always fill their registers
+            DataType::String { length, .. } => (*length as usize).div_ceil(2) * 2,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            DataType::SignedByte
+                | DataType::SignedInteger16
+                | DataType::SignedInteger32
+                | DataType::SignedInteger64
+        )
+    }
+
+    pub fn get_bcd_digits(&self) -> Option<u32> {
+        match self {
+            DataType::Bcd16 => Some(4),
+            DataType::Bcd32 => Some(8),
+            _ => None,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Value {
-    Integer(i128),
+    Integer(#[serde(deserialize_with = "deserialize_integer")] i128),
     FloatingPoint(f64),
     Boolean(bool),
+    Text(String),
+}
+
+//Internally tagged enums, like scenario actions, buffer their content and can't hold an i128.
+//Values are at most 64 bits, so any integer the format has is enough
+fn deserialize_integer<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<i128, D::Error> {
+    struct IntegerVisitor;
+
+    impl Visitor<'_> for IntegerVisitor {
+        type Value = i128;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("an integer")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<i128, E> {
+            Ok(value as i128)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> std::result::Result<i128, E> {
+            Ok(value as i128)
+        }
+
+        fn visit_i128<E: de::Error>(self, value: i128) -> std::result::Result<i128, E> {
+            Ok(value)
+        }
+
+        fn visit_u128<E: de::Error>(self, value: u128) -> std::result::Result<i128, E> {
+            i128::try_from(value).map_err(|_| E::custom("integer out of range"))
+        }
+    }
+
+    deserializer.deserialize_any(IntegerVisitor)
+}
+
+impl Value {
+    //Booleans count as 0 and 1, so every value can be compared against numeric limits. Text is NaN
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Value::Integer(value) => *value as f64,
+            Value::FloatingPoint(value) => *value,
+            Value::Boolean(value) => {
+                if *value {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Value::Text(_) => f64::NAN,
+        }
+    }
 }
 
 //I have to repeat this enum in order to use the derivation of serde traits :(
@@ -84,6 +188,55 @@ impl ModbusTable {
     }
 }
 
+//Order of the value bytes in the registers as device manuals write it, A being the most significant
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ByteOrder {
+    Ab,
+    Ba,
+
+    Abcd,
+    Cdab,
+    Badc,
+    Dcba,
+
+    Abcdefgh,
+    Ghefcdab,
+    Badcfehg,
+    Hgfedcba,
+}
+
+impl ByteOrder {
+    pub fn get_bit_width(&self) -> u16 {
+        match self {
+            ByteOrder::Ab | ByteOrder::Ba => 16,
+            ByteOrder::Abcd | ByteOrder::Cdab | ByteOrder::Badc | ByteOrder::Dcba => 32,
+            ByteOrder::Abcdefgh | ByteOrder::Ghefcdab | ByteOrder::Badcfehg | ByteOrder::Hgfedcba => {
+                64
+            }
+        }
+    }
+
+    //Byte, word and double word swaps giving the same order. Registers are taken low byte first,
+    //so a register holding AB is read as BA and CDAB needs no swaps at all
+    pub fn get_swaps(&self) -> (bool, bool, bool) {
+        match self {
+            ByteOrder::Ab => (false, false, false),
+            ByteOrder::Ba => (true, false, false),
+
+            ByteOrder::Abcd => (false, true, false),
+            ByteOrder::Cdab => (false, false, false),
+            ByteOrder::Badc => (true, true, false),
+            ByteOrder::Dcba => (true, false, false),
+
+            ByteOrder::Abcdefgh => (false, true, true),
+            ByteOrder::Ghefcdab => (false, false, false),
+            ByteOrder::Badcfehg => (true, true, true),
+            ByteOrder::Hgfedcba => (true, false, false),
+        }
+    }
+}
+
 fn default_starting_bit() -> u8 {
     0
 }
@@ -116,6 +269,85 @@ pub struct ValueFormattingParams {
     pub word_swap: bool,
     #[serde(default = "default_double_word_swap")]
     pub double_word_swap: bool,
+    //Replaces the three swaps above
+    #[serde(default)]
+    pub byte_order: Option<ByteOrder>,
+
+    //Engineering value = raw * scale + offset
+    #[serde(default)]
+    pub scale: Option<f64>,
+    #[serde(default)]
+    pub offset: Option<f64>,
+    //Two point linear map, an alternative to scale and offset
+    #[serde(default)]
+    pub raw_range: Option<[f64; 2]>,
+    #[serde(default)]
+    pub engineering_range: Option<[f64; 2]>,
+
+    #[serde(default)]
+    pub unit: Option<String>,
+    //Engineering values are rounded to them
+    #[serde(default)]
+    pub decimals: Option<u8>,
+
+    //Labels of an enumerated value, like 0 = Stopped
+    #[serde(default, deserialize_with = "deserialize_states")]
+    pub states: Option<BTreeMap<i64, String>>,
+}
+
+//Values flatten these params, so the map keys arrive buffered as strings and serde won't read them as i64
+fn deserialize_states<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<BTreeMap<i64, String>>, D::Error> {
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    struct StateValue(i64);
+
+    impl<'de> Deserialize<'de> for StateValue {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<Self, D::Error> {
+            struct StateValueVisitor;
+
+            impl Visitor<'_> for StateValueVisitor {
+                type Value = StateValue;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("an integer state value")
+                }
+
+                fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<StateValue, E> {
+                    Ok(StateValue(value))
+                }
+
+                fn visit_u64<E: de::Error>(self, value: u64) -> std::result::Result<StateValue, E> {
+                    i64::try_from(value)
+                        .map(StateValue)
+                        .map_err(|_| E::custom("state value out of range"))
+                }
+
+                fn visit_str<E: de::Error>(
+                    self,
+                    value: &str,
+                ) -> std::result::Result<StateValue, E> {
+                    value
+                        .parse()
+                        .map(StateValue)
+                        .map_err(|_| E::custom(format!("invalid state value {:?}", value)))
+                }
+            }
+
+            deserializer.deserialize_any(StateValueVisitor)
+        }
+    }
+
+    let states = Option::<BTreeMap<StateValue, String>>::deserialize(deserializer)?;
+
+    Ok(states.map(|states| {
+        states
+            .into_iter()
+            .map(|(value, label)| (value.0, label))
+            .collect()
+    }))
 }
 
 impl ValueFormattingParams {
@@ -151,7 +383,26 @@ impl ValueFormattingParams {
             ));
         }
 
-        if self.bit_length > MAX_VALUE_BIT_LENGTH {
+        //A float cut to fewer or extended to more bits can't be decoded
+        if self.data_type == DataType::Float && self.bit_length != 32 {
+            return Err(anyhow!(
+                "Float values are 32 bits long, {} was provided",
+                self.bit_length
+            ));
+        }
+
+        if let DataType::String { length, .. } = self.data_type {
+            if length == 0 {
+                return Err(anyhow!("String length can't be zero"));
+            }
+
+            if self.starting_bit != 0 || self.bit_length != self.data_type.min_bit_size() {
+                return Err(anyhow!(
+                    "Strings fill their registers, starting bit must be 0 and bit length {}",
+                    self.data_type.min_bit_size()
+                ));
+            }
+        } else if self.bit_length > MAX_VALUE_BIT_LENGTH {
             return Err(anyhow!(
                 "Bit length ({}) is too high, maximum length is {}",
                 self.bit_length,
@@ -159,6 +410,264 @@ impl ValueFormattingParams {
             ));
         }
 
+        self.validate_byte_order()?;
+        self.validate_scaling()?;
+        self.validate_states()?;
+
+        Ok(())
+    }
+
+    fn validate_byte_order(&self) -> Result<()> {
+        let Some(byte_order) = self.byte_order else {
+            return Ok(());
+        };
+
+        if self.byte_swap || self.word_swap || self.double_word_swap {
+            return Err(anyhow!(
+                "Byte order {:?} can't be used together with byte_swap, word_swap or double_word_swap",
+                byte_order
+            ));
+        }
+
+        //Strings are ordered inside each register
+        let width = match self.data_type {
+            DataType::String { .. } => 16,
+            _ => self.data_type.byte_size() as u16 * 8,
+        };
+
+        if byte_order.get_bit_width() != width {
+            return Err(anyhow!(
+                "Byte order {:?} is for {} bit values but {:?} is {} bits wide",
+                byte_order,
+                byte_order.get_bit_width(),
+                self.data_type,
+                width
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_states(&self) -> Result<()> {
+        let Some(states) = &self.states else {
+            return Ok(());
+        };
+
+        if let DataType::Boolean
+        | DataType::Float
+        | DataType::Double
+        | DataType::String { .. } = self.data_type
+        {
+            return Err(anyhow!("Only integer values can have states"));
+        }
+
+        if self.get_scaling().is_some() {
+            return Err(anyhow!("Values with states can't be scaled"));
+        }
+
+        let mut labels = std::collections::HashSet::new();
+
+        for label in states.values() {
+            if label.is_empty() {
+                return Err(anyhow!("State labels can't be empty"));
+            }
+
+            if !labels.insert(label) {
+                return Err(anyhow!("State label {} is repeated", label));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_state_label(&self, value: &Value) -> Option<String> {
+        let Value::Integer(value) = value else {
+            return None;
+        };
+
+        let value = i64::try_from(*value).ok()?;
+
+        self.states.as_ref()?.get(&value).cloned()
+    }
+
+    pub fn get_state_value(&self, label: &str) -> Option<i64> {
+        self.states
+            .as_ref()?
+            .iter()
+            .find(|(_, state)| *state == label)
+            .map(|(value, _)| *value)
+    }
+
+    pub fn get_swaps(&self) -> (bool, bool, bool) {
+        match self.byte_order {
+            Some(byte_order) => byte_order.get_swaps(),
+            None => (self.byte_swap, self.word_swap, self.double_word_swap),
+        }
+    }
+
+    fn validate_scaling(&self) -> Result<()> {
+        let linear = self.scale.is_some() || self.offset.is_some();
+        let ranges = self.raw_range.is_some() || self.engineering_range.is_some();
+
+        if linear && ranges {
+            return Err(anyhow!(
+                "Scale and offset can't be used together with raw and engineering ranges"
+            ));
+        }
+
+        if self.raw_range.is_some() != self.engineering_range.is_some() {
+            return Err(anyhow!("Raw and engineering ranges must be given together"));
+        }
+
+        if (linear || ranges)
+            && matches!(self.data_type, DataType::Boolean | DataType::String { .. })
+        {
+            return Err(anyhow!("Boolean and string values can't be scaled"));
+        }
+
+        if self.scale == Some(0.0) {
+            return Err(anyhow!("Scale can't be zero"));
+        }
+
+        if let (Some(raw_range), Some(engineering_range)) =
+            (self.raw_range, self.engineering_range)
+        {
+            if raw_range[0] == raw_range[1] || engineering_range[0] == engineering_range[1] {
+                return Err(anyhow!("Raw and engineering ranges can't be empty"));
+            }
+        }
+
         Ok(())
     }
+
+    //What the bits of an integer value can hold, None for the rest of data types
+    pub fn get_integer_range(&self) -> Option<(i128, i128)> {
+        if let Some(digits) = self.data_type.get_bcd_digits() {
+            return Some((0, 10i128.pow(digits) - 1));
+        }
+
+        if let DataType::Boolean | DataType::Float | DataType::Double | DataType::String { .. } =
+            self.data_type
+        {
+            return None;
+        }
+
+        let width = (self.bit_length as u32).min(self.data_type.byte_size() as u32 * 8);
+
+        if self.data_type.is_signed() {
+            Some((-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1))
+        } else {
+            Some((0, (1i128 << width) - 1))
+        }
+    }
+
+    //Gain and offset from raw to engineering values, None when the value isn't scaled
+    pub fn get_scaling(&self) -> Option<(f64, f64)> {
+        if let (Some(raw_range), Some(engineering_range)) =
+            (self.raw_range, self.engineering_range)
+        {
+            let gain = (engineering_range[1] - engineering_range[0]) / (raw_range[1] - raw_range[0]);
+            return Some((gain, engineering_range[0] - gain * raw_range[0]));
+        }
+
+        if self.scale.is_none() && self.offset.is_none() {
+            return None;
+        }
+
+        Some((self.scale.unwrap_or(1.0), self.offset.unwrap_or(0.0)))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Rtu(SerialConfig),
+    //Raw RTU frames, CRC included, over a TCP socket like most serial gateways do
+    RtuOverTcp,
+    Udp,
+}
+
+impl Transport {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Transport::Rtu(serial_config) => serial_config.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+fn default_baud_rate() -> u32 {
+    19200
+}
+
+fn default_parity() -> Parity {
+    //Modbus over serial line specifies even parity as the default
+    Parity::Even
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
+fn default_frame_delay() -> Option<Duration> {
+    None
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerialConfig {
+    pub device: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default = "default_parity")]
+    pub parity: Parity,
+    #[serde(default = "default_stop_bits")]
+    pub stop_bits: u8,
+    //Overrides the 3.5 character silent interval that delimits frames
+    #[serde(with = "humantime_serde", default = "default_frame_delay")]
+    pub frame_delay: Option<Duration>,
+}
+
+impl SerialConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.device.is_empty() {
+            return Err(anyhow!("A serial device must be provided for RTU transports"));
+        }
+
+        if self.baud_rate == 0 {
+            return Err(anyhow!("Baud rate must be greater than 0"));
+        }
+
+        if self.stop_bits != 1 && self.stop_bits != 2 {
+            return Err(anyhow!(
+                "Stop bits must be 1 or 2, {} was provided",
+                self.stop_bits
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn silent_interval(&self) -> Duration {
+        if let Some(frame_delay) = self.frame_delay {
+            return frame_delay;
+        }
+
+        //Over 19200 bauds the spec recommends a fixed 1.75ms interval
+        if self.baud_rate > 19200 {
+            return Duration::from_micros(1750);
+        }
+
+        //Every RTU character takes 11 bits: start, 8 data bits, parity (or extra stop) and stop
+        Duration::from_micros(3_500_000 * 11 / self.baud_rate as u64)
+    }
 }
@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub const HEADER_SIZE: usize = 7;
+
+//MBAP header + 253 bytes of PDU
+pub const MAX_FRAME_SIZE: usize = 260;
+
+const MODBUS_PROTOCOL_ID: u16 = 0;
+
+pub struct MbapFrame {
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    pub pdu: Vec<u8>,
+}
+
+pub fn encode_frame(transaction_id: u16, unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = vec![];
+
+    frame.extend_from_slice(&transaction_id.to_be_bytes());
+    frame.extend_from_slice(&MODBUS_PROTOCOL_ID.to_be_bytes());
+    //The length field counts the unit id too
+    frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+    frame.push(unit_id);
+    frame.extend_from_slice(pdu);
+
+    frame
+}
+
+pub fn decode_frame(frame: &[u8]) -> Result<MbapFrame> {
+    if frame.len() <= HEADER_SIZE {
+        return Err(anyhow!("MBAP frame is too short ({} bytes)", frame.len()));
+    }
+
+    let transaction_id = u16::from_be_bytes([frame[0], frame[1]]);
+    let protocol_id = u16::from_be_bytes([frame[2], frame[3]]);
+    let length = u16::from_be_bytes([frame[4], frame[5]]) as usize;
+
+    if protocol_id != MODBUS_PROTOCOL_ID {
+        return Err(anyhow!("Unknown protocol id {} in MBAP header", protocol_id));
+    }
+
+    if length != frame.len() - HEADER_SIZE + 1 {
+        return Err(anyhow!(
+            "MBAP header announces {} bytes but {} were received",
+            length,
+            frame.len() - HEADER_SIZE + 1
+        ));
+    }
+
+    Ok(MbapFrame {
+        transaction_id,
+        unit_id: frame[6],
+        pdu: frame[HEADER_SIZE..].to_vec(),
+    })
+}
+
+pub async fn read_stream_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut frame = vec![0u8; HEADER_SIZE];
+    reader.read_exact(&mut frame).await?;
+
+    let length = u16::from_be_bytes([frame[4], frame[5]]) as usize;
+
+    if length < 2 || HEADER_SIZE + length - 1 > MAX_FRAME_SIZE {
+        return Err(anyhow!("MBAP header announces an invalid length of {}", length));
+    }
+
+    frame.resize(HEADER_SIZE + length - 1, 0);
+    reader.read_exact(&mut frame[HEADER_SIZE..]).await?;
+
+    Ok(frame)
+}
@@ -0,0 +1,4 @@
+pub mod mbap;
+pub mod pdu;
+pub mod rtu;
+pub mod tls;
@@ -0,0 +1,553 @@
+use anyhow::{anyhow, Result};
+use tweakable_modbus::{ExceptionCode, ModbusTable};
+
+pub const READ_COILS: u8 = 0x01;
+pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+pub const WRITE_SINGLE_COIL: u8 = 0x05;
+pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+const EXCEPTION_FLAG: u8 = 0x80;
+
+const MAX_READ_BITS: u16 = 2000;
+const MAX_READ_REGISTERS: u16 = 125;
+const MAX_WRITE_BITS: u16 = 1968;
+const MAX_WRITE_REGISTERS: u16 = 123;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    ReadCoils { address: u16, ammount: u16 },
+    ReadDiscreteInputs { address: u16, ammount: u16 },
+    ReadHoldingRegisters { address: u16, ammount: u16 },
+    ReadInputRegisters { address: u16, ammount: u16 },
+    WriteSingleCoil { address: u16, value: bool },
+    WriteSingleRegister { address: u16, value: u16 },
+    WriteMultipleCoils { address: u16, values: Vec<bool> },
+    WriteMultipleRegisters { address: u16, values: Vec<u16> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    Coils(Vec<bool>),
+    Registers(Vec<u16>),
+    Written,
+    Exception(u8),
+}
+
+impl Request {
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Request::ReadCoils { .. } => READ_COILS,
+            Request::ReadDiscreteInputs { .. } => READ_DISCRETE_INPUTS,
+            Request::ReadHoldingRegisters { .. } => READ_HOLDING_REGISTERS,
+            Request::ReadInputRegisters { .. } => READ_INPUT_REGISTERS,
+            Request::WriteSingleCoil { .. } => WRITE_SINGLE_COIL,
+            Request::WriteSingleRegister { .. } => WRITE_SINGLE_REGISTER,
+            Request::WriteMultipleCoils { .. } => WRITE_MULTIPLE_COILS,
+            Request::WriteMultipleRegisters { .. } => WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    pub fn table(&self) -> ModbusTable {
+        match self {
+            Request::ReadCoils { .. }
+            | Request::WriteSingleCoil { .. }
+            | Request::WriteMultipleCoils { .. } => ModbusTable::Coils,
+            Request::ReadDiscreteInputs { .. } => ModbusTable::DiscreteInput,
+            Request::ReadHoldingRegisters { .. }
+            | Request::WriteSingleRegister { .. }
+            | Request::WriteMultipleRegisters { .. } => ModbusTable::HoldingRegisters,
+            Request::ReadInputRegisters { .. } => ModbusTable::InputRegisters,
+        }
+    }
+
+    pub fn address(&self) -> u16 {
+        match self {
+            Request::ReadCoils { address, .. }
+            | Request::ReadDiscreteInputs { address, .. }
+            | Request::ReadHoldingRegisters { address, .. }
+            | Request::ReadInputRegisters { address, .. }
+            | Request::WriteSingleCoil { address, .. }
+            | Request::WriteSingleRegister { address, .. }
+            | Request::WriteMultipleCoils { address, .. }
+            | Request::WriteMultipleRegisters { address, .. } => *address,
+        }
+    }
+
+    pub fn ammount(&self) -> u16 {
+        match self {
+            Request::ReadCoils { ammount, .. }
+            | Request::ReadDiscreteInputs { ammount, .. }
+            | Request::ReadHoldingRegisters { ammount, .. }
+            | Request::ReadInputRegisters { ammount, .. } => *ammount,
+            Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => 1,
+            Request::WriteMultipleCoils { values, .. } => values.len() as u16,
+            Request::WriteMultipleRegisters { values, .. } => values.len() as u16,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut pdu = vec![self.function_code()];
+        pdu.extend_from_slice(&self.address().to_be_bytes());
+
+        match self {
+            Request::ReadCoils { ammount, .. }
+            | Request::ReadDiscreteInputs { ammount, .. }
+            | Request::ReadHoldingRegisters { ammount, .. }
+            | Request::ReadInputRegisters { ammount, .. } => {
+                pdu.extend_from_slice(&ammount.to_be_bytes());
+            }
+            Request::WriteSingleCoil { value, .. } => {
+                let value: u16 = if *value { 0xFF00 } else { 0x0000 };
+                pdu.extend_from_slice(&value.to_be_bytes());
+            }
+            Request::WriteSingleRegister { value, .. } => {
+                pdu.extend_from_slice(&value.to_be_bytes());
+            }
+            Request::WriteMultipleCoils { values, .. } => {
+                let bytes = pack_bits(values);
+                pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+                pdu.push(bytes.len() as u8);
+                pdu.extend_from_slice(&bytes);
+            }
+            Request::WriteMultipleRegisters { values, .. } => {
+                pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+                pdu.push((values.len() * 2) as u8);
+                for value in values {
+                    pdu.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+
+        pdu
+    }
+
+    //On failure the exception code that must be sent back is returned
+    pub fn decode(pdu: &[u8]) -> Result<Request, u8> {
+        let illegal_data_value = exception_code_to_u8(&ExceptionCode::IllegalDataValue);
+
+        let function_code = *pdu.first().ok_or(illegal_data_value)?;
+
+        let supported = matches!(
+            function_code,
+            READ_COILS
+                | READ_DISCRETE_INPUTS
+                | READ_HOLDING_REGISTERS
+                | READ_INPUT_REGISTERS
+                | WRITE_SINGLE_COIL
+                | WRITE_SINGLE_REGISTER
+                | WRITE_MULTIPLE_COILS
+                | WRITE_MULTIPLE_REGISTERS
+        );
+
+        if !supported {
+            return Err(exception_code_to_u8(&ExceptionCode::IllegalFunction));
+        }
+
+        if pdu.len() < 5 {
+            return Err(illegal_data_value);
+        }
+
+        let address = u16::from_be_bytes([pdu[1], pdu[2]]);
+        let field = u16::from_be_bytes([pdu[3], pdu[4]]);
+
+        let request = match function_code {
+            READ_COILS | READ_DISCRETE_INPUTS => {
+                if field == 0 || field > MAX_READ_BITS {
+                    return Err(illegal_data_value);
+                }
+
+                if function_code == READ_COILS {
+                    Request::ReadCoils {
+                        address,
+                        ammount: field,
+                    }
+                } else {
+                    Request::ReadDiscreteInputs {
+                        address,
+                        ammount: field,
+                    }
+                }
+            }
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                if field == 0 || field > MAX_READ_REGISTERS {
+                    return Err(illegal_data_value);
+                }
+
+                if function_code == READ_HOLDING_REGISTERS {
+                    Request::ReadHoldingRegisters {
+                        address,
+                        ammount: field,
+                    }
+                } else {
+                    Request::ReadInputRegisters {
+                        address,
+                        ammount: field,
+                    }
+                }
+            }
+            WRITE_SINGLE_COIL => match field {
+                0xFF00 => Request::WriteSingleCoil {
+                    address,
+                    value: true,
+                },
+                0x0000 => Request::WriteSingleCoil {
+                    address,
+                    value: false,
+                },
+                _ => return Err(illegal_data_value),
+            },
+            WRITE_SINGLE_REGISTER => Request::WriteSingleRegister {
+                address,
+                value: field,
+            },
+            WRITE_MULTIPLE_COILS => {
+                let byte_count = *pdu.get(5).ok_or(illegal_data_value)? as usize;
+
+                if field == 0
+                    || field > MAX_WRITE_BITS
+                    || byte_count != (field as usize).div_ceil(8)
+                    || pdu.len() != 6 + byte_count
+                {
+                    return Err(illegal_data_value);
+                }
+
+                Request::WriteMultipleCoils {
+                    address,
+                    values: unpack_bits(&pdu[6..], field as usize),
+                }
+            }
+            _ => {
+                let byte_count = *pdu.get(5).ok_or(illegal_data_value)? as usize;
+
+                if field == 0
+                    || field > MAX_WRITE_REGISTERS
+                    || byte_count != field as usize * 2
+                    || pdu.len() != 6 + byte_count
+                {
+                    return Err(illegal_data_value);
+                }
+
+                Request::WriteMultipleRegisters {
+                    address,
+                    values: pdu[6..]
+                        .chunks_exact(2)
+                        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                        .collect(),
+                }
+            }
+        };
+
+        if address as u32 + request.ammount() as u32 > u16::MAX as u32 + 1 {
+            return Err(exception_code_to_u8(&ExceptionCode::IllegalDataAddress));
+        }
+
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self, request: &Request) -> Vec<u8> {
+        let function_code = request.function_code();
+
+        match self {
+            Response::Exception(exception_code) => {
+                vec![function_code | EXCEPTION_FLAG, *exception_code]
+            }
+            Response::Coils(values) => {
+                let bytes = pack_bits(values);
+                let mut pdu = vec![function_code, bytes.len() as u8];
+                pdu.extend_from_slice(&bytes);
+                pdu
+            }
+            Response::Registers(values) => {
+                let mut pdu = vec![function_code, (values.len() * 2) as u8];
+                for value in values {
+                    pdu.extend_from_slice(&value.to_be_bytes());
+                }
+                pdu
+            }
+            Response::Written => match request {
+                //Single writes echo the request
+                Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => {
+                    request.encode()
+                }
+                _ => {
+                    let mut pdu = vec![function_code];
+                    pdu.extend_from_slice(&request.address().to_be_bytes());
+                    pdu.extend_from_slice(&request.ammount().to_be_bytes());
+                    pdu
+                }
+            },
+        }
+    }
+
+    pub fn decode(request: &Request, pdu: &[u8]) -> Result<Response> {
+        let function_code = *pdu.first().ok_or(anyhow!("Empty response"))?;
+
+        if function_code == request.function_code() | EXCEPTION_FLAG {
+            let exception_code = *pdu
+                .get(1)
+                .ok_or(anyhow!("Exception response without exception code"))?;
+            return Ok(Response::Exception(exception_code));
+        }
+
+        if function_code != request.function_code() {
+            return Err(anyhow!(
+                "Expected function code {} but {} was received",
+                request.function_code(),
+                function_code
+            ));
+        }
+
+        let ammount = request.ammount() as usize;
+
+        match request {
+            Request::ReadCoils { .. } | Request::ReadDiscreteInputs { .. } => {
+                let byte_count = *pdu.get(1).ok_or(anyhow!("Missing byte count"))? as usize;
+
+                if byte_count != ammount.div_ceil(8) || pdu.len() != 2 + byte_count {
+                    return Err(anyhow!("Malformed read bits response"));
+                }
+
+                Ok(Response::Coils(unpack_bits(&pdu[2..], ammount)))
+            }
+            Request::ReadHoldingRegisters { .. } | Request::ReadInputRegisters { .. } => {
+                let byte_count = *pdu.get(1).ok_or(anyhow!("Missing byte count"))? as usize;
+
+                if byte_count != ammount * 2 || pdu.len() != 2 + byte_count {
+                    return Err(anyhow!("Malformed read registers response"));
+                }
+
+                Ok(Response::Registers(
+                    pdu[2..]
+                        .chunks_exact(2)
+                        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                        .collect(),
+                ))
+            }
+            _ => {
+                if pdu.len() != 5 {
+                    return Err(anyhow!("Malformed write response"));
+                }
+
+                Ok(Response::Written)
+            }
+        }
+    }
+}
+
+pub fn exception_code_to_u8(exception_code: &ExceptionCode) -> u8 {
+    match exception_code {
+        ExceptionCode::IllegalFunction => 0x01,
+        ExceptionCode::IllegalDataAddress => 0x02,
+        ExceptionCode::IllegalDataValue => 0x03,
+        ExceptionCode::ServerDeviceFailure => 0x04,
+        ExceptionCode::Acknowledge => 0x05,
+        ExceptionCode::ServerDeviceBusy => 0x06,
+        ExceptionCode::MemoryParityError => 0x08,
+        ExceptionCode::GatewayPathUnavailable => 0x0A,
+        ExceptionCode::GatewayTargetDeviceFailedToRespond => 0x0B,
+    }
+}
+
+pub fn exception_code_from_u8(exception_code: u8) -> Result<ExceptionCode> {
+    match exception_code {
+        0x01 => Ok(ExceptionCode::IllegalFunction),
+        0x02 => Ok(ExceptionCode::IllegalDataAddress),
+        0x03 => Ok(ExceptionCode::IllegalDataValue),
+        0x04 => Ok(ExceptionCode::ServerDeviceFailure),
+        0x05 => Ok(ExceptionCode::Acknowledge),
+        0x06 => Ok(ExceptionCode::ServerDeviceBusy),
+        0x08 => Ok(ExceptionCode::MemoryParityError),
+        0x0A => Ok(ExceptionCode::GatewayPathUnavailable),
+        0x0B => Ok(ExceptionCode::GatewayTargetDeviceFailedToRespond),
+        _ => Err(anyhow!("Unknown exception code {}", exception_code)),
+    }
+}
+
+fn pack_bits(values: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; values.len().div_ceil(8)];
+
+    for (i, value) in values.iter().enumerate() {
+        if *value {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+    }
+
+    bytes
+}
+
+fn unpack_bits(bytes: &[u8], ammount: usize) -> Vec<bool> {
+    (0..ammount)
+        .map(|i| (bytes[i / 8] >> (i % 8)) & 1 == 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Examples from the Modbus application protocol specification
+    #[test]
+    fn requests_known_answers() {
+        let requests = [
+            (
+                Request::ReadCoils {
+                    address: 0x0013,
+                    ammount: 19,
+                },
+                vec![0x01, 0x00, 0x13, 0x00, 0x13],
+            ),
+            (
+                Request::ReadHoldingRegisters {
+                    address: 0x006B,
+                    ammount: 3,
+                },
+                vec![0x03, 0x00, 0x6B, 0x00, 0x03],
+            ),
+            (
+                Request::WriteSingleCoil {
+                    address: 0x00AC,
+                    value: true,
+                },
+                vec![0x05, 0x00, 0xAC, 0xFF, 0x00],
+            ),
+            (
+                Request::WriteSingleRegister {
+                    address: 0x0001,
+                    value: 0x0003,
+                },
+                vec![0x06, 0x00, 0x01, 0x00, 0x03],
+            ),
+            (
+                Request::WriteMultipleCoils {
+                    address: 0x0013,
+                    values: vec![
+                        true, false, true, true, false, false, true, true, true, false,
+                    ],
+                },
+                vec![0x0F, 0x00, 0x13, 0x00, 0x0A, 0x02, 0xCD, 0x01],
+            ),
+            (
+                Request::WriteMultipleRegisters {
+                    address: 0x0001,
+                    values: vec![0x000A, 0x0102],
+                },
+                vec![0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02],
+            ),
+        ];
+
+        for (request, pdu) in requests {
+            assert_eq!(request.encode(), pdu);
+            assert_eq!(Request::decode(&pdu), Ok(request));
+        }
+    }
+
+    #[test]
+    fn malformed_requests_get_their_exception_code() {
+        //Unsupported function code
+        assert_eq!(Request::decode(&[0x2B, 0x0E, 0x01, 0x00]), Err(0x01));
+        //No registers to read
+        assert_eq!(Request::decode(&[0x03, 0x00, 0x00, 0x00, 0x00]), Err(0x03));
+        //A coil can only be written with 0xFF00 or 0x0000
+        assert_eq!(Request::decode(&[0x05, 0x00, 0x01, 0x12, 0x34]), Err(0x03));
+        //Byte count doesn't match the ammount of registers
+        assert_eq!(
+            Request::decode(&[0x10, 0x00, 0x01, 0x00, 0x02, 0x02, 0x00, 0x0A]),
+            Err(0x03)
+        );
+        //Past the last address
+        assert_eq!(Request::decode(&[0x03, 0xFF, 0xFF, 0x00, 0x02]), Err(0x02));
+    }
+
+    #[test]
+    fn responses_known_answers() {
+        let read_coils = Request::ReadCoils {
+            address: 0x0013,
+            ammount: 19,
+        };
+        let coils = vec![
+            true, false, true, true, false, false, true, true, true, true, false, true, false,
+            true, true, false, true, false, true,
+        ];
+
+        assert_eq!(
+            Response::Coils(coils.clone()).encode(&read_coils),
+            vec![0x01, 0x03, 0xCD, 0x6B, 0x05]
+        );
+        assert_eq!(
+            Response::decode(&read_coils, &[0x01, 0x03, 0xCD, 0x6B, 0x05]).unwrap(),
+            Response::Coils(coils)
+        );
+
+        let read_registers = Request::ReadHoldingRegisters {
+            address: 0x006B,
+            ammount: 3,
+        };
+        let registers = [0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64];
+
+        assert_eq!(
+            Response::Registers(vec![0x022B, 0x0000, 0x0064]).encode(&read_registers),
+            registers
+        );
+        assert_eq!(
+            Response::decode(&read_registers, &registers).unwrap(),
+            Response::Registers(vec![0x022B, 0x0000, 0x0064])
+        );
+
+        let write_registers = Request::WriteMultipleRegisters {
+            address: 0x0001,
+            values: vec![0x000A, 0x0102],
+        };
+
+        assert_eq!(
+            Response::Written.encode(&write_registers),
+            vec![0x10, 0x00, 0x01, 0x00, 0x02]
+        );
+        assert_eq!(
+            Response::decode(&write_registers, &[0x10, 0x00, 0x01, 0x00, 0x02]).unwrap(),
+            Response::Written
+        );
+
+        //Single writes are echoed
+        let write_coil = Request::WriteSingleCoil {
+            address: 0x00AC,
+            value: true,
+        };
+        assert_eq!(Response::Written.encode(&write_coil), write_coil.encode());
+    }
+
+    #[test]
+    fn exception_responses() {
+        let request = Request::ReadInputRegisters {
+            address: 0x0008,
+            ammount: 1,
+        };
+
+        assert_eq!(Response::Exception(0x02).encode(&request), vec![0x84, 0x02]);
+        assert_eq!(
+            Response::decode(&request, &[0x84, 0x02]).unwrap(),
+            Response::Exception(0x02)
+        );
+
+        //An answer to another function or a short one is an error
+        assert!(Response::decode(&request, &[0x03, 0x02, 0x00, 0x01]).is_err());
+        assert!(Response::decode(&request, &[0x04, 0x04, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn exception_codes_round_trip() {
+        for code in [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x08, 0x0A, 0x0B] {
+            assert_eq!(
+                exception_code_to_u8(&exception_code_from_u8(code).unwrap()),
+                code
+            );
+        }
+
+        assert!(exception_code_from_u8(0x07).is_err());
+    }
+}
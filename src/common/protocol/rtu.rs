@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_serial::{DataBits, SerialPortBuilderExt, SerialStream, StopBits};
+
+use crate::common::model::{Parity, SerialConfig};
+use crate::common::protocol::pdu;
+
+//Slave id + 253 bytes of PDU + CRC
+pub const MAX_FRAME_SIZE: usize = 256;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+
+    for byte in data {
+        crc ^= *byte as u16;
+
+        for _i in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xA001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+
+    crc
+}
+
+pub fn encode_frame(slave_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = vec![slave_id];
+    frame.extend_from_slice(pdu);
+
+    //The CRC is the only field sent low byte first
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+
+    frame
+}
+
+pub fn decode_frame(frame: &[u8]) -> Result<(u8, Vec<u8>)> {
+    if frame.len() < 4 {
+        return Err(anyhow!("RTU frame is too short ({} bytes)", frame.len()));
+    }
+
+    let (content, crc) = frame.split_at(frame.len() - 2);
+    let crc = u16::from_le_bytes([crc[0], crc[1]]);
+
+    if crc16(content) != crc {
+        return Err(anyhow!("RTU frame has a wrong CRC"));
+    }
+
+    Ok((content[0], content[1..].to_vec()))
+}
+
+//A frame ends when the line stays silent for the given interval
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    silent_interval: Duration,
+) -> Result<Vec<u8>> {
+    let mut frame = vec![];
+    let mut buffer = [0u8; MAX_FRAME_SIZE];
+
+    let read = reader.read(&mut buffer).await?;
+
+    if read == 0 {
+        return Err(anyhow!("Serial line was closed"));
+    }
+
+    frame.extend_from_slice(&buffer[..read]);
+
+    loop {
+        match tokio::time::timeout(silent_interval, reader.read(&mut buffer)).await {
+            Err(_) => break,
+            Ok(read) => {
+                let read = read?;
+
+                if read == 0 {
+                    break;
+                }
+
+                frame.extend_from_slice(&buffer[..read]);
+            }
+        }
+    }
+
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(anyhow!(
+            "RTU frame is too long ({} bytes), maximum is {}",
+            frame.len(),
+            MAX_FRAME_SIZE
+        ));
+    }
+
+    Ok(frame)
+}
+
+//Over a stream there is no silent interval, so the frame length has to be worked out from its content.
+//None means more bytes are needed to know it
+fn expected_frame_length(frame: &[u8], is_request: bool) -> Result<Option<usize>> {
+    let function_code = match frame.get(1) {
+        Some(function_code) => *function_code,
+        None => return Ok(None),
+    };
+
+    //Slave id + function code + exception code + CRC
+    if function_code & 0x80 != 0 {
+        return Ok(Some(5));
+    }
+
+    let byte_count = |index: usize| frame.get(index).map(|byte_count| *byte_count as usize);
+
+    let length = match (function_code, is_request) {
+        (pdu::READ_COILS..=pdu::WRITE_SINGLE_REGISTER, true) => Some(8),
+        (pdu::WRITE_MULTIPLE_COILS | pdu::WRITE_MULTIPLE_REGISTERS, true) => {
+            byte_count(6).map(|byte_count| 9 + byte_count)
+        }
+        (pdu::READ_COILS..=pdu::READ_INPUT_REGISTERS, false) => {
+            byte_count(2).map(|byte_count| 5 + byte_count)
+        }
+        (
+            pdu::WRITE_SINGLE_COIL
+            | pdu::WRITE_SINGLE_REGISTER
+            | pdu::WRITE_MULTIPLE_COILS
+            | pdu::WRITE_MULTIPLE_REGISTERS,
+            false,
+        ) => Some(8),
+        _ => {
+            return Err(anyhow!(
+                "Can't delimit RTU frame with function code {}",
+                function_code
+            ))
+        }
+    };
+
+    Ok(length)
+}
+
+pub async fn read_stream_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    is_request: bool,
+) -> Result<Vec<u8>> {
+    let mut frame = vec![0u8; 2];
+    reader.read_exact(&mut frame).await?;
+
+    loop {
+        match expected_frame_length(&frame, is_request)? {
+            Some(length) => {
+                if length > MAX_FRAME_SIZE {
+                    return Err(anyhow!(
+                        "RTU frame is too long ({} bytes), maximum is {}",
+                        length,
+                        MAX_FRAME_SIZE
+                    ));
+                }
+
+                let read = frame.len();
+                frame.resize(length, 0);
+                reader.read_exact(&mut frame[read..]).await?;
+
+                return Ok(frame);
+            }
+            None => {
+                let mut byte = [0u8];
+                reader.read_exact(&mut byte).await?;
+                frame.push(byte[0]);
+            }
+        }
+    }
+}
+
+pub fn open_serial_port(config: &SerialConfig) -> Result<SerialStream> {
+    let parity = match config.parity {
+        Parity::None => tokio_serial::Parity::None,
+        Parity::Even => tokio_serial::Parity::Even,
+        Parity::Odd => tokio_serial::Parity::Odd,
+    };
+
+    let stop_bits = if config.stop_bits == 2 {
+        StopBits::Two
+    } else {
+        StopBits::One
+    };
+
+    let port = tokio_serial::new(&config.device, config.baud_rate)
+        .data_bits(DataBits::Eight)
+        .parity(parity)
+        .stop_bits(stop_bits)
+        .open_native_async()?;
+
+    Ok(port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn crc16_known_answers() {
+        assert_eq!(crc16(b"123456789"), 0x4B37);
+        assert_eq!(crc16(&[]), 0xFFFF);
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]), 0xCDC5);
+    }
+
+    #[test]
+    fn frames_carry_the_crc_low_byte_first() {
+        //Read 3 holding registers from 0x006B of slave 0x11, from the Modbus over serial line spec
+        let pdu = [0x03, 0x00, 0x6B, 0x00, 0x03];
+        let frame = encode_frame(0x11, &pdu);
+
+        assert_eq!(frame, vec![0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87]);
+        assert_eq!(decode_frame(&frame).unwrap(), (0x11, pdu.to_vec()));
+    }
+
+    #[test]
+    fn frames_with_a_wrong_crc_are_rejected() {
+        let mut frame = encode_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x01]);
+        let last = frame.len() - 1;
+        frame[last] ^= 0xFF;
+
+        assert!(decode_frame(&frame).is_err());
+        assert!(decode_frame(&[0x01, 0x03, 0x00]).is_err());
+    }
+
+    #[tokio::test]
+    async fn silent_interval_delimits_frames() {
+        let (mut line, mut port) = tokio::io::duplex(MAX_FRAME_SIZE);
+        let first = encode_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x02]);
+        let second = encode_frame(2, &[0x06, 0x00, 0x01, 0x12, 0x34]);
+
+        let writer = {
+            let (first, second) = (first.clone(), second.clone());
+
+            tokio::spawn(async move {
+                //A pause shorter than the silent interval doesn't split the frame
+                line.write_all(&first[..3]).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                line.write_all(&first[3..]).await.unwrap();
+
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                line.write_all(&second).await.unwrap();
+                line
+            })
+        };
+
+        let silent_interval = Duration::from_millis(50);
+
+        assert_eq!(read_frame(&mut port, silent_interval).await.unwrap(), first);
+        assert_eq!(
+            read_frame(&mut port, silent_interval).await.unwrap(),
+            second
+        );
+
+        drop(writer.await.unwrap());
+        assert!(read_frame(&mut port, silent_interval).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_frames_are_delimited_by_their_content() {
+        let read_response = encode_frame(1, &[0x03, 0x04, 0x00, 0x0A, 0x01, 0x02]);
+        let exception = encode_frame(1, &[0x83, 0x02]);
+        let write_request = encode_frame(
+            1,
+            &[0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02],
+        );
+
+        let mut responses: &[u8] = &[read_response.clone(), exception.clone()].concat();
+        assert_eq!(
+            read_stream_frame(&mut responses, false).await.unwrap(),
+            read_response
+        );
+        assert_eq!(
+            read_stream_frame(&mut responses, false).await.unwrap(),
+            exception
+        );
+
+        let mut requests: &[u8] = &write_request;
+        assert_eq!(
+            read_stream_frame(&mut requests, true).await.unwrap(),
+            write_request
+        );
+
+        let mut unknown: &[u8] = &encode_frame(1, &[0x2B, 0x0E, 0x01, 0x00]);
+        assert!(read_stream_frame(&mut unknown, true).await.is_err());
+    }
+}
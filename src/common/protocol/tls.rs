@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+//Role extension defined by the Modbus/TCP Security spec
+const ROLE_OID: &str = "1.3.6.1.4.1.50316.802.1";
+
+//UTF8String DER tag
+const UTF8_STRING_TAG: u8 = 0x0C;
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+
+    let certificates = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificates found in {}", path.display()));
+    }
+
+    Ok(certificates)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or(anyhow!("No private key found in {}", path.display()))
+}
+
+fn load_root_store(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+
+    for certificate in load_certificates(path)? {
+        roots.add(certificate)?;
+    }
+
+    Ok(roots)
+}
+
+pub fn build_client_config(
+    certificate: &Path,
+    private_key: &Path,
+    ca_certificate: &Path,
+) -> Result<ClientConfig> {
+    let config = ClientConfig::builder()
+        .with_root_certificates(load_root_store(ca_certificate)?)
+        .with_client_auth_cert(load_certificates(certificate)?, load_private_key(private_key)?)?;
+
+    Ok(config)
+}
+
+pub fn build_server_config(
+    certificate: &Path,
+    private_key: &Path,
+    client_ca_certificate: Option<&Path>,
+) -> Result<ServerConfig> {
+    let builder = match client_ca_certificate {
+        Some(client_ca_certificate) => {
+            let verifier =
+                WebPkiClientVerifier::builder(Arc::new(load_root_store(client_ca_certificate)?))
+                    .build()?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+
+    let config =
+        builder.with_single_cert(load_certificates(certificate)?, load_private_key(private_key)?)?;
+
+    Ok(config)
+}
+
+//Returns the role stored in the certificate's Modbus role extension, if there is one
+pub fn get_certificate_role(certificate: &CertificateDer) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate.as_ref()).ok()?;
+
+    let extension = certificate
+        .extensions()
+        .iter()
+        .find(|extension| extension.oid.to_id_string() == ROLE_OID)?;
+
+    let (&tag, content) = extension.value.split_first()?;
+
+    if tag != UTF8_STRING_TAG {
+        return None;
+    }
+
+    let (length, role) = parse_der_length(content)?;
+
+    if length != role.len() {
+        return None;
+    }
+
+    String::from_utf8(role.to_vec()).ok()
+}
+
+//Short form lengths are a single byte, long form ones start with 0x80 + the ammount of length bytes
+fn parse_der_length(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let (&first, rest) = bytes.split_first()?;
+
+    if first < 0x80 {
+        return Some((first as usize, rest));
+    }
+
+    let length_size = (first & 0x7F) as usize;
+
+    if length_size == 0 || length_size > std::mem::size_of::<u32>() || rest.len() < length_size {
+        return None;
+    }
+
+    let (length_bytes, rest) = rest.split_at(length_size);
+
+    let length = length_bytes
+        .iter()
+        .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+
+    Some((length, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, CustomExtension, KeyPair};
+
+    fn certificate_with_extension(extension: Option<CustomExtension>) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(vec!["master".to_string()]).unwrap();
+        params.custom_extensions = extension.into_iter().collect();
+
+        let key = KeyPair::generate().unwrap();
+        params.self_signed(&key).unwrap().der().clone()
+    }
+
+    fn role_extension(content: Vec<u8>) -> CustomExtension {
+        CustomExtension::from_oid_content(&[1, 3, 6, 1, 4, 1, 50316, 802, 1], content)
+    }
+
+    #[test]
+    fn role_is_read_from_the_modbus_extension() {
+        let mut role = vec![UTF8_STRING_TAG, 8];
+        role.extend_from_slice(b"operator");
+
+        let certificate = certificate_with_extension(Some(role_extension(role)));
+        assert_eq!(
+            get_certificate_role(&certificate),
+            Some("operator".to_string())
+        );
+    }
+
+    #[test]
+    fn long_roles_use_long_form_lengths() {
+        let long_role = "operator-".repeat(20);
+        let mut role = vec![UTF8_STRING_TAG, 0x81, long_role.len() as u8];
+        role.extend_from_slice(long_role.as_bytes());
+
+        let certificate = certificate_with_extension(Some(role_extension(role)));
+        assert_eq!(get_certificate_role(&certificate), Some(long_role));
+
+        let longer_role = "maintenance-".repeat(30);
+        let mut role = vec![UTF8_STRING_TAG, 0x82];
+        role.extend_from_slice(&(longer_role.len() as u16).to_be_bytes());
+        role.extend_from_slice(longer_role.as_bytes());
+
+        let certificate = certificate_with_extension(Some(role_extension(role)));
+        assert_eq!(get_certificate_role(&certificate), Some(longer_role));
+
+        //Long form length that doesn't match the content
+        let mut role = vec![UTF8_STRING_TAG, 0x81, 200];
+        role.extend_from_slice(b"operator");
+
+        let certificate = certificate_with_extension(Some(role_extension(role)));
+        assert_eq!(get_certificate_role(&certificate), None);
+    }
+
+    #[test]
+    fn certificates_without_a_valid_role_have_none() {
+        assert_eq!(
+            get_certificate_role(&certificate_with_extension(None)),
+            None
+        );
+
+        //Wrong tag and a length that doesn't match the content
+        let printable_string = role_extension(vec![0x13, 2, b'o', b'p']);
+        assert_eq!(
+            get_certificate_role(&certificate_with_extension(Some(printable_string))),
+            None
+        );
+
+        let truncated = role_extension(vec![UTF8_STRING_TAG, 8, b'o', b'p']);
+        assert_eq!(
+            get_certificate_role(&certificate_with_extension(Some(truncated))),
+            None
+        );
+    }
+}
@@ -0,0 +1,9 @@
+use rand::Rng;
+
+//Box-Muller transform, avoids pulling a distributions crate for a single distribution
+pub fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
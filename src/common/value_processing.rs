@@ -1,4 +1,4 @@
-use crate::common::model::{DataType, Value, ValueFormattingParams};
+use crate::common::model::{DataType, StringEncoding, Value, ValueFormattingParams};
 
 use anyhow::{anyhow, Result};
 use tweakable_modbus::ModbusDataType;
@@ -24,7 +24,7 @@ fn extract_bytes_from_registers(registers: &Vec<ModbusDataType>) -> Vec<u8> {
 }
 
 fn build_registers_from_bytes(mut bytes: Vec<u8>) -> Vec<ModbusDataType> {
-    if !bytes.len() % 2 == 0 {
+    if !bytes.len().is_multiple_of(2) {
         bytes.push(0);
     }
 
@@ -35,12 +35,12 @@ fn build_registers_from_bytes(mut bytes: Vec<u8>) -> Vec<ModbusDataType> {
 }
 
 fn apply_endianness(
-    bytes: &Vec<u8>,
+    bytes: &[u8],
     byte_swap: bool,
     word_swap: bool,
     double_word_swap: bool,
 ) -> Vec<u8> {
-    let mut result = bytes.clone();
+    let mut result = bytes.to_vec();
     // Byte swap: intercambia cada par de bytes
     if byte_swap {
         let mut swapped = vec![];
@@ -84,68 +84,66 @@ fn apply_endianness(
     result
 }
 
-fn apply_mask(mut data: Vec<u8>, start_bit: usize, length: usize) -> Vec<u8> {
-    let mut result = vec![];
-    let mut current_byte = 0u8;
-    let mut bit_pos = 0;
+//Swaps from the byte order preset or the legacy booleans
+fn apply_config_endianness(bytes: &[u8], config: &ValueFormattingParams) -> Vec<u8> {
+    let (byte_swap, word_swap, double_word_swap) = config.get_swaps();
+
+    apply_endianness(bytes, byte_swap, word_swap, double_word_swap)
+}
 
-    while data.len() * 8 < length
-    {
-        data.push(0);
-    } 
+//Takes the value bits out of the registers, bit 0 is the LSB of the first register
+fn apply_mask(data: Vec<u8>, start_bit: usize, length: usize) -> Vec<u8> {
+    let mut result = vec![0u8; length.div_ceil(8)];
 
     for i in 0..length {
         let absolute_bit = start_bit + i;
-        let byte_index = absolute_bit / 8;
-        let bit_index = 7 - (absolute_bit % 8); // bits van de MSB a LSB
 
-        if byte_index >= data.len() {
-            break; // fuera de rango
+        if absolute_bit / 8 >= data.len() {
+            break;
         }
 
-        let bit = (data[byte_index] >> bit_index) & 1;
-        current_byte = (current_byte << 1) | bit;
-        bit_pos += 1;
+        let bit = (data[absolute_bit / 8] >> (absolute_bit % 8)) & 1;
 
-        if bit_pos == 8 {
-            result.push(current_byte);
-            current_byte = 0;
-            bit_pos = 0;
-        }
-    }
-
-    // Si quedan bits pendientes (menos de 8)
-    if bit_pos > 0 {
-        current_byte <<= 8 - bit_pos; // rellena con ceros al final
-        result.push(current_byte);
+        result[i / 8] |= bit << (i % 8);
     }
 
     result
 }
 
-fn move_to_mask_position(data: Vec<u8>, start_bit: usize, length: usize)  -> Vec<u8> {
-    let mut result = vec![];
+//Fills the value bytes up to its data type, values narrower than their type keep their sign
+fn extend_to_data_type(mut data: Vec<u8>, config: &ValueFormattingParams) -> Vec<u8> {
+    let type_bits = config.data_type.byte_size() * 8;
+    let length = config.bit_length as usize;
+
+    if data.len() * 8 < type_bits {
+        data.resize(config.data_type.byte_size(), 0);
+    }
 
-    let full_bytes_to_append = start_bit / 8;
+    if config.data_type.is_signed() && length < type_bits {
+        let negative = (data[(length - 1) / 8] >> ((length - 1) % 8)) & 1 == 1;
 
-    for _i in 0..full_bytes_to_append {
-        result.push(0);
+        if negative {
+            for bit in length..type_bits {
+                data[bit / 8] |= 1 << (bit % 8);
+            }
+        }
     }
 
-    let mut aux_byte = 0;
-    let mut bit_index = start_bit % 8;
+    data
+}
 
-    for i in 0..length {
-        let bit = (data[i / 8] >> i % 8) & 1;
-        aux_byte = aux_byte | bit << bit_index;
+fn move_to_mask_position(data: Vec<u8>, start_bit: usize, length: usize) -> Vec<u8> {
+    let mut result = vec![0u8; (start_bit + length).div_ceil(8)];
 
-        bit_index += 1;
-        // Last bit
-        if i % 8 == 7 {
-            result.push(aux_byte);
-            aux_byte = 0;
-            bit_index = 0;
+    for i in 0..length {
+        if i / 8 >= data.len() {
+            break;
         }
+
+        let bit = (data[i / 8] >> (i % 8)) & 1;
+        let absolute_bit = start_bit + i;
+
+        result[absolute_bit / 8] |= bit << (absolute_bit % 8);
     }
 
     result
@@ -157,12 +155,12 @@ pub fn registers_to_bytes(
 ) -> Vec<u8> {
     let bytes = extract_bytes_from_registers(&registers);
 
-    let mut bytes = apply_endianness(
-        &bytes,
-        config.byte_swap,
-        config.word_swap,
-        config.double_word_swap,
-    );
+    let mut bytes = apply_config_endianness(&bytes, config);
+
+    if let DataType::String { .. } = config.data_type {
+        //Characters go from the high byte of each register
+        return apply_endianness(&bytes, true, false, false);
+    }
 
     if config.data_type != DataType::Boolean {
         bytes = apply_mask(
@@ -170,11 +168,33 @@ pub fn registers_to_bytes(
             config.starting_bit as usize,
             config.bit_length as usize,
         );
+        bytes = extend_to_data_type(bytes, config);
     }
 
     return bytes;
 }
 
+//Inverse of registers_to_bytes: the value bits go to their position and then the registers get swapped
+fn build_registers_from_value_bytes(
+    value: Vec<u8>,
+    config: &ValueFormattingParams,
+) -> Vec<ModbusDataType> {
+    let mut bytes = move_to_mask_position(
+        value,
+        config.starting_bit as usize,
+        config.bit_length as usize,
+    );
+
+    //Swaps work on whole registers
+    if !bytes.len().is_multiple_of(2) {
+        bytes.push(0);
+    }
+
+    let bytes = apply_config_endianness(&bytes, config);
+
+    build_registers_from_bytes(bytes)
+}
+
 pub fn value_to_bytes(value: Value) -> Vec<u8> {
     match value {
         Value::Integer(integer) => integer.to_le_bytes().to_vec(),
@@ -182,12 +202,107 @@ pub fn value_to_bytes(value: Value) -> Vec<u8> {
         Value::Boolean(boolean) => {
             vec![boolean as u8]
         }
+        Value::Text(text) => text.into_bytes(),
     }
 }
 
+fn decode_bcd(raw: u64, digits: u32) -> Result<i128> {
+    let mut result = 0;
+
+    for digit in (0..digits).rev() {
+        let nibble = (raw >> (digit * 4)) & 0xF;
+
+        if nibble > 9 {
+            return Err(anyhow!("{:#x} is not a valid BCD value", raw));
+        }
+
+        result = result * 10 + nibble as i128;
+    }
+
+    Ok(result)
+}
+
+fn encode_bcd(value: i128, digits: u32) -> Result<i128> {
+    if value < 0 || value >= 10i128.pow(digits) {
+        return Err(anyhow!("{} doesn't fit in {} BCD digits", value, digits));
+    }
+
+    let mut result = 0;
+    let mut remaining = value;
+
+    for digit in 0..digits {
+        result |= (remaining % 10) << (digit * 4);
+        remaining /= 10;
+    }
+
+    Ok(result)
+}
+
+fn decode_string(raw_value: &[u8], length: u16, encoding: StringEncoding) -> String {
+    let bytes = &raw_value[..raw_value.len().min(length as usize)];
+
+    let text = match encoding {
+        StringEncoding::Ascii => bytes
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii() {
+                    *byte as char
+                } else {
+                    char::REPLACEMENT_CHARACTER
+                }
+            })
+            .collect(),
+        StringEncoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+        StringEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+    };
+
+    text.trim_end_matches(['\0', ' ']).to_string()
+}
+
+//Padded with NULs up to the registers the string takes
+fn encode_string(text: &str, length: u16, encoding: StringEncoding) -> Result<Vec<u8>> {
+    let mut bytes = match encoding {
+        StringEncoding::Ascii => {
+            if !text.is_ascii() {
+                return Err(anyhow!("{} is not an ASCII string", text));
+            }
+            text.as_bytes().to_vec()
+        }
+        StringEncoding::Latin1 => text
+            .chars()
+            .map(|character| {
+                u8::try_from(character)
+                    .map_err(|_| anyhow!("{} can't be encoded as Latin-1", character))
+            })
+            .collect::<Result<Vec<u8>>>()?,
+        StringEncoding::Utf8 => text.as_bytes().to_vec(),
+    };
+
+    if bytes.len() > length as usize {
+        return Err(anyhow!(
+            "{} is {} bytes long, the value holds {}",
+            text,
+            bytes.len(),
+            length
+        ));
+    }
+
+    bytes.resize((length as usize).div_ceil(2) * 2, 0);
+
+    Ok(bytes)
+}
+
+//Takes engineering values, they are turned back into raw values before encoding them
 pub fn value_to_registers(
     value: Value,
     config: &ValueFormattingParams,
+) -> Result<Vec<ModbusDataType>> {
+    raw_value_to_registers(from_engineering(value, config)?, config)
+}
+
+pub fn raw_value_to_registers(
+    value: Value,
+    config: &ValueFormattingParams,
 ) -> Result<Vec<ModbusDataType>> {
     let mut result = vec![];
     match config.data_type {
@@ -200,14 +315,7 @@ pub fn value_to_registers(
         }
         DataType::Double => {
             if let Value::FloatingPoint(value) = value {
-                let bytes = value.to_le_bytes().to_vec();
-                let bytes = apply_endianness(
-                    &bytes,
-                    config.byte_swap,
-                    config.word_swap,
-                    config.double_word_swap,
-                );
-                result = build_registers_from_bytes(bytes);
+                result = build_registers_from_value_bytes(value.to_le_bytes().to_vec(), config);
             }
             else {
                 return Err(anyhow!("Expected floating point value but found otherwise"));
@@ -216,29 +324,34 @@ pub fn value_to_registers(
         DataType::Float => {
             if let Value::FloatingPoint(value) = value {
                 let value = value as f32;
-                let bytes = value.to_le_bytes().to_vec();
-                let bytes = apply_endianness(
-                    &bytes,
-                    config.byte_swap,
-                    config.word_swap,
-                    config.double_word_swap,
-                );
-                result = build_registers_from_bytes(bytes);
+                result = build_registers_from_value_bytes(value.to_le_bytes().to_vec(), config);
             }
             else {
                 return Err(anyhow!("Expected floating point value but found otherwise"));
             }
         }
+        DataType::String { length, encoding } => {
+            if let Value::Text(text) = value {
+                let bytes = encode_string(&text, length, encoding)?;
+                let bytes = apply_endianness(&bytes, true, false, false);
+                let bytes = apply_config_endianness(&bytes, config);
+                result = build_registers_from_bytes(bytes);
+            }
+            else {
+                return Err(anyhow!("Expected text value but found otherwise"));
+            }
+        }
         _ => {
             if let Value::Integer(value) = value {
+                let value = match config.data_type.get_bcd_digits() {
+                    Some(digits) => encode_bcd(value, digits)?,
+                    None => value,
+                };
+
                 let value = value.to_le_bytes().to_vec();
                 let value = value[..config.data_type.byte_size()].to_vec();
 
-                let value = apply_endianness(&value, config.byte_swap, config.word_swap, config.double_word_swap);
-
-                let value = move_to_mask_position(value, config.starting_bit as usize, config.bit_length as usize);
-
-                result = build_registers_from_bytes(value)
+                result = build_registers_from_value_bytes(value, config);
             }
             else {
                 return Err(anyhow!("Expected integer value but found otherwise"));
@@ -249,7 +362,43 @@ pub fn value_to_registers(
     return Ok(result);
 }
 
+//Writes the value bits over the current registers, keeping the bits outside of the value untouched
+pub fn merge_value_into_registers(
+    current_registers: Vec<ModbusDataType>,
+    value: Value,
+    config: &ValueFormattingParams,
+) -> Result<Vec<ModbusDataType>> {
+    let value_registers = value_to_registers(value, config)?;
+
+    let mask = vec![0xFF; (config.bit_length as usize).div_ceil(8)];
+    let mask = build_registers_from_value_bytes(mask, config);
+
+    let mut result = vec![];
+
+    for (i, current) in current_registers.into_iter().enumerate() {
+        let (current, value, mask) = match (current, value_registers.get(i), mask.get(i)) {
+            (
+                ModbusDataType::Register(current),
+                Some(ModbusDataType::Register(value)),
+                Some(ModbusDataType::Register(mask)),
+            ) => (current, *value, *mask),
+            (current, _, _) => {
+                result.push(current);
+                continue;
+            }
+        };
+
+        result.push(ModbusDataType::Register((current & !mask) | (value & mask)));
+    }
+
+    Ok(result)
+}
+
 pub fn format_value(raw_value: Vec<u8>, data_type: &DataType) -> Result<Value> {
+    if let DataType::String { length, encoding } = data_type {
+        return Ok(Value::Text(decode_string(&raw_value, *length, *encoding)));
+    }
+
     if raw_value.is_empty() {
         return Err(anyhow!("Value is empty"));
     }
@@ -261,9 +410,13 @@ pub fn format_value(raw_value: Vec<u8>, data_type: &DataType) -> Result<Value> {
 
             Ok(Value::Boolean(raw_value[0] != 0))
         }
+        //Polled floats keep their 4 bytes, aggregates are stored as doubles
+        DataType::Float if raw_value.len() == 4 => Ok(Value::FloatingPoint(
+            f32::from_le_bytes(raw_value.try_into().unwrap()) as f64,
+        )),
         DataType::Float | DataType::Double => {
             if raw_value.len() != 8 as usize {
-                return Err(anyhow!("Double values must be 8 bytes long"));
+                return Err(anyhow!("Floating point values must be 4 or 8 bytes long"));
             }
 
             Ok(Value::FloatingPoint(f64::from_le_bytes(
@@ -276,6 +429,12 @@ pub fn format_value(raw_value: Vec<u8>, data_type: &DataType) -> Result<Value> {
 
             Ok(Value::Integer(byte_value as i128))
         }
+        DataType::SignedByte => {
+            let significant_bytes = &raw_value[..1];
+            let signed_byte_value = i8::from_le_bytes(significant_bytes.try_into().unwrap());
+
+            Ok(Value::Integer(signed_byte_value as i128))
+        }
         DataType::SignedInteger16 => {
             let significant_bytes = &raw_value[..2];
             let signed_16_value = i16::from_le_bytes(significant_bytes.try_into().unwrap());
@@ -312,5 +471,117 @@ pub fn format_value(raw_value: Vec<u8>, data_type: &DataType) -> Result<Value> {
 
             Ok(Value::Integer(unsigned_64_value as i128))
         }
+        DataType::Bcd16 => {
+            let significant_bytes = &raw_value[..2];
+            let bcd_value = u16::from_le_bytes(significant_bytes.try_into().unwrap());
+
+            Ok(Value::Integer(decode_bcd(bcd_value as u64, 4)?))
+        }
+        DataType::Bcd32 => {
+            let significant_bytes = &raw_value[..4];
+            let bcd_value = u32::from_le_bytes(significant_bytes.try_into().unwrap());
+
+            Ok(Value::Integer(decode_bcd(bcd_value as u64, 8)?))
+        }
+        DataType::String { .. } => unreachable!(),
+    }
+}
+
+fn round_to_decimals(value: f64, decimals: Option<u8>) -> f64 {
+    match decimals {
+        Some(decimals) => {
+            let factor = 10f64.powi(decimals as i32);
+            (value * factor).round() / factor
+        }
+        None => value,
+    }
+}
+
+//Raw value as read from the registers to what it means, scaled values are always floating point
+pub fn to_engineering(value: Value, config: &ValueFormattingParams) -> Value {
+    if let Value::Boolean(_) | Value::Text(_) = value {
+        return value;
+    }
+
+    match (value, config.get_scaling()) {
+        (value, Some((gain, offset))) => {
+            Value::FloatingPoint(round_to_decimals(value.as_f64() * gain + offset, config.decimals))
+        }
+        (Value::FloatingPoint(floating), None) => {
+            Value::FloatingPoint(round_to_decimals(floating, config.decimals))
+        }
+        (value, None) => value,
+    }
+}
+
+//Inverse of to_engineering, the raw value gets the variant its data type expects.
+//Integers that don't fit in the value bits are rejected instead of being cut when encoded
+pub fn from_engineering(value: Value, config: &ValueFormattingParams) -> Result<Value> {
+    let raw_value = match config.get_scaling() {
+        Some(scaling) => scale_to_raw(value, scaling, &config.data_type)?,
+        None => value,
+    };
+
+    if let (Value::Integer(integer), Some((min, max))) = (&raw_value, config.get_integer_range()) {
+        if *integer < min || *integer > max {
+            return Err(anyhow!(
+                "{} doesn't fit in the value, it must be between {} and {}",
+                integer,
+                min,
+                max
+            ));
+        }
+    }
+
+    Ok(raw_value)
+}
+
+fn scale_to_raw(value: Value, (gain, offset): (f64, f64), data_type: &DataType) -> Result<Value> {
+    if let Value::Boolean(_) | Value::Text(_) = value {
+        return Err(anyhow!("Expected numeric value but found otherwise"));
+    }
+
+    let raw = (value.as_f64() - offset) / gain;
+
+    if !raw.is_finite() {
+        return Err(anyhow!("{} can't be turned into a raw value", value.as_f64()));
+    }
+
+    match data_type {
+        DataType::Float | DataType::Double => Ok(Value::FloatingPoint(raw)),
+        _ => Ok(Value::Integer(raw.round() as i128)),
+    }
+}
+
+//Raw bytes from registers_to_bytes, as stored by the master, to an engineering value
+pub fn bytes_to_value(raw_value: Vec<u8>, config: &ValueFormattingParams) -> Result<Value> {
+    Ok(to_engineering(
+        format_value(raw_value, &config.data_type)?,
+        config,
+    ))
+}
+
+//Aggregates are stored with value_to_bytes, so they hold values that are already decoded
+pub fn format_aggregate_value(raw_value: Vec<u8>, config: &ValueFormattingParams) -> Result<Value> {
+    //Texts were decoded before aggregating them, they're stored as UTF-8 whatever the registers use
+    if let DataType::String { .. } = config.data_type {
+        return Ok(Value::Text(String::from_utf8(raw_value)?));
     }
+
+    let data_type = if config.get_scaling().is_some() {
+        DataType::Double
+    } else if config.data_type.get_bcd_digits().is_some() {
+        DataType::UnsignedInteger64
+    } else {
+        config.data_type.clone()
+    };
+
+    format_value(raw_value, &data_type)
+}
+
+pub fn registers_to_value(
+    registers: Vec<ModbusDataType>,
+    config: &ValueFormattingParams,
+) -> Result<Value> {
+    bytes_to_value(registers_to_bytes(registers, config), config)
 }
@@ -1,6 +1,7 @@
 use clap::{Parser};
 
 use modbus_watch::client::comm::ModbusWatcher;
+use modbus_watch::client::data::DbBatchParams;
 use modbus_watch::client::model::PolledConnection;
 use modbus_watch::common::logging::{init_logger, LogLevel};
 
@@ -18,6 +19,14 @@ struct Args {
     log_file: String,
     #[arg(long = "api-port", default_value = "8000")]
     api_port: u16,
+    #[arg(long = "db-batch-size", default_value = "500")]
+    db_batch_size: usize,
+    #[arg(long = "db-batch-delay-ms", default_value = "200")]
+    db_batch_delay_ms: u64,
+    #[arg(long = "db-channel-size", default_value = "16384")]
+    db_channel_size: usize,
+    #[arg(long = "db-migrate-only")]
+    db_migrate_only: bool,
 }
 
 #[tokio::main]
@@ -31,6 +40,19 @@ async fn main() {
         None
     };
 
+    if args.db_migrate_only {
+        match modbus_watch::client::data::DbManager::migrate_only(args.db_file) {
+            Ok((previous_version, version)) => {
+                info!("Database migrated from version {} to {}", previous_version, version);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                error!("Couldn't migrate db: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let config = std::fs::read_to_string(&args.config_file).unwrap_or_else(|e| {
         error!("Couldn't read config file: {}", e);
         std::process::exit(1);
@@ -48,15 +70,23 @@ async fn main() {
         }
     }
 
-    let (tx, rx) = mpsc::channel::<modbus_watch::client::data::InsertValueMessage>(1024);
+    let (tx, rx) =
+        mpsc::channel::<modbus_watch::client::data::InsertValueMessage>(args.db_channel_size);
+
+    let batch_params = DbBatchParams {
+        max_batch_size: args.db_batch_size.max(1),
+        max_batch_delay: std::time::Duration::from_millis(args.db_batch_delay_ms),
+    };
 
-    let mut db = modbus_watch::client::data::DbManager::new(args.db_file, &config, rx)
+    let mut db = modbus_watch::client::data::DbManager::new(args.db_file, &config, rx, batch_params)
         .unwrap_or_else(|e| {
             error!("Couldn't init db: {}", e);
             std::process::exit(1);
         });
 
     let api_db_access = db.get_db();
+    let db_metrics = db.get_metrics();
+    let poll_stream = db.get_stream();
     let aggregation_db_access = db.get_db();
 
     tokio::spawn(async move {
@@ -70,7 +100,19 @@ async fn main() {
         std::process::exit(1);
     });
 
-    modbus_watch::client::api::serve_api(config.clone(), api_db_access, args.api_port).await;
+    let modbus_writer = modbus_watcher.get_writer();
+    let connections_health = modbus_watcher.get_health();
+
+    modbus_watch::client::api::serve_api(
+        config.clone(),
+        api_db_access,
+        modbus_writer,
+        connections_health,
+        db_metrics,
+        poll_stream,
+        args.api_port,
+    )
+    .await;
 
     modbus_watch::client::aggregations::start_aggregation_building(aggregation_db_access, config)
         .await;
@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::server::{api::value::ValueApiState, state::audit::WriteRecord};
+
+#[derive(Deserialize)]
+pub struct WritesParams {
+    //Milliseconds since epoch, only writes after it are returned
+    pub since: Option<u64>,
+}
+
+pub async fn get_writes(
+    State(state): State<ValueApiState>,
+    Query(params): Query<WritesParams>,
+) -> Json<Vec<WriteRecord>> {
+    Json(state.writes.lock().await.get_writes(params.since))
+}
+
+pub async fn get_value_writes(
+    State(state): State<ValueApiState>,
+    Path(id): Path<String>,
+    Query(params): Query<WritesParams>,
+) -> Result<Json<Vec<WriteRecord>>, Response> {
+    if !state.app_state.lock().await.contains_key(&id) {
+        return Err((StatusCode::NOT_FOUND, "Value not defined").into_response());
+    }
+
+    Ok(Json(
+        state
+            .writes
+            .lock()
+            .await
+            .get_value_writes(&id, params.since),
+    ))
+}
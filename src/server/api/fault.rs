@@ -0,0 +1,126 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::server::{
+    model::fault::FaultRule,
+    state::{AppState, SlaveFaults},
+};
+
+#[derive(Clone)]
+pub struct FaultApiState {
+    pub app_state: AppState,
+    pub faults: SlaveFaults,
+}
+
+pub async fn list_faults(
+    State(state): State<FaultApiState>,
+    Path(id): Path<u8>,
+) -> Result<Json<Vec<FaultRule>>, Response> {
+    let faults = state.faults.lock().await;
+
+    match faults.get(&id) {
+        Some(rules) => Ok(Json(rules.clone())),
+        None => Err((StatusCode::NOT_FOUND, "Slave not defined").into_response()),
+    }
+}
+
+//Creates the rule or replaces the one with the same id
+pub async fn set_fault(
+    State(state): State<FaultApiState>,
+    Path((id, fault_id)): Path<(u8, String)>,
+    Json(mut rule): Json<FaultRule>,
+) -> Result<Json<FaultRule>, Response> {
+    rule.id = fault_id;
+
+    if let Err(err) = rule.validate() {
+        return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
+
+    if let Some(value_id) = &rule.value {
+        let app_state = state.app_state.lock().await;
+
+        let in_slave = app_state
+            .get(value_id)
+            .is_some_and(|value| value.starting_address.slave_id == id);
+
+        if !in_slave {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Value {} is not in slave {}", value_id, id),
+            )
+                .into_response());
+        }
+    }
+
+    let mut faults = state.faults.lock().await;
+
+    let Some(rules) = faults.get_mut(&id) else {
+        return Err((StatusCode::NOT_FOUND, "Slave not defined").into_response());
+    };
+
+    match rules.iter_mut().find(|existing| existing.id == rule.id) {
+        Some(existing) => *existing = rule.clone(),
+        None => rules.push(rule.clone()),
+    }
+
+    Ok(Json(rule))
+}
+
+pub async fn delete_fault(
+    State(state): State<FaultApiState>,
+    Path((id, fault_id)): Path<(u8, String)>,
+) -> Result<StatusCode, Response> {
+    let mut faults = state.faults.lock().await;
+
+    let Some(rules) = faults.get_mut(&id) else {
+        return Err((StatusCode::NOT_FOUND, "Slave not defined").into_response());
+    };
+
+    let previous_len = rules.len();
+    rules.retain(|rule| rule.id != fault_id);
+
+    if rules.len() == previous_len {
+        return Err((StatusCode::NOT_FOUND, "Fault not defined").into_response());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn arm_fault(
+    State(state): State<FaultApiState>,
+    Path((id, fault_id)): Path<(u8, String)>,
+) -> Result<Json<FaultRule>, Response> {
+    set_armed(state, id, fault_id, true).await
+}
+
+pub async fn disarm_fault(
+    State(state): State<FaultApiState>,
+    Path((id, fault_id)): Path<(u8, String)>,
+) -> Result<Json<FaultRule>, Response> {
+    set_armed(state, id, fault_id, false).await
+}
+
+async fn set_armed(
+    state: FaultApiState,
+    id: u8,
+    fault_id: String,
+    armed: bool,
+) -> Result<Json<FaultRule>, Response> {
+    let mut faults = state.faults.lock().await;
+
+    let rule = faults
+        .get_mut(&id)
+        .and_then(|rules| rules.iter_mut().find(|rule| rule.id == fault_id));
+
+    match rule {
+        Some(rule) => {
+            rule.armed = armed;
+            Ok(Json(rule.clone()))
+        }
+        None => Err((StatusCode::NOT_FOUND, "Fault not defined").into_response()),
+    }
+}
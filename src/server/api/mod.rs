@@ -1,19 +1,98 @@
 use std::net::SocketAddr;
 
-use axum::{routing::get, Router};
+use axum::{
+    routing::{get, post, put},
+    Router,
+};
 
-use crate::server::state::AppState;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
+use crate::server::state::{
+    audit::WriteAudit, scenario::ScenarioRunner, snapshot::NamedSnapshots, AppState, SlaveFaults,
+    SlaveLatencies,
+};
+
+mod audit;
 mod common;
 mod config;
+mod fault;
+mod register;
+mod scenario;
+mod slave;
+mod snapshot;
 mod value;
 
-pub async fn serve_api(app_state: AppState, port: u16) {
-    let api_v1 = Router::new()
-        .route("/values", get(common::list_values))
+pub async fn serve_api(
+    app_state: AppState,
+    latencies: SlaveLatencies,
+    faults: SlaveFaults,
+    scenario_runner: Option<Arc<Mutex<ScenarioRunner>>>,
+    snapshots: NamedSnapshots,
+    writes: WriteAudit,
+    port: u16,
+) {
+    let slaves = Router::new()
+        .route(
+            "/slaves/{id}/latency",
+            get(slave::get_latency).put(slave::set_latency),
+        )
+        .with_state(latencies);
+
+    let faults = Router::new()
+        .route("/slaves/{id}/faults", get(fault::list_faults))
+        .route(
+            "/slaves/{id}/faults/{fault}",
+            put(fault::set_fault).delete(fault::delete_fault),
+        )
+        .route("/slaves/{id}/faults/{fault}/arm", post(fault::arm_fault))
+        .route("/slaves/{id}/faults/{fault}/disarm", post(fault::disarm_fault))
+        .with_state(fault::FaultApiState {
+            app_state: app_state.clone(),
+            faults,
+        });
+
+    let snapshots = Router::new()
+        .route("/snapshot", post(snapshot::take_snapshot))
+        .route("/restore/{name}", post(snapshot::restore_snapshot))
+        .with_state(snapshot::SnapshotApiState {
+            app_state: app_state.clone(),
+            snapshots,
+        });
+
+    let values = Router::new()
         .route("/values/{id}", get(value::get_value).put(value::set_value))
+        .route("/values/{id}/writes", get(audit::get_value_writes))
+        .route("/writes", get(audit::get_writes))
+        .route(
+            "/slaves/{id}/{table}",
+            get(register::get_registers).put(register::set_registers),
+        )
+        .with_state(value::ValueApiState {
+            app_state: app_state.clone(),
+            writes,
+        });
+
+    let mut api_v1 = Router::new()
+        .route("/values", get(common::list_values))
         .route("/values/{id}/config", get(config::get_config))
-        .with_state(app_state.clone());
+        .with_state(app_state.clone())
+        .merge(values)
+        .merge(slaves)
+        .merge(faults)
+        .merge(snapshots);
+
+    //Without a scenario loaded its endpoints answer 404
+    if let Some(scenario_runner) = scenario_runner {
+        let scenario = Router::new()
+            .route("/scenario", get(scenario::get_progress))
+            .route("/scenario/start", post(scenario::start))
+            .route("/scenario/pause", post(scenario::pause))
+            .route("/scenario/rewind", post(scenario::rewind))
+            .with_state(scenario_runner);
+
+        api_v1 = api_v1.merge(scenario);
+    }
 
     let api = Router::new().nest("/api/v1", api_v1);
 
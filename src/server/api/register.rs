@@ -0,0 +1,242 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tweakable_modbus::{ModbusAddress, ModbusDataType};
+
+use crate::{
+    common::model::ModbusTable,
+    server::{
+        api::value::ValueApiState,
+        state::{
+            audit::{WriteRecord, WriteSource},
+            snapshot::StoredRegister,
+            ValueState,
+        },
+    },
+};
+
+#[derive(Deserialize)]
+pub struct RegisterRange {
+    #[serde(default)]
+    pub start: u16,
+    pub count: Option<u16>,
+}
+
+#[derive(Serialize)]
+pub struct RawRegister {
+    pub address: u16,
+    //Both missing when no value is mapped to the address
+    pub value: Option<StoredRegister>,
+    pub value_id: Option<String>,
+}
+
+fn parse_table(table: &str) -> Option<ModbusTable> {
+    match table {
+        "coils" => Some(ModbusTable::Coils),
+        "discrete_inputs" => Some(ModbusTable::DiscreteInput),
+        "input_registers" => Some(ModbusTable::InputRegisters),
+        "holding_registers" => Some(ModbusTable::HoldingRegisters),
+        _ => None,
+    }
+}
+
+fn get_address(slave_id: u8, table: &ModbusTable, address: u16) -> ModbusAddress {
+    ModbusAddress {
+        slave_id,
+        table: table.to_tweakable_modbus_table(),
+        address,
+    }
+}
+
+//Id of the value holding every mapped address of the table
+fn get_table_map(
+    app_state: &HashMap<String, ValueState>,
+    slave_id: u8,
+    table: &ModbusTable,
+) -> Option<HashMap<u16, String>> {
+    let mut map = HashMap::new();
+    let mut slave_found = false;
+
+    for (value_id, value_state) in app_state {
+        if value_state.starting_address.slave_id != slave_id {
+            continue;
+        }
+
+        slave_found = true;
+
+        if value_state.config.table != *table {
+            continue;
+        }
+
+        let starting_address = value_state.starting_address.address;
+
+        for offset in 0..value_state.get_all_registers().len() {
+            map.insert(
+                starting_address.wrapping_add(offset as u16),
+                value_id.clone(),
+            );
+        }
+    }
+
+    slave_found.then_some(map)
+}
+
+fn get_raw_registers(
+    app_state: &HashMap<String, ValueState>,
+    map: &HashMap<u16, String>,
+    slave_id: u8,
+    table: &ModbusTable,
+    addresses: Vec<u16>,
+) -> Vec<RawRegister> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            let value_id = map.get(&address).cloned();
+
+            let value = value_id.as_ref().and_then(|value_id| {
+                app_state[value_id].get_register(get_address(slave_id, table, address))
+            });
+
+            RawRegister {
+                address,
+                value: value.map(StoredRegister::from),
+                value_id,
+            }
+        })
+        .collect()
+}
+
+fn get_range(range: &RegisterRange, default_count: u16) -> Result<Vec<u16>, Response> {
+    let count = range.count.unwrap_or(default_count);
+
+    if count == 0 {
+        return Err((StatusCode::BAD_REQUEST, "Count can't be zero").into_response());
+    }
+
+    if range.start as u32 + count as u32 > u16::MAX as u32 + 1 {
+        return Err((StatusCode::BAD_REQUEST, "Range goes past the last address").into_response());
+    }
+
+    Ok((0..count).map(|offset| range.start + offset).collect())
+}
+
+pub async fn get_registers(
+    State(state): State<ValueApiState>,
+    Path((slave_id, table)): Path<(u8, String)>,
+    Query(range): Query<RegisterRange>,
+) -> Result<Json<Vec<RawRegister>>, Response> {
+    let Some(table) = parse_table(&table) else {
+        return Err((StatusCode::NOT_FOUND, "Table not defined").into_response());
+    };
+
+    let addresses = get_range(&range, 1)?;
+
+    let app_state = state.app_state.lock().await;
+
+    let Some(map) = get_table_map(&app_state, slave_id, &table) else {
+        return Err((StatusCode::NOT_FOUND, "Slave not defined").into_response());
+    };
+
+    Ok(Json(get_raw_registers(&app_state, &map, slave_id, &table, addresses)))
+}
+
+//Sets the registers as they are, without the checks a master write goes through
+pub async fn set_registers(
+    State(state): State<ValueApiState>,
+    Path((slave_id, table)): Path<(u8, String)>,
+    Query(range): Query<RegisterRange>,
+    Json(registers): Json<Vec<StoredRegister>>,
+) -> Result<Json<Vec<RawRegister>>, Response> {
+    let Some(table) = parse_table(&table) else {
+        return Err((StatusCode::NOT_FOUND, "Table not defined").into_response());
+    };
+
+    if registers.is_empty() || registers.len() > u16::MAX as usize {
+        return Err((StatusCode::BAD_REQUEST, "Wrong number of registers").into_response());
+    }
+
+    let addresses = get_range(&range, registers.len() as u16)?;
+
+    if addresses.len() != registers.len() {
+        return Err((StatusCode::BAD_REQUEST, "Count doesn't match the registers").into_response());
+    }
+
+    let bit_table = table.register_size() == 1;
+
+    for register in &registers {
+        let right_type = matches!(
+            (register, bit_table),
+            (StoredRegister::Coil(_), true) | (StoredRegister::Register(_), false)
+        );
+
+        if !right_type {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Coils and discrete inputs take booleans, registers take numbers",
+            )
+                .into_response());
+        }
+    }
+
+    let mut app_state = state.app_state.lock().await;
+
+    let Some(map) = get_table_map(&app_state, slave_id, &table) else {
+        return Err((StatusCode::NOT_FOUND, "Slave not defined").into_response());
+    };
+
+    //Registers written to each value, nothing is written if any address is unmapped
+    let mut value_writes: HashMap<String, Vec<(ModbusAddress, ModbusDataType)>> = HashMap::new();
+
+    for (address, register) in addresses.iter().zip(registers) {
+        let Some(value_id) = map.get(address) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("No value is mapped to address {}", address),
+            )
+                .into_response());
+        };
+
+        value_writes.entry(value_id.clone()).or_default().push((
+            get_address(slave_id, &table, *address),
+            ModbusDataType::from(register),
+        ));
+    }
+
+    let mut records = vec![];
+
+    for (value_id, value_registers) in value_writes {
+        let value_state = app_state.get_mut(&value_id).unwrap();
+
+        let old_registers = value_state.get_all_registers();
+        let new_registers = value_state.get_registers_with(&value_registers);
+
+        value_state.set_all_registers(new_registers);
+
+        records.push(WriteRecord::new(
+            &value_id,
+            WriteSource::Http,
+            table.clone(),
+            value_registers[0].0.address,
+            old_registers,
+            value_state.get_all_registers(),
+            &value_state.config.formatting_params,
+        ));
+    }
+
+    let raw_registers = get_raw_registers(&app_state, &map, slave_id, &table, addresses);
+
+    drop(app_state);
+
+    let mut writes = state.writes.lock().await;
+
+    for record in records {
+        writes.record(record);
+    }
+
+    Ok(Json(raw_registers))
+}
@@ -0,0 +1,29 @@
+use axum::{extract::State, Json};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::server::state::scenario::{ScenarioProgress, ScenarioRunner};
+
+type ScenarioState = Arc<Mutex<ScenarioRunner>>;
+
+pub async fn get_progress(State(runner): State<ScenarioState>) -> Json<ScenarioProgress> {
+    Json(runner.lock().await.get_progress())
+}
+
+pub async fn start(State(runner): State<ScenarioState>) -> Json<ScenarioProgress> {
+    let mut runner = runner.lock().await;
+    runner.start();
+    Json(runner.get_progress())
+}
+
+pub async fn pause(State(runner): State<ScenarioState>) -> Json<ScenarioProgress> {
+    let mut runner = runner.lock().await;
+    runner.pause();
+    Json(runner.get_progress())
+}
+
+pub async fn rewind(State(runner): State<ScenarioState>) -> Json<ScenarioProgress> {
+    let mut runner = runner.lock().await;
+    runner.rewind();
+    Json(runner.get_progress())
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::server::{model::latency::LatencyConfig, state::SlaveLatencies};
+
+pub async fn get_latency(
+    State(latencies): State<SlaveLatencies>,
+    Path(id): Path<u8>,
+) -> Result<Json<Option<LatencyConfig>>, Response> {
+    let latencies = latencies.lock().await;
+
+    match latencies.get(&id) {
+        Some(latency) => Ok(Json(latency.clone())),
+        None => Err((StatusCode::NOT_FOUND, "Slave not defined").into_response()),
+    }
+}
+
+pub async fn set_latency(
+    State(latencies): State<SlaveLatencies>,
+    Path(id): Path<u8>,
+    Json(latency): Json<Option<LatencyConfig>>,
+) -> Result<Json<Option<LatencyConfig>>, Response> {
+    if let Some(latency) = &latency {
+        if let Err(err) = latency.validate() {
+            return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+        }
+    }
+
+    let mut latencies = latencies.lock().await;
+
+    if !latencies.contains_key(&id) {
+        return Err((StatusCode::NOT_FOUND, "Slave not defined").into_response());
+    }
+
+    latencies.insert(id, latency.clone());
+
+    Ok(Json(latency))
+}
@@ -0,0 +1,67 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::server::state::{
+    snapshot::{NamedSnapshots, RestoreSummary, StateSnapshot},
+    AppState,
+};
+
+#[derive(Clone)]
+pub struct SnapshotApiState {
+    pub app_state: AppState,
+    pub snapshots: NamedSnapshots,
+}
+
+#[derive(Deserialize)]
+pub struct SnapshotRequest {
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct SnapshotSummary {
+    pub name: String,
+    pub secs_since_epoch: u64,
+    pub values: usize,
+}
+
+//Taking a snapshot with an existing name replaces it
+pub async fn take_snapshot(
+    State(state): State<SnapshotApiState>,
+    Json(request): Json<SnapshotRequest>,
+) -> Result<Json<SnapshotSummary>, Response> {
+    if request.name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Snapshot name can't be empty").into_response());
+    }
+
+    let snapshot = StateSnapshot::take(&*state.app_state.lock().await);
+
+    let summary = SnapshotSummary {
+        name: request.name.clone(),
+        secs_since_epoch: snapshot.secs_since_epoch,
+        values: snapshot.values.len(),
+    };
+
+    state.snapshots.lock().await.insert(request.name, snapshot);
+
+    Ok(Json(summary))
+}
+
+pub async fn restore_snapshot(
+    State(state): State<SnapshotApiState>,
+    Path(name): Path<String>,
+) -> Result<Json<RestoreSummary>, Response> {
+    let snapshots = state.snapshots.lock().await;
+
+    let Some(snapshot) = snapshots.get(&name) else {
+        return Err((StatusCode::NOT_FOUND, "Snapshot not defined").into_response());
+    };
+
+    let summary = snapshot.restore(&mut *state.app_state.lock().await);
+
+    Ok(Json(summary))
+}
@@ -4,17 +4,36 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use serde::Deserialize;
 
 use crate::{
     common::{model::Value, value_processing},
-    server::state::AppState,
+    server::state::{
+        audit::{WriteAudit, WriteRecord, WriteSource},
+        AppState,
+    },
 };
 
+#[derive(Clone)]
+pub struct ValueApiState {
+    pub app_state: AppState,
+    pub writes: WriteAudit,
+}
+
+//Enumerated values can also be written by their number or state label
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ValueInput {
+    Value(Value),
+    Number(i64),
+    Label(String),
+}
+
 pub async fn get_value(
-    State(state): State<AppState>,
+    State(state): State<ValueApiState>,
     Path(id): Path<String>,
 ) -> Result<Json<Value>, Response> {
-    let state = state.lock().await;
+    let state = state.app_state.lock().await;
 
     if !state.contains_key(&id) {
         return Err((StatusCode::NOT_FOUND, "Value not defined").into_response());
@@ -22,12 +41,9 @@ pub async fn get_value(
 
     let value_ref = state.get(&id).unwrap();
 
-    let value = value_processing::format_value(
-        value_processing::registers_to_bytes(
-            value_ref.get_all_registers(),
-            &value_ref.config.formatting_params,
-        ),
-        &value_ref.config.formatting_params.data_type,
+    let value = value_processing::registers_to_value(
+        value_ref.get_all_registers(),
+        &value_ref.config.formatting_params,
     );
 
     if value.is_err() {
@@ -40,22 +56,68 @@ pub async fn get_value(
 }
 
 pub async fn set_value(
-    State(state): State<AppState>,
+    State(state): State<ValueApiState>,
     Path(id): Path<String>,
-    Json(value): Json<Value>,
+    Json(value): Json<ValueInput>,
 ) -> Result<Json<Value>, Response> {
-    let mut state = state.lock().await;
+    let mut app_state = state.app_state.lock().await;
 
-    if !state.contains_key(&id) {
+    if !app_state.contains_key(&id) {
         return Err((StatusCode::NOT_FOUND, "Not found").into_response());
     }
 
-    let value_ref = state.get_mut(&id).unwrap();
+    let value_ref = app_state.get_mut(&id).unwrap();
+
+    let formatting_params = &value_ref.config.formatting_params;
+
+    let value = match value {
+        ValueInput::Value(Value::Text(label)) | ValueInput::Label(label)
+            if formatting_params.states.is_some() =>
+        {
+            match formatting_params.get_state_value(&label) {
+                Some(number) => Value::Integer(number as i128),
+                None => {
+                    return Err(
+                        (StatusCode::BAD_REQUEST, format!("Unknown state {}", label))
+                            .into_response(),
+                    );
+                }
+            }
+        }
+        ValueInput::Value(value) => value,
+        ValueInput::Number(number) => Value::Integer(number as i128),
+        ValueInput::Label(label) => Value::Text(label),
+    };
+
+    if let Err(err) = value_ref.config.check_value(&value) {
+        return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
 
     let value_registers =
-        value_processing::value_to_registers(value, &value_ref.config.formatting_params).unwrap();
+        match value_processing::value_to_registers(value.clone(), &value_ref.config.formatting_params) {
+            Ok(value_registers) => value_registers,
+            Err(err) => {
+                return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+            }
+        };
+
+    let old_registers = value_ref.get_all_registers();
 
     value_ref.set_all_registers(value_registers);
 
+    let record = WriteRecord::new(
+        &id,
+        WriteSource::Http,
+        value_ref.config.table.clone(),
+        value_ref.config.starting_address,
+        old_registers,
+        value_ref.get_all_registers(),
+        &value_ref.config.formatting_params,
+    );
+
+    drop(app_state);
+
+    state.writes.lock().await.record(record);
+
     Ok(Json(value))
 }
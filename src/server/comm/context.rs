@@ -1,85 +1,474 @@
+use std::collections::HashSet;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::{collections::HashMap, net::SocketAddr};
-use tweakable_modbus::{
-    ExceptionCode, ModbusAddress, ModbusDataType, ModbusSlaveConnectionParameters,
-};
-
-use crate::server::model::connection::ServedConnectionConfig;
+use rand::Rng;
+use tracing::{debug, error};
+use tweakable_modbus::{ExceptionCode, ModbusAddress, ModbusDataType};
+
+use crate::common::model::Transport;
+use crate::common::value_processing;
+use crate::common::protocol::pdu::{self, Request, Response};
+use crate::server::comm::{rtu, tcp, tls, udp};
+use crate::server::model::connection::{ServedConnectionConfig, ServedTlsConfig};
+use crate::server::model::fault::{FaultAction, FaultRule};
 use crate::{
     common::model::{ModbusTable, ValueFormattingParams},
-    server::{model::ServedConnection, state::AppState},
+    server::{
+        model::ServedConnection,
+        state::{
+            audit::{WriteAudit, WriteRecord, WriteSource},
+            AppState, SlaveFaults, SlaveLatencies,
+        },
+    },
 };
 
 type AddressBindings = HashMap<ModbusAddress, String>;
 
-struct ModbusSlaveCallback {
+//What the transport has to send back, faults on the frame itself are applied by each transport
+pub enum SlaveReply {
+    Respond(Vec<u8>),
+    //Transports without transaction id answer normally
+    WrongTransactionId(Vec<u8>),
+    WrongUnitId(Vec<u8>),
+    NoResponse,
+    CloseConnection,
+}
+
+//Who sent the request, the peer is missing on serial lines
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub peer: Option<SocketAddr>,
+    //Role from the master's TLS certificate
+    pub role: Option<String>,
+}
+
+pub struct ModbusSlaveCallback {
     app_state: AppState,
-    bindings: AddressBindings
+    bindings: AddressBindings,
+    latencies: SlaveLatencies,
+    faults: SlaveFaults,
+    writes: WriteAudit,
 }
 
 impl ModbusSlaveCallback {
-    pub fn new(app_state: AppState, bindings: AddressBindings) -> Self {
-        Self {app_state, bindings}
+    pub fn new(
+        app_state: AppState,
+        bindings: AddressBindings,
+        latencies: SlaveLatencies,
+        faults: SlaveFaults,
+        writes: WriteAudit,
+    ) -> Self {
+        Self {app_state, bindings, latencies, faults, writes}
     }
-}
 
-#[async_trait::async_trait]
-#[allow(dead_code)]
-impl tweakable_modbus::ModbusCallBack for ModbusSlaveCallback {
-    async fn on_read(&self, address: ModbusAddress) -> Result<ModbusDataType, ExceptionCode>
-    {
-        if !self.bindings.contains_key(&address) {
-            return Err(ExceptionCode::IllegalDataAddress);
+    fn fault_matches(&self, slave_id: u8, rule: &FaultRule, request: &Request) -> bool {
+        let first_address = request.address();
+        let last_address = first_address.saturating_add(request.ammount().saturating_sub(1));
+
+        if let Some(table) = &rule.table {
+            if table.to_tweakable_modbus_table() != request.table() {
+                return false;
+            }
+        }
+
+        if let Some(addresses) = &rule.addresses {
+            if addresses.start > last_address || addresses.end < first_address {
+                return false;
+            }
+        }
+
+        if let Some(value_id) = &rule.value {
+            let touches_value = (first_address..=last_address).any(|address| {
+                let address = ModbusAddress {
+                    slave_id,
+                    table: request.table(),
+                    address,
+                };
+
+                self.bindings.get(&address) == Some(value_id)
+            });
+
+            if !touches_value {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    //First armed rule matching the request that passes its probability roll
+    async fn get_triggered_fault(&self, slave_id: u8, request: &Request) -> Option<FaultAction> {
+        let mut faults = self.faults.lock().await;
+
+        let rules = faults.get_mut(&slave_id)?;
+
+        for rule in rules.iter_mut() {
+            if !rule.armed || !self.fault_matches(slave_id, rule, request) {
+                continue;
+            }
+
+            if rule.probability < 1.0 && rand::random::<f64>() >= rule.probability {
+                continue;
+            }
+
+            if rule.one_shot {
+                rule.armed = false;
+            }
+
+            debug!("Fault {} triggered on slave {}", rule.id, slave_id);
+
+            return Some(rule.action.clone());
         }
 
-        let value_id = self.bindings.get(&address).unwrap();
+        None
+    }
+
+    async fn simulate_latency(&self, slave_id: u8, function_code: u8) {
+        let delay = match self.latencies.lock().await.get(&slave_id) {
+            Some(Some(latency)) => latency.get_delay(function_code),
+            _ => return,
+        };
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    //Every transport passes the request PDUs here, the reply says what to put on the wire
+    pub async fn handle_pdu(&self, slave_id: u8, pdu: &[u8], client: &ClientInfo) -> SlaveReply {
+        self.simulate_latency(slave_id, pdu.first().copied().unwrap_or(0))
+            .await;
+
+        let request = match Request::decode(pdu) {
+            Ok(request) => request,
+            Err(exception_code) => {
+                return SlaveReply::Respond(vec![
+                    pdu.first().copied().unwrap_or(0) | 0x80,
+                    exception_code,
+                ]);
+            }
+        };
+
+        let fault = self.get_triggered_fault(slave_id, &request).await;
+
+        let response = match &fault {
+            Some(FaultAction::NoResponse) => return SlaveReply::NoResponse,
+            Some(FaultAction::CloseConnection) => return SlaveReply::CloseConnection,
+            Some(FaultAction::Exception { exception_code }) => Response::Exception(*exception_code),
+            _ => match self.handle_request(slave_id, &request, client).await {
+                Ok(response) => response,
+                Err(exception_code) => {
+                    Response::Exception(pdu::exception_code_to_u8(&exception_code))
+                }
+            },
+        };
+
+        let response = response.encode(&request);
+
+        match fault {
+            Some(FaultAction::WrongTransactionId) => SlaveReply::WrongTransactionId(response),
+            Some(FaultAction::WrongUnitId) => SlaveReply::WrongUnitId(response),
+            Some(FaultAction::TruncatedResponse) => SlaveReply::Respond(truncate_pdu(response)),
+            Some(FaultAction::GarbledResponse) => SlaveReply::Respond(garble_pdu(response)),
+            _ => SlaveReply::Respond(response),
+        }
+    }
+
+    //Broadcasts are never answered, so only their writes are carried out, without latency or faults
+    pub async fn handle_broadcast(&self, slave_id: u8, pdu: &[u8], client: &ClientInfo) {
+        let request = match Request::decode(pdu) {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+
+        if let Request::ReadCoils { .. }
+        | Request::ReadDiscreteInputs { .. }
+        | Request::ReadHoldingRegisters { .. }
+        | Request::ReadInputRegisters { .. } = request
+        {
+            return;
+        }
+
+        if let Err(exception_code) = self.handle_request(slave_id, &request, client).await {
+            debug!(
+                "Broadcast write to slave {} failed: {:?}",
+                slave_id, exception_code
+            );
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        slave_id: u8,
+        request: &Request,
+        client: &ClientInfo,
+    ) -> Result<Response, ExceptionCode> {
+        let address = ModbusAddress {
+            slave_id,
+            table: request.table(),
+            address: request.address(),
+        };
+
+        match request {
+            Request::ReadCoils { ammount, .. } | Request::ReadDiscreteInputs { ammount, .. } => {
+                let mut coils = vec![];
+
+                for register in self.read_registers(&address, *ammount).await? {
+                    match register {
+                        ModbusDataType::Coil(coil) => coils.push(coil),
+                        _ => return Err(ExceptionCode::ServerDeviceFailure),
+                    }
+                }
+
+                Ok(Response::Coils(coils))
+            }
+            Request::ReadHoldingRegisters { ammount, .. }
+            | Request::ReadInputRegisters { ammount, .. } => {
+                let mut registers = vec![];
+
+                for register in self.read_registers(&address, *ammount).await? {
+                    match register {
+                        ModbusDataType::Register(register) => registers.push(register),
+                        _ => return Err(ExceptionCode::ServerDeviceFailure),
+                    }
+                }
+
+                Ok(Response::Registers(registers))
+            }
+            Request::WriteSingleCoil { value, .. } => {
+                self.write_register(address, ModbusDataType::Coil(*value), Some(client))
+                    .await?;
+                Ok(Response::Written)
+            }
+            Request::WriteSingleRegister { value, .. } => {
+                self.write_register(address, ModbusDataType::Register(*value), Some(client))
+                    .await?;
+                Ok(Response::Written)
+            }
+            Request::WriteMultipleCoils { values, .. } => {
+                let writes = values
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, value)| {
+                        (
+                            offset_address(&address, offset),
+                            ModbusDataType::Coil(*value),
+                        )
+                    })
+                    .collect();
+
+                self.write_registers(writes, Some(client)).await?;
+                Ok(Response::Written)
+            }
+            Request::WriteMultipleRegisters { values, .. } => {
+                let writes = values
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, value)| {
+                        (
+                            offset_address(&address, offset),
+                            ModbusDataType::Register(*value),
+                        )
+                    })
+                    .collect();
+
+                self.write_registers(writes, Some(client)).await?;
+                Ok(Response::Written)
+            }
+        }
+    }
 
+    //Read under a single lock, so a value being written is never read half updated
+    async fn read_registers(
+        &self,
+        address: &ModbusAddress,
+        ammount: u16,
+    ) -> Result<Vec<ModbusDataType>, ExceptionCode> {
         let app_state_ref = self.app_state.lock().await;
 
-        if !app_state_ref.contains_key(value_id) {
-            return Err(ExceptionCode::ServerDeviceFailure);
+        let mut registers = vec![];
+
+        for offset in 0..ammount as usize {
+            let address = offset_address(address, offset);
+
+            let Some(value_id) = self.bindings.get(&address) else {
+                return Err(ExceptionCode::IllegalDataAddress);
+            };
+
+            let register = app_state_ref
+                .get(value_id)
+                .and_then(|value_binding| value_binding.get_register(address))
+                .ok_or(ExceptionCode::ServerDeviceFailure)?;
+
+            registers.push(register);
         }
 
-        let value_binding = app_state_ref.get(value_id).unwrap();
+        Ok(registers)
+    }
+
+    async fn write_register(
+        &self,
+        address: ModbusAddress,
+        value: ModbusDataType,
+        client: Option<&ClientInfo>,
+    ) -> Result<(), ExceptionCode> {
+        self.write_registers(vec![(address, value)], client).await
+    }
 
-        if let Some(value) = value_binding.get_register(address) {
-            Ok(value)
+    //Write requests always target coils or holding registers, input tables are only written by
+    //the simulation itself, so an address only served as an input is answered as an illegal
+    //function instead of a missing address
+    fn unbound_write_exception(&self, address: &ModbusAddress) -> ExceptionCode {
+        let input_table = match address.table {
+            tweakable_modbus::ModbusTable::Coils => tweakable_modbus::ModbusTable::DiscreteInput,
+            tweakable_modbus::ModbusTable::HoldingRegisters => {
+                tweakable_modbus::ModbusTable::InputRegisters
+            }
+            _ => return ExceptionCode::IllegalDataAddress,
+        };
+
+        let input_address = ModbusAddress {
+            slave_id: address.slave_id,
+            table: input_table,
+            address: address.address,
+        };
+
+        if self.bindings.contains_key(&input_address) {
+            ExceptionCode::IllegalFunction
         } else {
-            Err(ExceptionCode::ServerDeviceFailure)
+            ExceptionCode::IllegalDataAddress
         }
     }
 
-    async fn on_write(&self, address: ModbusAddress, value: ModbusDataType) -> Result<(), ExceptionCode> {
-        if !self.bindings.contains_key(&address) {
-            return Err(ExceptionCode::IllegalDataAddress);
+    //Every register of a request is validated and applied under a single lock, so either the
+    //whole request is written or nothing is, and reads never see half of a value
+    async fn write_registers(
+        &self,
+        writes: Vec<(ModbusAddress, ModbusDataType)>,
+        client: Option<&ClientInfo>,
+    ) -> Result<(), ExceptionCode> {
+        let Some((first_address, _)) = writes.first() else {
+            return Ok(());
+        };
+
+        let unit_id = first_address.slave_id;
+
+        //Registers written to each value, in the order they come in the request
+        let mut value_writes: Vec<(&String, Vec<(ModbusAddress, ModbusDataType)>)> = vec![];
+
+        for (address, value) in writes {
+            let Some(value_id) = self.bindings.get(&address) else {
+                return Err(self.unbound_write_exception(&address));
+            };
+
+            match value_writes.iter_mut().find(|(id, _)| *id == value_id) {
+                Some((_, registers)) => registers.push((address, value)),
+                None => value_writes.push((value_id, vec![(address, value)])),
+            }
         }
 
-        let value_id = self.bindings.get(&address).unwrap();
+        let role = client.and_then(|client| client.role.clone());
 
         let mut app_state_ref = self.app_state.lock().await;
 
-        if !app_state_ref.contains_key(value_id) {
-            return Err(ExceptionCode::ServerDeviceFailure);
+        //Resulting registers of every value, nothing is applied until all of them are valid
+        let mut updates = vec![];
+
+        for (value_id, registers) in &value_writes {
+            let Some(value_binding) = app_state_ref.get(*value_id) else {
+                return Err(ExceptionCode::ServerDeviceFailure);
+            };
+
+            //Modbus/TCP Security answers unauthorized requests with an illegal function exception
+            if let Some(write_roles) = &value_binding.config.write_roles {
+                if !role
+                    .as_ref()
+                    .is_some_and(|role| write_roles.iter().any(|allowed| allowed == role))
+                {
+                    return Err(ExceptionCode::IllegalFunction);
+                }
+            }
+
+            //The function is fine, there's just nothing writable at that address
+            if value_binding.config.read_only {
+                return Err(ExceptionCode::IllegalDataAddress);
+            }
+
+            let new_registers = value_binding.get_registers_with(registers);
+
+            let new_value = value_processing::registers_to_value(
+                new_registers.clone(),
+                &value_binding.config.formatting_params,
+            );
+
+            let valid = new_value
+                .is_ok_and(|new_value| value_binding.config.check_value(&new_value).is_ok());
+
+            if !valid {
+                return Err(ExceptionCode::IllegalDataValue);
+            }
+
+            updates.push((*value_id, registers[0].0.address, new_registers));
+        }
+
+        let mut records = vec![];
+
+        for (value_id, address, new_registers) in updates {
+            let value_binding = app_state_ref.get_mut(value_id).unwrap();
+
+            let old_registers = value_binding.get_all_registers();
+
+            value_binding.set_all_registers(new_registers);
+
+            records.push(WriteRecord::new(
+                value_id,
+                WriteSource::Modbus {
+                    client: client.and_then(|client| client.peer).map(|peer| peer.ip()),
+                    unit_id,
+                    role: role.clone(),
+                },
+                value_binding.config.table.clone(),
+                address,
+                old_registers,
+                value_binding.get_all_registers(),
+                &value_binding.config.formatting_params,
+            ));
         }
 
-        let value_binding = app_state_ref.get_mut(value_id).unwrap();
+        drop(app_state_ref);
 
-        value_binding.set_register(address, value);
+        let mut writes_ref = self.writes.lock().await;
+
+        for record in records {
+            writes_ref.record(record);
+        }
 
         Ok(())
     }
-
 }
+
 pub struct ModbusSlaveCommContext {
     address: SocketAddr,
+    transport: Transport,
+    tls: Option<ServedTlsConfig>,
+    slave_ids: HashSet<u8>,
     bindings: AddressBindings,
     app_state: AppState,
+    latencies: SlaveLatencies,
+    faults: SlaveFaults,
+    writes: WriteAudit,
     config: ServedConnectionConfig,
 }
 
 impl ModbusSlaveCommContext {
-    pub fn new(config: &ServedConnection, app_state: AppState) -> Arc<Self> {
+    pub fn new(
+        config: &ServedConnection,
+        app_state: AppState,
+        latencies: SlaveLatencies,
+        faults: SlaveFaults,
+        writes: WriteAudit,
+    ) -> Arc<Self> {
         let mut bindings = AddressBindings::new();
         for slave in &config.slaves {
             for value in &slave.values {
@@ -96,30 +485,132 @@ impl ModbusSlaveCommContext {
             }
         }
 
-        let address = SocketAddr::new(IpAddr::from([0, 0, 0, 0]), config.port);
+        let address = SocketAddr::new(IpAddr::from([0, 0, 0, 0]), config.get_port());
+
+        let slave_ids = config.slaves.iter().map(|slave| slave.id).collect();
 
         Arc::new(ModbusSlaveCommContext {
             address,
+            transport: config.transport.clone(),
+            tls: config.tls.clone(),
+            slave_ids,
             bindings,
             app_state,
+            latencies,
+            faults,
+            writes,
             config: config.config.clone(),
         })
     }
 
     pub fn serve(arc: Arc<Self>) {
-        let callback = Box::new(ModbusSlaveCallback::new(arc.app_state.clone(), arc.bindings.clone()));
+        let callback = ModbusSlaveCallback::new(
+            arc.app_state.clone(),
+            arc.bindings.clone(),
+            arc.latencies.clone(),
+            arc.faults.clone(),
+            arc.writes.clone(),
+        );
+
+        let slave_ids = arc.slave_ids.clone();
+        let address = arc.address;
+
+        match arc.transport.clone() {
+            Transport::Tcp => match arc.tls.clone() {
+                None => {
+                    let connection_time_to_live = arc.config.connection_time_to_live;
+                    let callback = Arc::new(callback);
+
+                    tokio::spawn(async move {
+                        if let Err(err) =
+                            tcp::serve_tcp(address, connection_time_to_live, slave_ids, callback)
+                                .await
+                        {
+                            error!("TCP slave stopped: {}", err);
+                        }
+                    });
+                }
+                Some(tls_config) => {
+                    let connection_time_to_live = arc.config.connection_time_to_live;
+                    let callback = Arc::new(callback);
+
+                    tokio::spawn(async move {
+                        if let Err(err) = tls::serve_tls(
+                            address,
+                            tls_config,
+                            connection_time_to_live,
+                            slave_ids,
+                            callback,
+                        )
+                        .await
+                        {
+                            error!("Modbus/TCP Security slave stopped: {}", err);
+                        }
+                    });
+                }
+            },
+            Transport::Rtu(serial_config) => {
+                let callback = Arc::new(callback);
+
+                tokio::spawn(async move {
+                    if let Err(err) = rtu::serve_rtu(serial_config, slave_ids, callback).await {
+                        error!("RTU slave stopped: {}", err);
+                    }
+                });
+            }
+            Transport::RtuOverTcp => {
+                let connection_time_to_live = arc.config.connection_time_to_live;
+                let callback = Arc::new(callback);
+
+                tokio::spawn(async move {
+                    if let Err(err) = rtu::serve_rtu_over_tcp(
+                        address,
+                        connection_time_to_live,
+                        slave_ids,
+                        callback,
+                    )
+                    .await
+                    {
+                        error!("RTU over TCP slave stopped: {}", err);
+                    }
+                });
+            }
+            Transport::Udp => {
+                let callback = Arc::new(callback);
+
+                tokio::spawn(async move {
+                    if let Err(err) = udp::serve_udp(address, slave_ids, callback).await {
+                        error!("UDP slave stopped: {}", err);
+                    }
+                });
+            }
+        }
+    }
+}
 
-        let mut slave =
-            tweakable_modbus::ModbusSlaveConnection::new_tcp(arc.address, callback);
+fn offset_address(address: &ModbusAddress, offset: usize) -> ModbusAddress {
+    let mut address = address.clone();
+    address.address = address.address.wrapping_add(offset as u16);
+    address
+}
 
-        let params = ModbusSlaveConnectionParameters {
-            connection_time_to_live: arc.config.connection_time_to_live,
-            allowed_slaves: Arc::new(None),
-            allowed_ip_address: None,
-        };
+fn truncate_pdu(mut pdu: Vec<u8>) -> Vec<u8> {
+    let length = rand::rng().random_range(0..pdu.len().max(1));
+    pdu.truncate(length);
+    pdu
+}
 
-        tokio::spawn(async move { slave.server_with_parameters(params).await.unwrap() });
+fn garble_pdu(mut pdu: Vec<u8>) -> Vec<u8> {
+    let mut rng = rand::rng();
+
+    if pdu.is_empty() {
+        return pdu;
     }
+
+    let index = rng.random_range(0..pdu.len());
+    pdu[index] ^= rng.random_range(1..=u8::MAX);
+
+    pdu
 }
 
 fn get_involved_addresses(
@@ -1,18 +1,37 @@
 use std::sync::Arc;
 
 use context::ModbusSlaveCommContext;
-use crate::server::{model::ServedConnection, state::AppState};
+use crate::server::{
+    model::ServedConnection,
+    state::{audit::WriteAudit, AppState, SlaveFaults, SlaveLatencies},
+};
 
 mod context;
+mod rtu;
+mod tcp;
+mod tls;
+mod udp;
 pub struct ModbusServer {
     pub contexts: Vec<Arc<ModbusSlaveCommContext>>,
 }
 
 impl ModbusServer {
-    pub fn new(config: &Vec<ServedConnection>, app_state: AppState) -> Self {
+    pub fn new(
+        config: &Vec<ServedConnection>,
+        app_state: AppState,
+        latencies: SlaveLatencies,
+        faults: SlaveFaults,
+        writes: WriteAudit,
+    ) -> Self {
         let mut contexts = vec![];
         for connection in config {
-            let context = ModbusSlaveCommContext::new(connection, app_state.clone());
+            let context = ModbusSlaveCommContext::new(
+                connection,
+                app_state.clone(),
+                latencies.clone(),
+                faults.clone(),
+                writes.clone(),
+            );
             contexts.push(context);
         }
 
@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::common::model::SerialConfig;
+use crate::common::protocol::rtu;
+use crate::server::comm::context::{ClientInfo, ModbusSlaveCallback, SlaveReply};
+
+const BROADCAST_ID: u8 = 0;
+
+pub async fn serve_rtu(
+    config: SerialConfig,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let mut port = rtu::open_serial_port(&config)?;
+    let silent_interval = config.silent_interval();
+
+    info!("Serving RTU slaves {:?} on {}", slave_ids, config.device);
+
+    loop {
+        let frame = rtu::read_frame(&mut port, silent_interval).await?;
+
+        //Frames with a wrong CRC must be silently discarded
+        let (slave_id, request) = match rtu::decode_frame(&frame) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                debug!("Discarding RTU frame: {}", err);
+                continue;
+            }
+        };
+
+        if slave_id != BROADCAST_ID && !slave_ids.contains(&slave_id) {
+            continue;
+        }
+
+        if slave_id == BROADCAST_ID {
+            //Broadcasts are applied to every slave in the line but never answered
+            for slave_id in &slave_ids {
+                callback
+                    .handle_broadcast(*slave_id, &request, &ClientInfo::default())
+                    .await;
+            }
+            continue;
+        }
+
+        //A serial line can't be closed, so that fault is a missing response
+        let response = match callback
+            .handle_pdu(slave_id, &request, &ClientInfo::default())
+            .await
+        {
+            SlaveReply::Respond(pdu) | SlaveReply::WrongTransactionId(pdu) => {
+                rtu::encode_frame(slave_id, &pdu)
+            }
+            SlaveReply::WrongUnitId(pdu) => rtu::encode_frame(slave_id.wrapping_add(1), &pdu),
+            SlaveReply::NoResponse | SlaveReply::CloseConnection => continue,
+        };
+
+        port.write_all(&response).await?;
+    }
+}
+
+pub async fn serve_rtu_over_tcp(
+    address: SocketAddr,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let listener = TcpListener::bind(address).await?;
+
+    info!("Serving RTU over TCP slaves {:?} on {}", slave_ids, address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let slave_ids = slave_ids.clone();
+        let callback = callback.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                handle_rtu_stream(stream, peer, connection_time_to_live, slave_ids, callback).await
+            {
+                debug!("RTU over TCP connection with {} closed: {}", peer, err);
+            }
+        });
+    }
+}
+
+async fn handle_rtu_stream(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let client = ClientInfo {
+        peer: Some(peer),
+        role: None,
+    };
+
+    loop {
+        let frame = tokio::time::timeout(
+            connection_time_to_live,
+            rtu::read_stream_frame(&mut stream, true),
+        )
+        .await
+        .map_err(|_| anyhow!("Connection was idle for {:?}", connection_time_to_live))??;
+
+        let (slave_id, request) = match rtu::decode_frame(&frame) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                debug!("Discarding RTU frame: {}", err);
+                continue;
+            }
+        };
+
+        if !slave_ids.contains(&slave_id) {
+            continue;
+        }
+
+        let response = match callback.handle_pdu(slave_id, &request, &client).await {
+            SlaveReply::Respond(pdu) | SlaveReply::WrongTransactionId(pdu) => {
+                rtu::encode_frame(slave_id, &pdu)
+            }
+            SlaveReply::WrongUnitId(pdu) => rtu::encode_frame(slave_id.wrapping_add(1), &pdu),
+            SlaveReply::NoResponse => continue,
+            SlaveReply::CloseConnection => return Ok(()),
+        };
+
+        stream.write_all(&response).await?;
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+use crate::common::protocol::mbap;
+use crate::server::comm::context::{ClientInfo, ModbusSlaveCallback, SlaveReply};
+
+//Served in-tree instead of through tweakable_modbus so each request can be delayed
+pub async fn serve_tcp(
+    address: SocketAddr,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let listener = TcpListener::bind(address).await?;
+
+    info!("Serving TCP slaves {:?} on {}", slave_ids, address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let slave_ids = slave_ids.clone();
+        let callback = callback.clone();
+
+        let client = ClientInfo {
+            peer: Some(peer),
+            role: None,
+        };
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                handle_mbap_stream(stream, connection_time_to_live, slave_ids, callback, client)
+                    .await
+            {
+                debug!("TCP connection with {} closed: {}", peer, err);
+            }
+        });
+    }
+}
+
+pub async fn handle_mbap_stream<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+    client: ClientInfo,
+) -> Result<()> {
+    loop {
+        let frame = tokio::time::timeout(
+            connection_time_to_live,
+            mbap::read_stream_frame(&mut stream),
+        )
+        .await
+        .map_err(|_| anyhow!("Connection was idle for {:?}", connection_time_to_live))??;
+
+        let frame = mbap::decode_frame(&frame)?;
+
+        if !slave_ids.contains(&frame.unit_id) {
+            continue;
+        }
+
+        let reply = callback
+            .handle_pdu(frame.unit_id, &frame.pdu, &client)
+            .await;
+
+        let response = match reply {
+            SlaveReply::Respond(pdu) => {
+                mbap::encode_frame(frame.transaction_id, frame.unit_id, &pdu)
+            }
+            SlaveReply::WrongTransactionId(pdu) => {
+                mbap::encode_frame(frame.transaction_id.wrapping_add(1), frame.unit_id, &pdu)
+            }
+            SlaveReply::WrongUnitId(pdu) => {
+                mbap::encode_frame(frame.transaction_id, frame.unit_id.wrapping_add(1), &pdu)
+            }
+            SlaveReply::NoResponse => continue,
+            SlaveReply::CloseConnection => return Ok(()),
+        };
+
+        stream.write_all(&response).await?;
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info};
+
+use crate::common::protocol::tls;
+use crate::server::comm::{
+    context::{ClientInfo, ModbusSlaveCallback},
+    tcp,
+};
+use crate::server::model::connection::ServedTlsConfig;
+
+pub async fn serve_tls(
+    address: SocketAddr,
+    tls_config: ServedTlsConfig,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let server_config = tls::build_server_config(
+        &tls_config.certificate,
+        &tls_config.private_key,
+        tls_config.client_ca_certificate.as_deref(),
+    )?;
+
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+    let listener = TcpListener::bind(address).await?;
+
+    info!("Serving Modbus/TCP Security slaves {:?} on {}", slave_ids, address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let slave_ids = slave_ids.clone();
+        let callback = callback.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("TLS handshake with {} failed: {}", peer, err);
+                    return;
+                }
+            };
+
+            if let Err(err) =
+                handle_tls_stream(stream, peer, connection_time_to_live, slave_ids, callback).await
+            {
+                debug!("TLS connection with {} closed: {}", peer, err);
+            }
+        });
+    }
+}
+
+async fn handle_tls_stream(
+    stream: TlsStream<TcpStream>,
+    peer: SocketAddr,
+    connection_time_to_live: Duration,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let role = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .and_then(tls::get_certificate_role);
+
+    debug!("TLS master connected with role {:?}", role);
+
+    let client = ClientInfo {
+        peer: Some(peer),
+        role,
+    };
+
+    tcp::handle_mbap_stream(stream, connection_time_to_live, slave_ids, callback, client).await
+}
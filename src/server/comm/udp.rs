@@ -0,0 +1,57 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+use crate::common::protocol::mbap;
+use crate::server::comm::context::{ClientInfo, ModbusSlaveCallback, SlaveReply};
+
+pub async fn serve_udp(
+    address: SocketAddr,
+    slave_ids: HashSet<u8>,
+    callback: Arc<ModbusSlaveCallback>,
+) -> Result<()> {
+    let socket = UdpSocket::bind(address).await?;
+    let mut buffer = [0u8; mbap::MAX_FRAME_SIZE];
+
+    info!("Serving UDP slaves {:?} on {}", slave_ids, address);
+
+    loop {
+        let (read, peer) = socket.recv_from(&mut buffer).await?;
+
+        let frame = match mbap::decode_frame(&buffer[..read]) {
+            Ok(frame) => frame,
+            Err(err) => {
+                debug!("Discarding UDP datagram from {}: {}", peer, err);
+                continue;
+            }
+        };
+
+        if !slave_ids.contains(&frame.unit_id) {
+            continue;
+        }
+
+        let client = ClientInfo {
+            peer: Some(peer),
+            role: None,
+        };
+
+        let response = match callback.handle_pdu(frame.unit_id, &frame.pdu, &client).await {
+            SlaveReply::Respond(pdu) => {
+                mbap::encode_frame(frame.transaction_id, frame.unit_id, &pdu)
+            }
+            SlaveReply::WrongTransactionId(pdu) => {
+                mbap::encode_frame(frame.transaction_id.wrapping_add(1), frame.unit_id, &pdu)
+            }
+            SlaveReply::WrongUnitId(pdu) => {
+                mbap::encode_frame(frame.transaction_id, frame.unit_id.wrapping_add(1), &pdu)
+            }
+            //There is no connection to close over UDP
+            SlaveReply::NoResponse | SlaveReply::CloseConnection => continue,
+        };
+
+        socket.send_to(&response, peer).await?;
+    }
+}
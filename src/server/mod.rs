@@ -1,4 +1,5 @@
 pub mod model;
 pub mod state;
 pub mod comm;
-pub mod api;
\ No newline at end of file
+pub mod api;
+pub mod replay;
\ No newline at end of file
@@ -1,17 +1,28 @@
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::path::PathBuf;
 
+use crate::common::model::Transport;
 use crate::server::model::slave::ServedSlave;
 
-fn default_port() -> u16 {
-    502
+//Modbus/TCP Security has its own well known port
+fn default_port(tls: bool) -> u16 {
+    if tls {
+        802
+    } else {
+        502
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ServedConnection {
-    #[serde(default = "default_port")]
-    pub port: u16,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub transport: Transport,
+    #[serde(default)]
+    pub tls: Option<ServedTlsConfig>,
 
     #[serde(flatten)]
     pub config: ServedConnectionConfig,
@@ -19,9 +30,21 @@ pub struct ServedConnection {
 }
 
 impl ServedConnection {
+    pub fn get_port(&self) -> u16 {
+        self.port.unwrap_or_else(|| default_port(self.tls.is_some()))
+    }
+
     pub fn validate(&self) -> Result<()> {
         let mut error_string = String::new();
 
+        if let Err(err) = self.transport.validate() {
+            error_string += &format!("\t{}\n", err);
+        }
+
+        if self.tls.is_some() && self.transport != Transport::Tcp {
+            error_string += "\tTLS is only supported with the tcp transport\n";
+        }
+
         for slave in &self.slaves {
             if let Err(err) = slave.validate() {
                 error_string += &format!("\t{}:\n{}\n", slave.id, err);
@@ -60,3 +83,20 @@ pub struct ServedConnectionConfig {
     #[serde(default = "default_connection_time_to_live")]
     pub connection_time_to_live: std::time::Duration,
 }
+
+impl Default for ServedConnectionConfig {
+    fn default() -> Self {
+        ServedConnectionConfig {
+            connection_time_to_live: default_connection_time_to_live(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ServedTlsConfig {
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+    //When provided masters must present a certificate signed by this CA
+    #[serde(default)]
+    pub client_ca_certificate: Option<PathBuf>,
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::common::model::ModbusTable;
+use crate::common::protocol::pdu;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FaultAction {
+    //Numeric modbus exception code, e.g. 6 for ServerDeviceBusy
+    Exception { exception_code: u8 },
+    NoResponse,
+    CloseConnection,
+    WrongTransactionId,
+    WrongUnitId,
+    TruncatedResponse,
+    GarbledResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+fn default_armed() -> bool {
+    true
+}
+
+//A rule without value or addresses applies to every request sent to the slave
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultRule {
+    pub id: String,
+    pub action: FaultAction,
+
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub table: Option<ModbusTable>,
+    #[serde(default)]
+    pub addresses: Option<AddressRange>,
+
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+    //Disarmed after triggering once
+    #[serde(default)]
+    pub one_shot: bool,
+    #[serde(default = "default_armed")]
+    pub armed: bool,
+}
+
+impl FaultRule {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.probability) {
+            return Err(anyhow!(
+                "Fault {} probability must be between 0 and 1",
+                self.id
+            ));
+        }
+
+        if let FaultAction::Exception { exception_code } = &self.action {
+            pdu::exception_code_from_u8(*exception_code)
+                .map_err(|err| anyhow!("Fault {}: {}", self.id, err))?;
+        }
+
+        if let Some(addresses) = &self.addresses {
+            if addresses.start > addresses.end {
+                return Err(anyhow!(
+                    "Fault {} address range starts after it ends",
+                    self.id
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
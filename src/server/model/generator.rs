@@ -0,0 +1,401 @@
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::common::model::{DataType, Value, ValueFormattingParams};
+use crate::common::random;
+use crate::common::value_processing;
+
+fn default_offset() -> f64 {
+    0.0
+}
+
+fn default_duty_cycle() -> f64 {
+    0.5
+}
+
+fn default_repeat() -> bool {
+    true
+}
+
+fn default_counter_step() -> u64 {
+    1
+}
+
+fn default_update_rate() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorStep {
+    pub value: f64,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Generator {
+    Sine {
+        amplitude: f64,
+        #[serde(default = "default_offset")]
+        offset: f64,
+        #[serde(with = "humantime_serde")]
+        period: Duration,
+    },
+    Square {
+        low: f64,
+        high: f64,
+        #[serde(with = "humantime_serde")]
+        period: Duration,
+        //Fraction of the period spent high
+        #[serde(default = "default_duty_cycle")]
+        duty_cycle: f64,
+    },
+    //Goes from min to max every period and jumps back to min
+    Sawtooth {
+        min: f64,
+        max: f64,
+        #[serde(with = "humantime_serde")]
+        period: Duration,
+    },
+    RandomWalk {
+        min: f64,
+        max: f64,
+        //Maximum change on each update
+        max_step: f64,
+    },
+    UniformNoise {
+        min: f64,
+        max: f64,
+    },
+    GaussianNoise {
+        mean: f64,
+        std_dev: f64,
+    },
+    Steps {
+        steps: Vec<GeneratorStep>,
+        #[serde(default = "default_repeat")]
+        repeat: bool,
+    },
+    //Rolls over at the width of the value
+    Counter {
+        #[serde(default = "default_counter_step")]
+        step: u64,
+    },
+    Toggle {
+        #[serde(with = "humantime_serde")]
+        period: Duration,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorConfig {
+    #[serde(flatten)]
+    pub generator: Generator,
+
+    #[serde(with = "humantime_serde", default = "default_update_rate")]
+    pub update_rate: Duration,
+}
+
+impl GeneratorConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.update_rate.is_zero() {
+            return Err(anyhow!("Generator update rate can't be zero"));
+        }
+
+        match &self.generator {
+            Generator::Sine { period, .. }
+            | Generator::Sawtooth { period, .. }
+            | Generator::Toggle { period } => {
+                if period.is_zero() {
+                    return Err(anyhow!("Generator period can't be zero"));
+                }
+            }
+            Generator::Square {
+                period, duty_cycle, ..
+            } => {
+                if period.is_zero() {
+                    return Err(anyhow!("Generator period can't be zero"));
+                }
+
+                if !(0.0..=1.0).contains(duty_cycle) {
+                    return Err(anyhow!("Square generator duty cycle must be between 0 and 1"));
+                }
+            }
+            Generator::RandomWalk { min, max, .. } | Generator::UniformNoise { min, max } => {
+                if min > max {
+                    return Err(anyhow!("Generator min {} is greater than max {}", min, max));
+                }
+            }
+            Generator::Steps { steps, .. } => {
+                if steps.is_empty() {
+                    return Err(anyhow!("Steps generator needs at least one step"));
+                }
+            }
+            Generator::GaussianNoise { .. } | Generator::Counter { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+//Keeps what the stateful generators need between updates
+pub struct GeneratorState {
+    config: GeneratorConfig,
+    formatting_params: ValueFormattingParams,
+    last_value: Option<f64>,
+    counter: u128,
+}
+
+impl GeneratorState {
+    pub fn new(config: GeneratorConfig, formatting_params: ValueFormattingParams) -> Self {
+        GeneratorState {
+            config,
+            formatting_params,
+            last_value: None,
+            counter: 0,
+        }
+    }
+
+    pub fn get_update_rate(&self) -> Duration {
+        self.config.update_rate
+    }
+
+    pub fn get_formatting_params(&self) -> &ValueFormattingParams {
+        &self.formatting_params
+    }
+
+    pub fn next_value(&mut self, elapsed: Duration) -> Value {
+        let mut rng = rand::rng();
+
+        let sample = match &self.config.generator {
+            Generator::Sine {
+                amplitude,
+                offset,
+                period,
+            } => {
+                let phase = get_phase(elapsed, *period);
+                offset + amplitude * (2.0 * std::f64::consts::PI * phase).sin()
+            }
+            Generator::Square {
+                low,
+                high,
+                period,
+                duty_cycle,
+            } => {
+                if get_phase(elapsed, *period) < *duty_cycle {
+                    *high
+                } else {
+                    *low
+                }
+            }
+            Generator::Sawtooth { min, max, period } => {
+                min + (max - min) * get_phase(elapsed, *period)
+            }
+            Generator::RandomWalk { min, max, max_step } => {
+                let last_value = self.last_value.unwrap_or((min + max) / 2.0);
+                let step = rng.random_range(-1.0..=1.0) * max_step;
+                (last_value + step).clamp(*min, *max)
+            }
+            Generator::UniformNoise { min, max } => rng.random_range(*min..=*max),
+            Generator::GaussianNoise { mean, std_dev } => {
+                mean + random::standard_normal(&mut rng) * std_dev
+            }
+            Generator::Steps { steps, repeat } => get_step_value(steps, *repeat, elapsed),
+            Generator::Counter { step } => {
+                let value = self.counter;
+                self.counter = self.wrap_counter(self.counter + *step as u128);
+
+                //Counts raw, like a device counter would
+                return value_processing::to_engineering(
+                    self.counter_to_value(value),
+                    &self.formatting_params,
+                );
+            }
+            Generator::Toggle { period } => {
+                let half_periods = elapsed.as_secs_f64() / (period.as_secs_f64() / 2.0);
+                (half_periods as u64 % 2) as f64
+            }
+        };
+
+        self.last_value = Some(sample);
+
+        self.sample_to_value(sample)
+    }
+
+    //Bits the value actually has on the registers
+    fn get_width(&self) -> u32 {
+        let type_bits = (self.formatting_params.data_type.byte_size() * 8) as u32;
+        (self.formatting_params.bit_length as u32).min(type_bits)
+    }
+
+    fn get_width_mask(&self) -> u128 {
+        (1u128 << self.get_width()) - 1
+    }
+
+    fn is_signed(&self) -> bool {
+        self.formatting_params.data_type.is_signed()
+    }
+
+    //Counters roll over at the last value the registers can hold
+    fn wrap_counter(&self, counter: u128) -> u128 {
+        match self.formatting_params.data_type.get_bcd_digits() {
+            Some(digits) => counter % 10u128.pow(digits),
+            None => counter & self.get_width_mask(),
+        }
+    }
+
+    fn counter_to_value(&self, counter: u128) -> Value {
+        match self.formatting_params.data_type {
+            DataType::Boolean => Value::Boolean(counter % 2 == 1),
+            DataType::Float | DataType::Double => Value::FloatingPoint(counter as f64),
+            _ => {
+                let width = self.get_width();
+
+                //Two's complement, so signed counters roll over to their minimum
+                if self.is_signed() && counter >> (width - 1) == 1 {
+                    Value::Integer(counter as i128 - (1i128 << width))
+                } else {
+                    Value::Integer(counter as i128)
+                }
+            }
+        }
+    }
+
+    fn sample_to_value(&self, sample: f64) -> Value {
+        //Samples are engineering values, kept within what the raw value can hold
+        if let Some((gain, offset)) = self.formatting_params.get_scaling() {
+            let Some((min, max)) = self.formatting_params.get_integer_range() else {
+                return Value::FloatingPoint(sample);
+            };
+
+            let first = min as f64 * gain + offset;
+            let last = max as f64 * gain + offset;
+
+            return Value::FloatingPoint(sample.clamp(first.min(last), first.max(last)));
+        }
+
+        match self.formatting_params.data_type {
+            DataType::Boolean => Value::Boolean(sample != 0.0),
+            DataType::Float | DataType::Double => Value::FloatingPoint(sample),
+            _ => {
+                let (min, max) = self.formatting_params.get_integer_range().unwrap();
+
+                Value::Integer((sample.round() as i128).clamp(min, max))
+            }
+        }
+    }
+}
+
+//Fraction of the current period, between 0 and 1
+fn get_phase(elapsed: Duration, period: Duration) -> f64 {
+    (elapsed.as_secs_f64() / period.as_secs_f64()).fract()
+}
+
+fn get_step_value(steps: &[GeneratorStep], repeat: bool, elapsed: Duration) -> f64 {
+    let total: Duration = steps.iter().map(|step| step.duration).sum();
+
+    let mut position = if repeat && !total.is_zero() {
+        Duration::from_secs_f64(elapsed.as_secs_f64() % total.as_secs_f64())
+    } else {
+        elapsed
+    };
+
+    for step in steps {
+        if position < step.duration {
+            return step.value;
+        }
+        position -= step.duration;
+    }
+
+    steps.last().map(|step| step.value).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build_state(
+        generator: serde_json::Value,
+        data_type: &str,
+        bit_length: u16,
+    ) -> GeneratorState {
+        let config: GeneratorConfig = serde_json::from_value(generator).unwrap();
+        let formatting_params = serde_json::from_value(json!({
+            "bit_length": bit_length,
+            "data_type": data_type
+        }))
+        .unwrap();
+
+        GeneratorState::new(config, formatting_params)
+    }
+
+    fn count_from(data_type: &str, bit_length: u16, counter: u128) -> (Value, Value) {
+        let mut state = build_state(json!({ "type": "counter" }), data_type, bit_length);
+        state.counter = counter;
+
+        (
+            state.next_value(Duration::ZERO),
+            state.next_value(Duration::ZERO),
+        )
+    }
+
+    #[test]
+    fn unsigned_counters_wrap_to_zero() {
+        assert_eq!(
+            count_from("UnsignedInteger16", 16, 65535),
+            (Value::Integer(65535), Value::Integer(0))
+        );
+    }
+
+    #[test]
+    fn signed_counters_wrap_to_their_minimum() {
+        assert_eq!(
+            count_from("SignedInteger16", 16, 32767),
+            (Value::Integer(32767), Value::Integer(-32768))
+        );
+        assert_eq!(
+            count_from("SignedInteger16", 16, 65535),
+            (Value::Integer(-1), Value::Integer(0))
+        );
+    }
+
+    #[test]
+    fn bcd_counters_wrap_at_their_last_digit() {
+        assert_eq!(
+            count_from("Bcd16", 16, 9999),
+            (Value::Integer(9999), Value::Integer(0))
+        );
+    }
+
+    #[test]
+    fn counters_follow_the_bit_length() {
+        assert_eq!(
+            count_from("UnsignedInteger16", 4, 15),
+            (Value::Integer(15), Value::Integer(0))
+        );
+    }
+
+    #[test]
+    fn random_walks_are_clamped() {
+        let generator =
+            json!({ "type": "random_walk", "min": 0.0, "max": 10.0, "max_step": 100.0 });
+        let mut state = build_state(generator, "UnsignedInteger16", 16);
+
+        let mut values = vec![];
+
+        for _ in 0..100 {
+            match state.next_value(Duration::ZERO) {
+                Value::Integer(value) => values.push(value),
+                value => panic!("Unexpected value {:?}", value),
+            }
+        }
+
+        assert!(values.iter().all(|value| (0..=10).contains(value)));
+        //Steps ten times wider than the range keep hitting the bounds
+        assert!(values.contains(&0) && values.contains(&10));
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::common::random;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LatencyModel {
+    Fixed {
+        #[serde(with = "humantime_serde")]
+        delay: Duration,
+    },
+    Uniform {
+        #[serde(with = "humantime_serde")]
+        min: Duration,
+        #[serde(with = "humantime_serde")]
+        max: Duration,
+    },
+    //Jitter is the standard deviation, negative samples are clamped to zero
+    Normal {
+        #[serde(with = "humantime_serde")]
+        mean: Duration,
+        #[serde(with = "humantime_serde")]
+        jitter: Duration,
+    },
+}
+
+impl LatencyModel {
+    pub fn validate(&self) -> Result<()> {
+        if let LatencyModel::Uniform { min, max } = self {
+            if min > max {
+                return Err(anyhow!(
+                    "Uniform latency min {:?} is greater than max {:?}",
+                    min,
+                    max
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_delay(&self) -> Duration {
+        let mut rng = rand::rng();
+
+        match self {
+            LatencyModel::Fixed { delay } => *delay,
+            LatencyModel::Uniform { min, max } => {
+                Duration::from_secs_f64(rng.random_range(min.as_secs_f64()..=max.as_secs_f64()))
+            }
+            LatencyModel::Normal { mean, jitter } => {
+                let delay =
+                    mean.as_secs_f64() + random::standard_normal(&mut rng) * jitter.as_secs_f64();
+
+                Duration::from_secs_f64(delay.max(0.0))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencyConfig {
+    #[serde(flatten)]
+    pub model: LatencyModel,
+
+    //Overrides the model for specific function codes
+    #[serde(default)]
+    pub function_codes: HashMap<u8, LatencyModel>,
+}
+
+impl LatencyConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.model.validate()?;
+
+        for (function_code, model) in &self.function_codes {
+            model
+                .validate()
+                .map_err(|err| anyhow!("Function code {}: {}", function_code, err))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_delay(&self, function_code: u8) -> Duration {
+        self.function_codes
+            .get(&function_code)
+            .unwrap_or(&self.model)
+            .get_delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build_config(config: serde_json::Value) -> LatencyConfig {
+        let config: LatencyConfig = serde_json::from_value(config).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn fixed_delays_are_always_the_same() {
+        let config = build_config(json!({ "type": "fixed", "delay": "150ms" }));
+
+        for function_code in [1, 3, 16] {
+            assert_eq!(config.get_delay(function_code), Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn function_codes_override_the_model() {
+        let config = build_config(json!({
+            "type": "fixed",
+            "delay": "10ms",
+            "function_codes": {
+                "16": { "type": "fixed", "delay": "500ms" }
+            }
+        }));
+
+        assert_eq!(config.get_delay(3), Duration::from_millis(10));
+        assert_eq!(config.get_delay(16), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn uniform_delays_stay_within_their_bounds() {
+        let config = build_config(json!({ "type": "uniform", "min": "20ms", "max": "40ms" }));
+
+        let (min, middle, max) = (
+            Duration::from_millis(20),
+            Duration::from_millis(30),
+            Duration::from_millis(40),
+        );
+
+        let delays: Vec<Duration> = (0..1000).map(|_| config.get_delay(3)).collect();
+
+        assert!(delays.iter().all(|delay| (min..=max).contains(delay)));
+
+        //Not stuck at one of the bounds
+        assert!(delays.iter().any(|delay| *delay < middle));
+        assert!(delays.iter().any(|delay| *delay > middle));
+    }
+
+    #[test]
+    fn uniform_bounds_must_be_ordered() {
+        let config: LatencyConfig = serde_json::from_value(json!({
+            "type": "fixed",
+            "delay": "10ms",
+            "function_codes": {
+                "3": { "type": "uniform", "min": "40ms", "max": "20ms" }
+            }
+        }))
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,4 +1,8 @@
 pub mod connection;
+pub mod fault;
+pub mod generator;
+pub mod latency;
+pub mod scenario;
 pub mod slave;
 pub mod value;
 
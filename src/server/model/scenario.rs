@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::common::model::Value;
+use crate::server::model::{fault::FaultRule, latency::LatencyConfig, ServedConnection};
+
+fn default_autostart() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioAction {
+    SetValue { value_id: String, value: Value },
+    //Adds the rule or replaces the one with the same id
+    SetFault { slave: u8, rule: FaultRule },
+    RemoveFault { slave: u8, fault: String },
+    ArmFault { slave: u8, fault: String },
+    DisarmFault { slave: u8, fault: String },
+    SetLatency { slave: u8, latency: Option<LatencyConfig> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioEvent {
+    #[serde(with = "humantime_serde")]
+    pub at: Duration,
+    #[serde(flatten)]
+    pub action: ScenarioAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "default_autostart")]
+    pub autostart: bool,
+    //Starts over after the last event
+    #[serde(default)]
+    pub repeat: bool,
+    pub events: Vec<ScenarioEvent>,
+}
+
+impl Scenario {
+    pub fn validate(&self, config: &Vec<ServedConnection>) -> Result<()> {
+        let mut error_string = String::new();
+
+        //Value ids with their slave, and every slave id
+        let mut values = HashMap::new();
+        let mut slaves = std::collections::HashSet::new();
+
+        for connection in config {
+            for slave in &connection.slaves {
+                slaves.insert(slave.id);
+
+                for value in &slave.values {
+                    values.insert(value.id.clone(), value);
+                }
+            }
+        }
+
+        for (index, event) in self.events.iter().enumerate() {
+            if index > 0 && event.at < self.events[index - 1].at {
+                error_string += &format!(
+                    "Event {} at {:?} comes before the previous event\n",
+                    index, event.at
+                );
+            }
+
+            let slave = match &event.action {
+                ScenarioAction::SetValue { value_id, value } => {
+                    match values.get(value_id) {
+                        Some(config) => {
+                            if let Err(err) = crate::common::value_processing::value_to_registers(
+                                value.clone(),
+                                &config.formatting_params,
+                            ) {
+                                error_string +=
+                                    &format!("Event {} can't set {}: {}\n", index, value_id, err);
+                            }
+                        }
+                        None => {
+                            error_string += &format!(
+                                "Event {} refers to value {} which is not configured\n",
+                                index, value_id
+                            );
+                        }
+                    }
+                    None
+                }
+                ScenarioAction::SetFault { slave, rule } => {
+                    if let Err(err) = rule.validate() {
+                        error_string += &format!("Event {}: {}\n", index, err);
+                    }
+                    Some(slave)
+                }
+                ScenarioAction::SetLatency { slave, latency } => {
+                    if let Some(Err(err)) = latency.as_ref().map(|latency| latency.validate()) {
+                        error_string += &format!("Event {}: {}\n", index, err);
+                    }
+                    Some(slave)
+                }
+                ScenarioAction::RemoveFault { slave, .. }
+                | ScenarioAction::ArmFault { slave, .. }
+                | ScenarioAction::DisarmFault { slave, .. } => Some(slave),
+            };
+
+            if let Some(slave) = slave {
+                if !slaves.contains(slave) {
+                    error_string += &format!(
+                        "Event {} refers to slave {} which is not configured\n",
+                        index, slave
+                    );
+                }
+            }
+        }
+
+        if self.repeat && self.get_length().is_zero() {
+            error_string += "A repeating scenario needs its last event after 0s\n";
+        }
+
+        if error_string.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(error_string))
+        }
+    }
+
+    //Time of the last event, a repeating scenario starts over then
+    pub fn get_length(&self) -> Duration {
+        self.events
+            .iter()
+            .map(|event| event.at)
+            .max()
+            .unwrap_or_default()
+    }
+}
@@ -2,7 +2,14 @@ use std::u16;
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 
-use crate::{common::model::ModbusTable, server::model::value::ServedValue};
+use crate::{
+    common::model::ModbusTable,
+    server::model::{
+        fault::FaultRule,
+        latency::{LatencyConfig, LatencyModel},
+        value::ServedValue,
+    },
+};
 
 fn default_id() -> u8 {
     1
@@ -15,7 +22,10 @@ pub struct ServedSlave {
 
     #[serde(flatten)]
     pub config: ServedSlaveConfig,
-    pub values: Vec<ServedValue>
+    pub values: Vec<ServedValue>,
+
+    #[serde(default)]
+    pub faults: Vec<FaultRule>,
 }
 
 impl ServedSlave {
@@ -43,6 +53,33 @@ impl ServedSlave {
             }
         }
 
+        let mut fault_ids = std::collections::HashSet::new();
+
+        for fault in &self.faults {
+            if let Err(error) = fault.validate() {
+                error_string += &format!("{}\n", error);
+            }
+
+            if !fault_ids.insert(fault.id.clone()) {
+                error_string += &format!("Repeated fault id {}\n", fault.id);
+            }
+
+            if let Some(value_id) = &fault.value {
+                if !self.values.iter().any(|value| value.id == *value_id) {
+                    error_string += &format!(
+                        "Fault {} refers to value {} which is not in the slave\n",
+                        fault.id, value_id
+                    );
+                }
+            }
+        }
+
+        if let Some(latency) = &self.config.latency {
+            if let Err(error) = latency.validate() {
+                error_string += &format!("Wrong latency: {}\n", error);
+            }
+        }
+
         if error_string.is_empty() {
             Ok(())
         }
@@ -64,6 +101,9 @@ fn default_grid_size() -> u16 {
 pub struct ServedSlaveConfig {
     #[serde(with = "humantime_serde", default = "default_response_delay")]
     response_delay: Option<std::time::Duration>,
+    //Takes precedence over response_delay
+    #[serde(default)]
+    latency: Option<LatencyConfig>,
 
     #[serde(default = "default_grid_size")]
     max_coils: u16,
@@ -74,3 +114,29 @@ pub struct ServedSlaveConfig {
     #[serde(default = "default_grid_size")]
     max_input_registers: u16,
 }
+
+impl Default for ServedSlaveConfig {
+    fn default() -> Self {
+        ServedSlaveConfig {
+            response_delay: default_response_delay(),
+            latency: None,
+            max_coils: default_grid_size(),
+            max_discrete_inputs: default_grid_size(),
+            max_holding_registers: default_grid_size(),
+            max_input_registers: default_grid_size(),
+        }
+    }
+}
+
+impl ServedSlaveConfig {
+    pub fn get_latency(&self) -> Option<LatencyConfig> {
+        if let Some(latency) = &self.latency {
+            return Some(latency.clone());
+        }
+
+        self.response_delay.map(|delay| LatencyConfig {
+            model: LatencyModel::Fixed { delay },
+            function_codes: Default::default(),
+        })
+    }
+}
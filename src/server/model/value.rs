@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::common::model::{ModbusTable, Value, ValueFormattingParams};
+use crate::common::model::{DataType, ModbusTable, Value, ValueFormattingParams};
+use crate::common::value_processing;
+use crate::server::model::generator::GeneratorConfig;
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ServedValue {
@@ -13,12 +15,58 @@ pub struct ServedValue {
     pub formatting_params: ValueFormattingParams,
 
     pub default_value: Value,
+
+    //Roles from the masters' TLS certificates allowed to write this value, anyone can when missing
+    #[serde(default)]
+    pub write_roles: Option<Vec<String>>,
+
+    //Keeps the value changing on its own instead of staying at the default value
+    #[serde(default)]
+    pub generator: Option<GeneratorConfig>,
+
+    //Masters can't write it, the API still can
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub allowed_values: Option<Vec<Value>>,
 }
 
 impl ServedValue {
     pub fn validate(&self, max_registers: u16) -> Result<()> {
         self.formatting_params.validate(self.table.clone())?;
 
+        if let Some(generator) = &self.generator {
+            if let DataType::String { .. } = self.formatting_params.data_type {
+                return Err(anyhow!("Value {} is a string, it can't have a generator", self.id));
+            }
+
+            generator.validate()?;
+        }
+
+        if let DataType::String { .. } = self.formatting_params.data_type {
+            if self.min.is_some() || self.max.is_some() {
+                return Err(anyhow!("Value {} is a string, it can't have a min or max", self.id));
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(anyhow!(
+                    "Value {} min {} is greater than max {}",
+                    self.id,
+                    min,
+                    max
+                ));
+            }
+        }
+
+        self.check_value(&self.default_value)
+            .map_err(|err| anyhow!("Value {} default value is not valid: {}", self.id, err))?;
+
         let register_size = self.table.register_size() as u16;
 
         let ending_bit =
@@ -42,4 +90,49 @@ impl ServedValue {
         }
         Ok(())
     }
+
+    //Limits a written value must respect
+    pub fn check_value(&self, value: &Value) -> Result<()> {
+        //Also checks it matches the data type and fits in its bits, like text longer than the string
+        value_processing::value_to_registers(value.clone(), &self.formatting_params)?;
+
+        if let Value::Text(text) = value {
+            if let Some(allowed_values) = &self.allowed_values {
+                if !allowed_values.contains(value) {
+                    return Err(anyhow!("{} is not one of the allowed values", text));
+                }
+            }
+
+            return Ok(());
+        }
+
+        let number = value.as_f64();
+
+        if number.is_nan() && (self.min.is_some() || self.max.is_some()) {
+            return Err(anyhow!("NaN is out of range"));
+        }
+
+        if let Some(min) = self.min {
+            if number < min {
+                return Err(anyhow!("{} is lower than the minimum {}", number, min));
+            }
+        }
+
+        if let Some(max) = self.max {
+            if number > max {
+                return Err(anyhow!("{} is greater than the maximum {}", number, max));
+            }
+        }
+
+        if let Some(allowed_values) = &self.allowed_values {
+            if !allowed_values
+                .iter()
+                .any(|allowed| allowed.as_f64() == number)
+            {
+                return Err(anyhow!("{} is not one of the allowed values", number));
+            }
+        }
+
+        Ok(())
+    }
 }
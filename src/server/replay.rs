@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::client::model::PolledValue;
+use crate::common::model::{DataType, Transport, Value};
+use crate::common::value_processing;
+use crate::server::model::{
+    connection::ServedConnectionConfig,
+    slave::{ServedSlave, ServedSlaveConfig},
+    ServedConnection, ServedValue,
+};
+use crate::server::state::{AppState, ValueState};
+
+//Polls read from the db at a time, the capture can be far bigger than memory
+const CHUNK_SIZE: usize = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct ReplayParams {
+    pub speed: f64,
+    pub repeat: bool,
+}
+
+struct RecordedPoll {
+    value_id: String,
+    millis_since_epoch: u64,
+    value: Vec<u8>,
+}
+
+enum ReplayItem {
+    Poll(RecordedPoll),
+    End,
+}
+
+//The capture is never modified, so it's opened read only
+fn open_capture(path: &PathBuf) -> Result<Connection> {
+    Ok(Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(columns.iter().any(|existing| existing == column))
+}
+
+fn get_recorded_values(conn: &Connection) -> Result<Vec<(u8, PolledValue)>> {
+    let mut stmt = conn.prepare("SELECT slave_id, config FROM modbus_values ORDER BY name")?;
+
+    let mut rows = stmt.query([])?;
+    let mut values = vec![];
+
+    while let Some(row) = rows.next()? {
+        let slave_id: u8 = row.get(0)?;
+        let config: String = row.get(1)?;
+
+        values.push((slave_id, serde_json::from_str::<PolledValue>(&config)?));
+    }
+
+    Ok(values)
+}
+
+fn get_first_value(conn: &Connection, value: &PolledValue) -> Result<Option<Value>> {
+    let quality_filter = if has_column(conn, "modbus_polls", "quality")? {
+        "AND quality = 0"
+    } else {
+        ""
+    };
+
+    let first = conn.query_row(
+        &format!(
+            "SELECT value FROM modbus_polls
+             WHERE value_id = ? AND value IS NOT NULL {}
+             ORDER BY id LIMIT 1",
+            quality_filter
+        ),
+        [value.id.clone()],
+        |row| row.get::<_, Vec<u8>>(0),
+    );
+
+    //Values that never had a good poll start at zero, any other error means the capture is broken
+    let first = match first {
+        Ok(bytes) => Some(bytes),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    match first {
+        Some(bytes) => Ok(Some(value_processing::bytes_to_value(
+            bytes,
+            &value.formatting_params,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+fn get_zero_value(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::Boolean => Value::Boolean(false),
+        DataType::Float | DataType::Double => Value::FloatingPoint(0.0),
+        DataType::String { .. } => Value::Text(String::new()),
+        _ => Value::Integer(0),
+    }
+}
+
+//Every recorded slave is served over TCP on the given port, starting at its first recorded value
+pub fn build_config_from_capture(path: &PathBuf, port: u16) -> Result<Vec<ServedConnection>> {
+    let conn = open_capture(path)?;
+
+    let mut slaves: BTreeMap<u8, Vec<ServedValue>> = BTreeMap::new();
+
+    for (slave_id, value) in get_recorded_values(&conn)? {
+        let default_value = match get_first_value(&conn, &value)? {
+            Some(default_value) => default_value,
+            None => get_zero_value(&value.formatting_params.data_type),
+        };
+
+        slaves.entry(slave_id).or_default().push(ServedValue {
+            id: value.id,
+            starting_address: value.starting_address,
+            table: value.table,
+            formatting_params: value.formatting_params,
+            default_value,
+            write_roles: None,
+            generator: None,
+            read_only: false,
+            min: None,
+            max: None,
+            allowed_values: None,
+        });
+    }
+
+    if slaves.is_empty() {
+        return Err(anyhow!("The capture has no values"));
+    }
+
+    let slaves = slaves
+        .into_iter()
+        .map(|(id, values)| ServedSlave {
+            id,
+            config: ServedSlaveConfig::default(),
+            values,
+            faults: vec![],
+        })
+        .collect();
+
+    Ok(vec![ServedConnection {
+        port: Some(port),
+        transport: Transport::Tcp,
+        tls: None,
+        config: ServedConnectionConfig::default(),
+        slaves,
+    }])
+}
+
+//Runs on its own thread since rusqlite is blocking, the channel keeps it just ahead of playback
+fn read_capture(path: PathBuf, repeat: bool, tx: mpsc::Sender<ReplayItem>) -> Result<()> {
+    let conn = open_capture(&path)?;
+
+    let timestamp = if has_column(&conn, "modbus_polls", "timestamp_ms")? {
+        "COALESCE(timestamp_ms, timestamp * 1000)"
+    } else {
+        "timestamp * 1000"
+    };
+
+    let quality_filter = if has_column(&conn, "modbus_polls", "quality")? {
+        "AND quality = 0"
+    } else {
+        ""
+    };
+
+    let query = format!(
+        "SELECT id, value_id, {timestamp}, value
+         FROM modbus_polls
+         WHERE id > ? AND value IS NOT NULL {quality_filter}
+         ORDER BY id
+         LIMIT ?"
+    );
+
+    loop {
+        let mut last_id: i64 = 0;
+        let mut pass_polls = 0;
+
+        loop {
+            let mut stmt = conn.prepare_cached(&query)?;
+            let mut rows = stmt.query(params![last_id, CHUNK_SIZE as i64])?;
+            let mut read = 0;
+
+            while let Some(row) = rows.next()? {
+                last_id = row.get(0)?;
+                read += 1;
+
+                let poll = RecordedPoll {
+                    value_id: row.get(1)?,
+                    millis_since_epoch: row.get(2)?,
+                    value: row.get(3)?,
+                };
+
+                if tx.blocking_send(ReplayItem::Poll(poll)).is_err() {
+                    return Ok(());
+                }
+            }
+
+            pass_polls += read;
+
+            if read < CHUNK_SIZE {
+                break;
+            }
+        }
+
+        if tx.blocking_send(ReplayItem::End).is_err() || !repeat {
+            return Ok(());
+        }
+
+        //Every pass would be just as empty, looping over them would only spin
+        if pass_polls == 0 {
+            return Err(anyhow!("The capture has no good polls to loop over"));
+        }
+    }
+}
+
+fn apply_poll(app_state: &mut HashMap<String, ValueState>, poll: RecordedPoll) -> Result<()> {
+    let Some(value_state) = app_state.get_mut(&poll.value_id) else {
+        return Ok(());
+    };
+
+    let params = &value_state.config.formatting_params;
+
+    //Recorded raw, so it's encoded as it is without scaling it back
+    let value = value_processing::format_value(poll.value, &params.data_type)?;
+    let registers = value_processing::raw_value_to_registers(value, params)?;
+
+    value_state.set_all_registers(registers);
+
+    Ok(())
+}
+
+//Polls are applied keeping their recorded spacing divided by the speed
+pub fn start_replay(path: PathBuf, params: ReplayParams, app_state: AppState) {
+    let (tx, mut rx) = mpsc::channel(CHUNK_SIZE);
+
+    let reader_path = path.clone();
+    let repeat = params.repeat;
+
+    std::thread::spawn(move || {
+        if let Err(err) = read_capture(reader_path, repeat, tx) {
+            error!("Couldn't read capture: {}", err);
+        }
+    });
+
+    tokio::spawn(async move {
+        info!(
+            "Replaying {} at {}x{}",
+            path.to_string_lossy(),
+            params.speed,
+            if params.repeat { ", looped" } else { "" }
+        );
+
+        //Recorded time and instant of the first poll of the current pass
+        let mut origin: Option<(u64, tokio::time::Instant)> = None;
+        let mut replayed: u64 = 0;
+
+        while let Some(item) = rx.recv().await {
+            let poll = match item {
+                ReplayItem::Poll(poll) => poll,
+                ReplayItem::End => {
+                    debug!("Replay pass finished, {} polls replayed", replayed);
+                    origin = None;
+                    continue;
+                }
+            };
+
+            let (origin_millis, origin_instant) =
+                *origin.get_or_insert((poll.millis_since_epoch, tokio::time::Instant::now()));
+
+            let offset = poll.millis_since_epoch.saturating_sub(origin_millis);
+            let due =
+                origin_instant + Duration::from_secs_f64(offset as f64 / 1000.0 / params.speed);
+
+            tokio::time::sleep_until(due).await;
+
+            replayed += 1;
+
+            let value_id = poll.value_id.clone();
+
+            if let Err(err) = apply_poll(&mut *app_state.lock().await, poll) {
+                warn!("Couldn't replay poll of value {}: {}", value_id, err);
+            }
+        }
+
+        info!("Replay of {} finished", path.to_string_lossy());
+    });
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+use tracing::error;
+use tweakable_modbus::ModbusDataType;
+
+use crate::common::model::{ModbusTable, Value, ValueFormattingParams};
+use crate::common::value_processing;
+use crate::server::state::snapshot::StoredRegister;
+
+pub type WriteAudit = Arc<Mutex<WriteLog>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WriteSource {
+    //Client is missing on serial lines
+    Modbus {
+        client: Option<IpAddr>,
+        unit_id: u8,
+        role: Option<String>,
+    },
+    Http,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteRecord {
+    pub millis_since_epoch: u64,
+    pub value_id: String,
+    pub source: WriteSource,
+    pub table: ModbusTable,
+    //First register written, API writes set the whole value from its starting address
+    pub address: u16,
+    pub old_registers: Vec<StoredRegister>,
+    pub new_registers: Vec<StoredRegister>,
+    //Value after the write, missing if the registers can't be decoded
+    pub value: Option<Value>,
+}
+
+impl WriteRecord {
+    pub fn new(
+        value_id: &str,
+        source: WriteSource,
+        table: ModbusTable,
+        address: u16,
+        old_registers: Vec<ModbusDataType>,
+        new_registers: Vec<ModbusDataType>,
+        formatting_params: &ValueFormattingParams,
+    ) -> Self {
+        let value =
+            value_processing::registers_to_value(new_registers.clone(), formatting_params).ok();
+
+        WriteRecord {
+            millis_since_epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            value_id: value_id.to_string(),
+            source,
+            table,
+            address,
+            old_registers: old_registers.into_iter().map(StoredRegister::from).collect(),
+            new_registers: new_registers.into_iter().map(StoredRegister::from).collect(),
+            value,
+        }
+    }
+}
+
+//Keeps the last writes in memory, the oldest are dropped once it's full
+pub struct WriteLog {
+    records: VecDeque<WriteRecord>,
+    capacity: usize,
+    file: Option<mpsc::UnboundedSender<WriteRecord>>,
+}
+
+impl WriteLog {
+    pub fn new(capacity: usize, file: Option<&PathBuf>) -> Result<Self> {
+        let file = match file {
+            Some(path) => Some(start_file_writer(BufWriter::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            ))),
+            None => None,
+        };
+
+        Ok(WriteLog {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            file,
+        })
+    }
+
+    pub fn record(&mut self, record: WriteRecord) {
+        if let Some(file) = &self.file {
+            if file.send(record.clone()).is_err() {
+                error!("The audit file writer stopped, the write isn't appended");
+            }
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(record);
+    }
+
+    //Writes strictly after the given time, all of them without it
+    pub fn get_writes(&self, since: Option<u64>) -> Vec<WriteRecord> {
+        self.records
+            .iter()
+            .filter(|record| since.is_none_or(|since| record.millis_since_epoch > since))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_value_writes(&self, value_id: &str, since: Option<u64>) -> Vec<WriteRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.value_id == value_id)
+            .filter(|record| since.is_none_or(|since| record.millis_since_epoch > since))
+            .cloned()
+            .collect()
+    }
+}
+
+//Appends on its own thread, so writes never wait for the disk while holding the state
+fn start_file_writer(mut file: BufWriter<File>) -> mpsc::UnboundedSender<WriteRecord> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<WriteRecord>();
+
+    std::thread::spawn(move || {
+        while let Some(record) = rx.blocking_recv() {
+            if let Err(err) = append_record(&mut file, &record) {
+                error!("Couldn't append write to the audit file: {}", err);
+            }
+        }
+    });
+
+    tx
+}
+
+fn append_record(file: &mut BufWriter<File>, record: &WriteRecord) -> Result<()> {
+    serde_json::to_writer(&mut *file, record)?;
+    file.write_all(b"\n")?;
+    file.flush()?;
+
+    Ok(())
+}
+
+pub fn build_write_audit(capacity: usize, file: Option<&PathBuf>) -> Result<WriteAudit> {
+    Ok(Arc::new(Mutex::new(WriteLog::new(capacity, file)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn records_are_appended_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("writes.jsonl");
+
+        let formatting_params: ValueFormattingParams =
+            serde_json::from_str(r#"{ "bit_length": 16, "data_type": "UnsignedInteger16" }"#)
+                .unwrap();
+
+        let mut log = WriteLog::new(1, Some(&path)).unwrap();
+
+        for register in [1, 2] {
+            log.record(WriteRecord::new(
+                "speed",
+                WriteSource::Http,
+                ModbusTable::HoldingRegisters,
+                100,
+                vec![ModbusDataType::Register(0)],
+                vec![ModbusDataType::Register(register)],
+                &formatting_params,
+            ));
+        }
+
+        //Only the last one is kept in memory
+        assert_eq!(log.get_writes(None).len(), 1);
+
+        let mut lines = vec![];
+
+        for _ in 0..100 {
+            lines = std::fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect();
+
+            if lines.len() == 2 {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["value"], serde_json::json!({ "Integer": 1 }));
+        assert_eq!(lines[1]["value"], serde_json::json!({ "Integer": 2 }));
+    }
+}
@@ -0,0 +1,51 @@
+use tracing::{debug, error};
+
+use crate::common::value_processing;
+use crate::server::model::generator::GeneratorState;
+use crate::server::model::ServedConnection;
+use crate::server::state::AppState;
+
+pub fn start_generators(config: &Vec<ServedConnection>, app_state: AppState) {
+    for connection in config {
+        for slave in &connection.slaves {
+            for value in &slave.values {
+                let Some(generator) = &value.generator else {
+                    continue;
+                };
+
+                let state =
+                    GeneratorState::new(generator.clone(), value.formatting_params.clone());
+
+                tokio::spawn(generator_task(value.id.clone(), state, app_state.clone()));
+            }
+        }
+    }
+}
+
+async fn generator_task(value_id: String, mut state: GeneratorState, app_state: AppState) {
+    let start = tokio::time::Instant::now();
+    let mut interval = tokio::time::interval(state.get_update_rate());
+
+    loop {
+        interval.tick().await;
+
+        let value = state.next_value(start.elapsed());
+
+        let registers = match value_processing::value_to_registers(
+            value.clone(),
+            state.get_formatting_params(),
+        ) {
+            Ok(registers) => registers,
+            Err(err) => {
+                error!("Generator for value {} stopped: {}", value_id, err);
+                return;
+            }
+        };
+
+        if let Some(value_state) = app_state.lock().await.get_mut(&value_id) {
+            value_state.set_all_registers(registers);
+        }
+
+        debug!("Generator set value {} to {:?}", value_id, value);
+    }
+}
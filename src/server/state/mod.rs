@@ -1,3 +1,4 @@
+use anyhow::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -8,11 +9,22 @@ use crate::{
         model::Value,
         value_processing,
     },
-    server::model::{ServedConnection, ServedValue},
+    server::model::{fault::FaultRule, latency::LatencyConfig, ServedConnection, ServedValue},
 };
 
+pub mod audit;
+pub mod generator;
+pub mod scenario;
+pub mod snapshot;
+
 pub type AppState = Arc<Mutex<HashMap<String, ValueState>>>;
 
+//Every configured slave id, with the latency it currently simulates
+pub type SlaveLatencies = Arc<Mutex<HashMap<u8, Option<LatencyConfig>>>>;
+
+//Fault rules of every configured slave id, armed or not
+pub type SlaveFaults = Arc<Mutex<HashMap<u8, Vec<FaultRule>>>>;
+
 pub struct ValueState {
     pub starting_address: ModbusAddress,
     registers: Vec<ModbusDataType>,
@@ -24,14 +36,14 @@ impl ValueState {
         starting_address: ModbusAddress,
         default_value: Value,
         config: ServedValue,
-    ) -> Self {
-        let registers = value_processing::value_to_registers(default_value, &config.formatting_params).unwrap();
+    ) -> Result<Self> {
+        let registers = value_processing::value_to_registers(default_value, &config.formatting_params)?;
 
-        ValueState {
+        Ok(ValueState {
             starting_address,
             registers,
             config,
-        }
+        })
     }
 
     pub fn get_all_registers(&self) -> Vec<ModbusDataType> {
@@ -58,6 +70,28 @@ impl ValueState {
         Some(value)
     }
 
+    //Registers the value would hold after the writes, addresses outside of it are ignored
+    pub fn get_registers_with(
+        &self,
+        writes: &[(ModbusAddress, ModbusDataType)],
+    ) -> Vec<ModbusDataType> {
+        let mut registers = self.registers.clone();
+
+        for (address, value) in writes {
+            if address.address < self.starting_address.address {
+                continue;
+            }
+
+            let offset = (address.address - self.starting_address.address) as usize;
+
+            if let Some(register) = registers.get_mut(offset) {
+                *register = *value;
+            }
+        }
+
+        registers
+    }
+
     pub fn set_register(& mut self, address: ModbusAddress, value:ModbusDataType) {
         if address.address < self.starting_address.address {
             return;
@@ -74,7 +108,7 @@ impl ValueState {
 
 }
 
-pub fn build_app_state(config: &Vec<ServedConnection>) -> AppState {
+pub fn build_app_state(config: &Vec<ServedConnection>) -> Result<AppState> {
     let mut app_state = HashMap::new();
 
     for connection in config {
@@ -90,12 +124,39 @@ pub fn build_app_state(config: &Vec<ServedConnection>) -> AppState {
                     value.id.clone(),
                     ValueState::new(
                         address,
-                        value.default_value,
+                        value.default_value.clone(),
                         value.clone(),
-                    ),
+                    )?,
                 );
             }
         }
     }
-    Arc::new(Mutex::new(app_state))
+    Ok(Arc::new(Mutex::new(app_state)))
+}
+
+pub fn build_slave_latencies(config: &Vec<ServedConnection>) -> SlaveLatencies {
+    let mut latencies = HashMap::new();
+
+    for connection in config {
+        for slave in &connection.slaves {
+            latencies.insert(slave.id, slave.config.get_latency());
+        }
+    }
+
+    Arc::new(Mutex::new(latencies))
+}
+
+pub fn build_slave_faults(config: &Vec<ServedConnection>) -> SlaveFaults {
+    let mut faults: HashMap<u8, Vec<FaultRule>> = HashMap::new();
+
+    for connection in config {
+        for slave in &connection.slaves {
+            faults
+                .entry(slave.id)
+                .or_default()
+                .extend(slave.faults.iter().cloned());
+        }
+    }
+
+    Arc::new(Mutex::new(faults))
 }
@@ -0,0 +1,419 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::common::value_processing;
+use crate::server::model::scenario::{Scenario, ScenarioAction};
+use crate::server::state::{AppState, SlaveFaults, SlaveLatencies};
+
+const STEP_INTERVAL: Duration = Duration::from_millis(10);
+
+//Time source of the scenario, only differences between readings matter
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    start: std::time::Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+//Only moves when told to, so tests can drive a scenario deterministically
+#[derive(Default)]
+pub struct VirtualClock {
+    now: std::sync::Mutex<Duration>,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioProgress {
+    pub running: bool,
+    pub finished: bool,
+    #[serde(with = "humantime_serde")]
+    pub elapsed: Duration,
+    #[serde(with = "humantime_serde")]
+    pub length: Duration,
+    pub executed_events: usize,
+    pub total_events: usize,
+    pub iterations: u64,
+}
+
+pub struct ScenarioRunner {
+    scenario: Scenario,
+    clock: Arc<dyn Clock>,
+    app_state: AppState,
+    latencies: SlaveLatencies,
+    faults: SlaveFaults,
+
+    //Scenario time accumulated before the last pause
+    elapsed_before_pause: Duration,
+    //Clock reading when it was last started, None while paused
+    resumed_at: Option<Duration>,
+    next_event: usize,
+    iterations: u64,
+}
+
+impl ScenarioRunner {
+    pub fn new(
+        scenario: Scenario,
+        clock: Arc<dyn Clock>,
+        app_state: AppState,
+        latencies: SlaveLatencies,
+        faults: SlaveFaults,
+    ) -> Self {
+        ScenarioRunner {
+            scenario,
+            clock,
+            app_state,
+            latencies,
+            faults,
+            elapsed_before_pause: Duration::ZERO,
+            resumed_at: None,
+            next_event: 0,
+            iterations: 0,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.resumed_at.is_some()
+    }
+
+    pub fn get_elapsed(&self) -> Duration {
+        match self.resumed_at {
+            Some(resumed_at) => {
+                self.elapsed_before_pause + self.clock.now().saturating_sub(resumed_at)
+            }
+            None => self.elapsed_before_pause,
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.resumed_at.is_none() {
+            self.resumed_at = Some(self.clock.now());
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.elapsed_before_pause = self.get_elapsed();
+        self.resumed_at = None;
+    }
+
+    //Back to t=0 without undoing the events already applied
+    pub fn rewind(&mut self) {
+        self.elapsed_before_pause = Duration::ZERO;
+        self.next_event = 0;
+        self.iterations = 0;
+
+        if self.resumed_at.is_some() {
+            self.resumed_at = Some(self.clock.now());
+        }
+    }
+
+    pub fn get_progress(&self) -> ScenarioProgress {
+        let total_events = self.scenario.events.len();
+
+        ScenarioProgress {
+            running: self.is_running(),
+            finished: !self.scenario.repeat && self.next_event >= total_events,
+            elapsed: self.get_elapsed(),
+            length: self.scenario.get_length(),
+            executed_events: self.next_event,
+            total_events,
+            iterations: self.iterations,
+        }
+    }
+
+    //Applies every event that is due, returns how many were applied
+    pub async fn step(&mut self) -> usize {
+        let mut applied = 0;
+
+        loop {
+            let elapsed = self.get_elapsed();
+
+            if self.next_event >= self.scenario.events.len() {
+                let length = self.scenario.get_length();
+
+                if !self.scenario.repeat || length.is_zero() || elapsed < length {
+                    return applied;
+                }
+
+                //Starting over keeps whatever time went past the end of the scenario
+                self.shift_elapsed(length);
+                self.next_event = 0;
+                self.iterations += 1;
+                continue;
+            }
+
+            let event = self.scenario.events[self.next_event].clone();
+
+            if event.at > elapsed {
+                return applied;
+            }
+
+            if let Err(err) = self.apply(&event.action).await {
+                error!("Scenario event {} failed: {}", self.next_event, err);
+            }
+
+            self.next_event += 1;
+            applied += 1;
+        }
+    }
+
+    fn shift_elapsed(&mut self, length: Duration) {
+        self.elapsed_before_pause = self.get_elapsed().saturating_sub(length);
+
+        if self.resumed_at.is_some() {
+            self.resumed_at = Some(self.clock.now());
+        }
+    }
+
+    async fn apply(&self, action: &ScenarioAction) -> Result<()> {
+        info!("Applying scenario event {:?}", action);
+
+        match action {
+            ScenarioAction::SetValue { value_id, value } => {
+                let mut app_state = self.app_state.lock().await;
+
+                let value_state = app_state
+                    .get_mut(value_id)
+                    .ok_or_else(|| anyhow!("Value {} not defined", value_id))?;
+
+                let registers = value_processing::value_to_registers(
+                    value.clone(),
+                    &value_state.config.formatting_params,
+                )?;
+
+                value_state.set_all_registers(registers);
+            }
+            ScenarioAction::SetFault { slave, rule } => {
+                let mut faults = self.faults.lock().await;
+                let rules = faults.entry(*slave).or_default();
+
+                match rules.iter_mut().find(|existing| existing.id == rule.id) {
+                    Some(existing) => *existing = rule.clone(),
+                    None => rules.push(rule.clone()),
+                }
+            }
+            ScenarioAction::RemoveFault { slave, fault } => {
+                if let Some(rules) = self.faults.lock().await.get_mut(slave) {
+                    rules.retain(|rule| rule.id != *fault);
+                }
+            }
+            ScenarioAction::ArmFault { slave, fault } => {
+                self.set_armed(*slave, fault, true).await?;
+            }
+            ScenarioAction::DisarmFault { slave, fault } => {
+                self.set_armed(*slave, fault, false).await?;
+            }
+            ScenarioAction::SetLatency { slave, latency } => {
+                self.latencies.lock().await.insert(*slave, latency.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn set_armed(&self, slave: u8, fault: &String, armed: bool) -> Result<()> {
+        let mut faults = self.faults.lock().await;
+
+        let rule = faults
+            .get_mut(&slave)
+            .and_then(|rules| rules.iter_mut().find(|rule| rule.id == *fault))
+            .ok_or_else(|| anyhow!("Fault {} not defined on slave {}", fault, slave))?;
+
+        rule.armed = armed;
+
+        Ok(())
+    }
+}
+
+pub fn start_scenario(runner: Arc<Mutex<ScenarioRunner>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let mut runner = runner.lock().await;
+
+            if runner.is_running() {
+                runner.step().await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::model::Value;
+    use crate::server::model::ServedConnection;
+    use crate::server::state;
+    use serde_json::json;
+
+    fn build_runner(
+        events: serde_json::Value,
+        repeat: bool,
+    ) -> (ScenarioRunner, Arc<VirtualClock>) {
+        let config: Vec<ServedConnection> = serde_json::from_value(json!([{
+            "port": 502,
+            "slaves": [{
+                "id": 1,
+                "values": [{
+                    "id": "level",
+                    "starting_address": 0,
+                    "table": "HoldingRegisters",
+                    "bit_length": 16,
+                    "data_type": "UnsignedInteger16",
+                    "default_value": { "Integer": 0 }
+                }]
+            }]
+        }]))
+        .unwrap();
+
+        let scenario: Scenario =
+            serde_json::from_value(json!({ "repeat": repeat, "events": events })).unwrap();
+        scenario.validate(&config).unwrap();
+
+        let clock = Arc::new(VirtualClock::new());
+
+        let runner = ScenarioRunner::new(
+            scenario,
+            clock.clone(),
+            state::build_app_state(&config).unwrap(),
+            state::build_slave_latencies(&config),
+            state::build_slave_faults(&config),
+        );
+
+        (runner, clock)
+    }
+
+    fn set_level(at: &str, level: i128) -> serde_json::Value {
+        json!({ "at": at, "action": "set_value", "value_id": "level", "value": { "Integer": level } })
+    }
+
+    async fn get_level(runner: &ScenarioRunner) -> Value {
+        let app_state = runner.app_state.lock().await;
+        let level = app_state.get("level").unwrap();
+
+        value_processing::registers_to_value(
+            level.get_all_registers(),
+            &level.config.formatting_params,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_are_applied_in_order_when_due() {
+        let events = json!([set_level("1s", 1), set_level("1s", 2), set_level("3s", 3)]);
+        let (mut runner, clock) = build_runner(events, false);
+
+        runner.start();
+        assert_eq!(runner.step().await, 0);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(runner.step().await, 2);
+        assert_eq!(get_level(&runner).await, Value::Integer(2));
+
+        clock.advance(Duration::from_millis(1499));
+        assert_eq!(runner.step().await, 0);
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(runner.step().await, 1);
+        assert_eq!(get_level(&runner).await, Value::Integer(3));
+
+        let progress = runner.get_progress();
+        assert!(progress.finished);
+        assert_eq!(progress.executed_events, 3);
+    }
+
+    #[tokio::test]
+    async fn paused_scenarios_keep_their_time_and_rewind_starts_over() {
+        let events = json!([set_level("1s", 1), set_level("2s", 2)]);
+        let (mut runner, clock) = build_runner(events, false);
+
+        runner.start();
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(runner.step().await, 1);
+
+        runner.pause();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(runner.step().await, 0);
+        assert_eq!(runner.get_elapsed(), Duration::from_millis(1500));
+
+        runner.start();
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(runner.step().await, 1);
+        assert_eq!(get_level(&runner).await, Value::Integer(2));
+
+        //The applied events are not undone
+        runner.rewind();
+        assert_eq!(runner.get_elapsed(), Duration::ZERO);
+        assert_eq!(runner.get_progress().executed_events, 0);
+        assert_eq!(get_level(&runner).await, Value::Integer(2));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(runner.step().await, 1);
+        assert_eq!(get_level(&runner).await, Value::Integer(1));
+    }
+
+    #[tokio::test]
+    async fn repeating_scenarios_keep_the_time_past_their_end() {
+        let events = json!([set_level("1s", 1), set_level("3s", 3)]);
+        let (mut runner, clock) = build_runner(events, true);
+
+        runner.start();
+        clock.advance(Duration::from_millis(3500));
+        assert_eq!(runner.step().await, 2);
+        assert_eq!(runner.get_elapsed(), Duration::from_millis(500));
+        assert_eq!(runner.get_progress().iterations, 1);
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(runner.step().await, 1);
+        assert_eq!(get_level(&runner).await, Value::Integer(1));
+
+        //Several lengths at once are all played
+        clock.advance(Duration::from_millis(6200));
+        assert_eq!(runner.step().await, 4);
+        assert_eq!(runner.get_elapsed(), Duration::from_millis(1200));
+        assert_eq!(runner.get_progress().iterations, 3);
+        assert!(!runner.get_progress().finished);
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use tweakable_modbus::ModbusDataType;
+
+use crate::common::model::{ModbusTable, ValueFormattingParams};
+use crate::server::state::{AppState, ValueState};
+
+//Named snapshots taken through the API, only kept in memory
+pub type NamedSnapshots = Arc<Mutex<HashMap<String, StateSnapshot>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredRegister {
+    Coil(bool),
+    Register(u16),
+}
+
+impl From<ModbusDataType> for StoredRegister {
+    fn from(register: ModbusDataType) -> Self {
+        match register {
+            ModbusDataType::Coil(coil) => StoredRegister::Coil(coil),
+            ModbusDataType::Register(register) => StoredRegister::Register(register),
+        }
+    }
+}
+
+impl From<StoredRegister> for ModbusDataType {
+    fn from(register: StoredRegister) -> Self {
+        match register {
+            StoredRegister::Coil(coil) => ModbusDataType::Coil(coil),
+            StoredRegister::Register(register) => ModbusDataType::Register(register),
+        }
+    }
+}
+
+//Everything that decides what the registers mean, a value is only restored if it hasn't changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueDefinition {
+    pub slave_id: u8,
+    pub table: ModbusTable,
+    pub starting_address: u16,
+    #[serde(flatten)]
+    pub formatting_params: ValueFormattingParams,
+}
+
+impl ValueDefinition {
+    fn from_state(value_state: &ValueState) -> Self {
+        ValueDefinition {
+            slave_id: value_state.starting_address.slave_id,
+            table: value_state.config.table.clone(),
+            starting_address: value_state.config.starting_address,
+            formatting_params: value_state.config.formatting_params.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredValue {
+    pub definition: ValueDefinition,
+    pub registers: Vec<StoredRegister>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub secs_since_epoch: u64,
+    pub values: HashMap<String, StoredValue>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreSummary {
+    pub restored: Vec<String>,
+    //Values whose definition changed or that aren't configured anymore
+    pub skipped: Vec<String>,
+}
+
+impl StateSnapshot {
+    pub fn take(app_state: &HashMap<String, ValueState>) -> Self {
+        let values = app_state
+            .iter()
+            .map(|(id, value_state)| {
+                let stored = StoredValue {
+                    definition: ValueDefinition::from_state(value_state),
+                    registers: value_state
+                        .get_all_registers()
+                        .into_iter()
+                        .map(StoredRegister::from)
+                        .collect(),
+                };
+
+                (id.clone(), stored)
+            })
+            .collect();
+
+        StateSnapshot {
+            secs_since_epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            values,
+        }
+    }
+
+    pub fn restore(&self, app_state: &mut HashMap<String, ValueState>) -> RestoreSummary {
+        let mut summary = RestoreSummary::default();
+
+        for (id, stored) in &self.values {
+            let Some(value_state) = app_state.get_mut(id) else {
+                summary.skipped.push(id.clone());
+                continue;
+            };
+
+            if ValueDefinition::from_state(value_state) != stored.definition
+                || value_state.get_all_registers().len() != stored.registers.len()
+            {
+                summary.skipped.push(id.clone());
+                continue;
+            }
+
+            value_state.set_all_registers(
+                stored
+                    .registers
+                    .iter()
+                    .map(|register| ModbusDataType::from(*register))
+                    .collect(),
+            );
+
+            summary.restored.push(id.clone());
+        }
+
+        summary.restored.sort();
+        summary.skipped.sort();
+
+        summary
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let snapshot = std::fs::read_to_string(path)?;
+
+        serde_json::from_str(&snapshot)
+            .map(Some)
+            .map_err(|err| anyhow!("Couldn't parse state file: {}", err))
+    }
+
+    //Written to a temporary file first so a crash never leaves a half written state file
+    pub fn save(&self, path: &PathBuf) -> Result<()> {
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        std::fs::write(&temporary, serde_json::to_string(self)?)?;
+        std::fs::rename(&temporary, path)?;
+
+        Ok(())
+    }
+}
+
+pub async fn save_state(path: &PathBuf, app_state: &AppState) -> Result<()> {
+    let snapshot = StateSnapshot::take(&*app_state.lock().await);
+    let path = path.clone();
+
+    tokio::task::spawn_blocking(move || snapshot.save(&path)).await?
+}
+
+pub async fn restore_state(path: &PathBuf, app_state: &AppState) -> Result<()> {
+    let Some(snapshot) = StateSnapshot::load(path)? else {
+        info!("State file {} doesn't exist yet", path.to_string_lossy());
+        return Ok(());
+    };
+
+    let summary = snapshot.restore(&mut *app_state.lock().await);
+
+    info!(
+        "Restored {} values from {}",
+        summary.restored.len(),
+        path.to_string_lossy()
+    );
+
+    if !summary.skipped.is_empty() {
+        warn!(
+            "Values changed since the state was saved, starting at their default value: {}",
+            summary.skipped.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+pub fn start_state_persistence(path: PathBuf, interval: Duration, app_state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        //The first tick is immediate and there is nothing new to save yet
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = save_state(&path, &app_state).await {
+                error!("Couldn't save state file: {}", err);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::model::ServedValue;
+    use tweakable_modbus::ModbusAddress;
+
+    fn build_state(values: serde_json::Value) -> HashMap<String, ValueState> {
+        let values: Vec<ServedValue> = serde_json::from_value(values).unwrap();
+
+        values
+            .into_iter()
+            .map(|value| {
+                let address = ModbusAddress {
+                    slave_id: 1,
+                    table: value.table.to_tweakable_modbus_table(),
+                    address: value.starting_address,
+                };
+
+                (
+                    value.id.clone(),
+                    ValueState::new(address, value.default_value.clone(), value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn value(id: &str, starting_address: u16, data_type: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "starting_address": starting_address,
+            "table": "HoldingRegisters",
+            "bit_length": 16,
+            "data_type": data_type,
+            "default_value": { "Integer": 1 }
+        })
+    }
+
+    fn get_registers(app_state: &HashMap<String, ValueState>, id: &str) -> Vec<StoredRegister> {
+        app_state[id]
+            .get_all_registers()
+            .into_iter()
+            .map(StoredRegister::from)
+            .collect()
+    }
+
+    #[test]
+    fn only_unchanged_values_are_restored() {
+        let mut app_state = build_state(serde_json::json!([
+            value("speed", 10, "UnsignedInteger16"),
+            value("mode", 11, "UnsignedInteger16"),
+            value("removed", 12, "UnsignedInteger16")
+        ]));
+
+        for id in ["speed", "mode", "removed"] {
+            app_state
+                .get_mut(id)
+                .unwrap()
+                .set_all_registers(vec![ModbusDataType::Register(42)]);
+        }
+
+        let snapshot = StateSnapshot::take(&app_state);
+
+        //The mode is now signed, its registers would mean something else
+        let mut app_state = build_state(serde_json::json!([
+            value("speed", 10, "UnsignedInteger16"),
+            value("mode", 11, "SignedInteger16")
+        ]));
+
+        let summary = snapshot.restore(&mut app_state);
+
+        assert_eq!(summary.restored, vec!["speed".to_string()]);
+        assert_eq!(
+            summary.skipped,
+            vec!["mode".to_string(), "removed".to_string()]
+        );
+
+        assert_eq!(
+            get_registers(&app_state, "speed"),
+            vec![StoredRegister::Register(42)]
+        );
+        assert_eq!(
+            get_registers(&app_state, "mode"),
+            vec![StoredRegister::Register(1)]
+        );
+    }
+}
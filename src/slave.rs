@@ -4,18 +4,50 @@ use modbus_watch::server::comm::ModbusServer;
 use tracing::error;
 
 use modbus_watch::common::logging::{init_logger, LogLevel};
+use modbus_watch::server::model::scenario::Scenario;
 use modbus_watch::server::model::ServedConnection;
+use modbus_watch::server::replay::{self, ReplayParams};
 use modbus_watch::server::state;
+use modbus_watch::server::state::scenario::{ScenarioRunner, SystemClock};
+use modbus_watch::server::state::{audit, snapshot};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
 
 #[derive(Parser)]
 struct Args {
-    config_file: std::path::PathBuf,
+    #[arg(required_unless_present = "replay_file")]
+    config_file: Option<std::path::PathBuf>,
     #[arg(long = "log-level", value_enum, default_value_t = LogLevel::Info)]
     log_level: LogLevel,
     #[arg(long = "log-file", default_value = "")]
     log_file: String,
     #[arg(long="port", default_value="8080")]
-    port: u16
+    port: u16,
+    #[arg(long = "scenario")]
+    scenario_file: Option<std::path::PathBuf>,
+    //Ultrabus db whose polls are played back into the served values
+    #[arg(long = "replay")]
+    replay_file: Option<std::path::PathBuf>,
+    #[arg(long = "replay-speed", default_value = "1.0")]
+    replay_speed: f64,
+    #[arg(long = "replay-loop")]
+    replay_loop: bool,
+    //Only used when the connections are built from the replayed db
+    #[arg(long = "replay-modbus-port", default_value = "502")]
+    replay_modbus_port: u16,
+    //Registers are restored from it on startup and saved to it periodically and on shutdown
+    #[arg(long = "state-file")]
+    state_file: Option<std::path::PathBuf>,
+    #[arg(long = "state-save-interval-secs", default_value = "10")]
+    state_save_interval_secs: u64,
+    //Writes kept in memory for the writes endpoints
+    #[arg(long = "write-history-size", default_value = "1000")]
+    write_history_size: usize,
+    //Every write is also appended to it as a JSON line
+    #[arg(long = "write-log-file")]
+    write_log_file: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
@@ -29,15 +61,32 @@ async fn main() {
         None
     };
 
-    let config = std::fs::read_to_string(&args.config_file).unwrap_or_else(|e| {
-        error!("Couldn't read config file: {}", e);
-        std::process::exit(1);
-    });
+    let config: Vec<ServedConnection> = match (&args.config_file, &args.replay_file) {
+        (Some(config_file), _) => {
+            let config = std::fs::read_to_string(config_file).unwrap_or_else(|e| {
+                error!("Couldn't read config file: {}", e);
+                std::process::exit(1);
+            });
 
-    let config: Vec<ServedConnection> = serde_json::from_str(&config).unwrap_or_else(|e| {
-        error!("Couldn't parse config file: {}", e);
+            serde_json::from_str(&config).unwrap_or_else(|e| {
+                error!("Couldn't parse config file: {}", e);
+                std::process::exit(1);
+            })
+        }
+        (None, Some(replay_file)) => {
+            replay::build_config_from_capture(replay_file, args.replay_modbus_port)
+                .unwrap_or_else(|e| {
+                    error!("Couldn't build config from the replayed db: {}", e);
+                    std::process::exit(1);
+                })
+        }
+        (None, None) => unreachable!(),
+    };
+
+    if !(args.replay_speed > 0.0 && args.replay_speed.is_finite()) {
+        error!("Replay speed must be greater than 0");
         std::process::exit(1);
-    });
+    }
 
     for connection in &config {
         if let Err(err) = connection.validate() {
@@ -46,15 +95,129 @@ async fn main() {
         }
     }
 
-    let app_state = state::build_app_state(&config);
+    let app_state = state::build_app_state(&config).unwrap_or_else(|e| {
+        error!("Wrong config:\n{}", e);
+        std::process::exit(1);
+    });
+    let latencies = state::build_slave_latencies(&config);
+    let faults = state::build_slave_faults(&config);
+
+    let writes = audit::build_write_audit(args.write_history_size, args.write_log_file.as_ref())
+        .unwrap_or_else(|e| {
+            error!("Couldn't open write log file: {}", e);
+            std::process::exit(1);
+        });
+
+    if args.state_save_interval_secs == 0 {
+        error!("State save interval must be greater than 0");
+        std::process::exit(1);
+    }
+
+    if let Some(state_file) = &args.state_file {
+        if let Err(err) = snapshot::restore_state(state_file, &app_state).await {
+            error!("Couldn't restore state file: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let scenario_runner = args.scenario_file.map(|scenario_file| {
+        let scenario = std::fs::read_to_string(&scenario_file).unwrap_or_else(|e| {
+            error!("Couldn't read scenario file: {}", e);
+            std::process::exit(1);
+        });
+
+        let scenario: Scenario = serde_json::from_str(&scenario).unwrap_or_else(|e| {
+            error!("Couldn't parse scenario file: {}", e);
+            std::process::exit(1);
+        });
+
+        if let Err(err) = scenario.validate(&config) {
+            error!("Wrong scenario:\n{}", err);
+            std::process::exit(1);
+        }
 
-    serve_api(app_state.clone(), args.port).await;
+        let autostart = scenario.autostart;
 
-    let modbus_server = ModbusServer::new(&config, app_state.clone());
+        let mut runner = ScenarioRunner::new(
+            scenario,
+            Arc::new(SystemClock::new()),
+            app_state.clone(),
+            latencies.clone(),
+            faults.clone(),
+        );
+
+        if autostart {
+            runner.start();
+        }
+
+        Arc::new(Mutex::new(runner))
+    });
+
+    serve_api(
+        app_state.clone(),
+        latencies.clone(),
+        faults.clone(),
+        scenario_runner.clone(),
+        Arc::new(Mutex::new(HashMap::new())),
+        writes.clone(),
+        args.port,
+    )
+    .await;
+
+    let modbus_server = ModbusServer::new(&config, app_state.clone(), latencies, faults, writes);
 
     modbus_server.serve();
 
-    tokio::signal::ctrl_c().await.unwrap();
+    state::generator::start_generators(&config, app_state.clone());
+
+    if let Some(replay_file) = args.replay_file {
+        replay::start_replay(
+            replay_file,
+            ReplayParams {
+                speed: args.replay_speed,
+                repeat: args.replay_loop,
+            },
+            app_state.clone(),
+        );
+    }
+
+    if let Some(scenario_runner) = scenario_runner {
+        state::scenario::start_scenario(scenario_runner);
+    }
+
+    if let Some(state_file) = &args.state_file {
+        snapshot::start_state_persistence(
+            state_file.clone(),
+            Duration::from_secs(args.state_save_interval_secs),
+            app_state.clone(),
+        );
+    }
+
+    wait_for_shutdown().await;
+
+    if let Some(state_file) = &args.state_file {
+        if let Err(err) = snapshot::save_state(state_file, &app_state).await {
+            error!("Couldn't save state file: {}", err);
+        }
+    }
 
     std::process::exit(0);
 }
+
+//Service managers and containers stop the slave with SIGTERM, the state must be saved then too
+#[cfg(unix)]
+async fn wait_for_shutdown() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).unwrap();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown() {
+    tokio::signal::ctrl_c().await.unwrap();
+}
@@ -0,0 +1,263 @@
+//Each test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use modbus_watch::common::protocol::mbap::{self, MbapFrame};
+use modbus_watch::common::protocol::pdu::{Request, Response};
+use modbus_watch::server::api::serve_api;
+use modbus_watch::server::comm::ModbusServer;
+use modbus_watch::server::model::ServedConnection;
+use modbus_watch::server::state::{self, audit, AppState, SlaveFaults, SlaveLatencies};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+pub fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+pub async fn wait_for_port(port: u16) {
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+    for _ in 0..100 {
+        if TcpStream::connect(address).await.is_ok() {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("Slave didn't start listening on {}", port);
+}
+
+pub struct TestSlave {
+    pub app_state: AppState,
+    pub latencies: SlaveLatencies,
+    pub faults: SlaveFaults,
+    pub modbus_port: u16,
+    pub api_port: u16,
+}
+
+pub fn slave(id: u8, values: Vec<serde_json::Value>) -> serde_json::Value {
+    json!({ "id": id, "values": values })
+}
+
+fn value(
+    id: &str,
+    starting_address: u16,
+    table: &str,
+    data_type: &str,
+    default_value: serde_json::Value,
+) -> serde_json::Value {
+    let bit_length = if data_type == "Boolean" { 1 } else { 16 };
+
+    json!({
+        "id": id,
+        "starting_address": starting_address,
+        "table": table,
+        "bit_length": bit_length,
+        "data_type": data_type,
+        "default_value": default_value
+    })
+}
+
+pub fn holding_u16(id: &str, starting_address: u16, default_value: u16) -> serde_json::Value {
+    value(
+        id,
+        starting_address,
+        "HoldingRegisters",
+        "UnsignedInteger16",
+        json!({ "Integer": default_value }),
+    )
+}
+
+pub fn input_u16(id: &str, starting_address: u16, default_value: u16) -> serde_json::Value {
+    value(
+        id,
+        starting_address,
+        "InputRegisters",
+        "UnsignedInteger16",
+        json!({ "Integer": default_value }),
+    )
+}
+
+pub fn coil(id: &str, starting_address: u16, default_value: bool) -> serde_json::Value {
+    value(
+        id,
+        starting_address,
+        "Coils",
+        "Boolean",
+        json!({ "Boolean": default_value }),
+    )
+}
+
+pub fn discrete_input(id: &str, starting_address: u16, default_value: bool) -> serde_json::Value {
+    value(
+        id,
+        starting_address,
+        "DiscreteInput",
+        "Boolean",
+        json!({ "Boolean": default_value }),
+    )
+}
+
+//Adds the fields a test is about to a slave or value, replacing the ones already there
+pub fn with_fields(mut object: serde_json::Value, fields: serde_json::Value) -> serde_json::Value {
+    let fields = fields.as_object().unwrap().clone();
+
+    object.as_object_mut().unwrap().extend(fields);
+
+    object
+}
+
+//Serves the slaves over Modbus/TCP along with their API
+pub async fn start_slave(slaves: serde_json::Value) -> TestSlave {
+    let modbus_port = get_free_port();
+    let api_port = get_free_port();
+
+    let config: Vec<ServedConnection> = serde_json::from_value(json!([{
+        "port": modbus_port,
+        "slaves": slaves
+    }]))
+    .unwrap();
+
+    for connection in &config {
+        connection.validate().unwrap();
+    }
+
+    let app_state = state::build_app_state(&config).unwrap();
+    let latencies = state::build_slave_latencies(&config);
+    let faults = state::build_slave_faults(&config);
+    let writes = audit::build_write_audit(10, None).unwrap();
+
+    serve_api(
+        app_state.clone(),
+        latencies.clone(),
+        faults.clone(),
+        None,
+        Arc::new(Mutex::new(HashMap::new())),
+        writes.clone(),
+        api_port,
+    )
+    .await;
+
+    ModbusServer::new(
+        &config,
+        app_state.clone(),
+        latencies.clone(),
+        faults.clone(),
+        writes,
+    )
+    .serve();
+
+    wait_for_port(modbus_port).await;
+
+    TestSlave {
+        app_state,
+        latencies,
+        faults,
+        modbus_port,
+        api_port,
+    }
+}
+
+//Speaks Modbus/TCP frame by frame, so every answer of the slave can be checked as it was sent
+pub struct RawMaster {
+    stream: TcpStream,
+    transaction_id: u16,
+}
+
+impl RawMaster {
+    pub async fn connect(port: u16) -> Self {
+        RawMaster {
+            stream: TcpStream::connect(("127.0.0.1", port)).await.unwrap(),
+            transaction_id: 0,
+        }
+    }
+
+    pub async fn send(&mut self, unit_id: u8, request: &Request) -> u16 {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+
+        let frame = mbap::encode_frame(self.transaction_id, unit_id, &request.encode());
+        self.stream.write_all(&frame).await.unwrap();
+
+        self.transaction_id
+    }
+
+    //None if the slave didn't answer in time, errors if it closed the connection
+    pub async fn receive(&mut self, timeout: Duration) -> Option<anyhow::Result<MbapFrame>> {
+        let frame = tokio::time::timeout(timeout, mbap::read_stream_frame(&mut self.stream))
+            .await
+            .ok()?;
+
+        Some(frame.and_then(|frame| mbap::decode_frame(&frame)))
+    }
+
+    pub async fn request(&mut self, unit_id: u8, request: &Request) -> Response {
+        let transaction_id = self.send(unit_id, request).await;
+
+        let frame = self
+            .receive(Duration::from_secs(5))
+            .await
+            .expect("The slave didn't answer")
+            .unwrap();
+
+        assert_eq!(frame.transaction_id, transaction_id);
+        assert_eq!(frame.unit_id, unit_id);
+
+        Response::decode(request, &frame.pdu).unwrap()
+    }
+
+    pub async fn read_holding_registers(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        ammount: u16,
+    ) -> Vec<u16> {
+        match self
+            .request(unit_id, &Request::ReadHoldingRegisters { address, ammount })
+            .await
+        {
+            Response::Registers(registers) => registers,
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+}
+
+//Bare HTTP/1.1 request to the API, returns the status code and the body
+pub async fn http(
+    port: u16,
+    method: &str,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> (u16, String) {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    );
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+    (status, body.to_string())
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use modbus_watch::client::comm::ModbusWatcher;
+use modbus_watch::client::data::{InsertValueMessage, PollQuality};
+use modbus_watch::client::model::PolledConnection;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+//Accepts every connection and never answers, aborting it closes them and refuses new ones
+async fn start_silent_slave() -> (u16, JoinHandle<()>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let slave = tokio::spawn(async move {
+        let mut streams = vec![];
+
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    (port, slave)
+}
+
+fn build_master(
+    port: u16,
+    min_reconnect_delay: &str,
+) -> (ModbusWatcher, mpsc::Receiver<InsertValueMessage>) {
+    let config: Vec<PolledConnection> = serde_json::from_value(json!([{
+        "config": {
+            "max_simultaneous_connections": 1,
+            "max_response_time": "200ms",
+            "min_reconnect_delay": min_reconnect_delay,
+            "max_reconnect_delay": "60s"
+        },
+        "port": port,
+        "slaves": [{
+            "id": 1,
+            "values": [{
+                "id": "setpoint",
+                "starting_address": 10,
+                "table": "HoldingRegisters",
+                "bit_length": 16,
+                "data_type": "UnsignedInteger16",
+                "poll_time": "100ms"
+            }]
+        }]
+    }]))
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel(64);
+
+    (ModbusWatcher::new(config, tx), rx)
+}
+
+async fn next_poll(rx: &mut mpsc::Receiver<InsertValueMessage>) -> InsertValueMessage {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("No poll was received")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn slow_slaves_dont_arm_the_backoff() {
+    let (port, _slave) = start_silent_slave().await;
+
+    let (mut watcher, mut rx) = build_master(port, "10s");
+    watcher.watch().await.unwrap();
+
+    let poll = next_poll(&mut rx).await;
+    assert_eq!(poll.quality, PollQuality::Timeout);
+    assert!(poll.value.is_empty());
+
+    let health = watcher.get_health()[0].lock().await.clone();
+
+    assert!(!health.is_backing_off());
+    assert_eq!(health.next_attempt, None);
+}
+
+#[tokio::test]
+async fn refused_connections_arm_the_backoff() {
+    let port = common::get_free_port();
+
+    let (mut watcher, mut rx) = build_master(port, "10s");
+    watcher.watch().await.unwrap();
+
+    let poll = next_poll(&mut rx).await;
+    assert_eq!(poll.quality, PollQuality::ConnectionError);
+    assert!(poll.value.is_empty());
+
+    let health = watcher.get_health()[0].lock().await.clone();
+
+    assert!(health.is_backing_off());
+    assert!(health.last_error.is_some());
+
+    //Polls keep being stored while waiting to reconnect, without trying again
+    for _ in 0..3 {
+        let poll = next_poll(&mut rx).await;
+        assert_eq!(poll.quality, PollQuality::ConnectionError);
+    }
+
+    assert_eq!(watcher.get_health()[0].lock().await.consecutive_failures, 1);
+}
+
+#[tokio::test]
+async fn timeouts_before_a_refusal_dont_grow_the_backoff() {
+    let (port, slave) = start_silent_slave().await;
+
+    let (mut watcher, mut rx) = build_master(port, "1s");
+    watcher.watch().await.unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(next_poll(&mut rx).await.quality, PollQuality::Timeout);
+    }
+
+    //The open connection is closed and reconnecting is refused
+    slave.abort();
+
+    let mut polls = 0;
+
+    while !watcher.get_health()[0].lock().await.is_backing_off() {
+        assert!(polls < 20, "The refused connection didn't arm the backoff");
+
+        next_poll(&mut rx).await;
+        polls += 1;
+    }
+
+    let health = watcher.get_health()[0].lock().await.clone();
+    assert!(health.consecutive_failures > 3);
+
+    //The first refusal waits the minimum delay, no matter how many timeouts came before
+    let backoff = health
+        .next_attempt
+        .unwrap()
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    assert!(backoff <= Duration::from_secs(1), "{:?}", backoff);
+}
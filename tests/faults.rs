@@ -0,0 +1,207 @@
+mod common;
+
+use std::time::Duration;
+
+use common::RawMaster;
+use modbus_watch::common::protocol::pdu::{Request, Response};
+use serde_json::json;
+
+const READ_SPEED: Request = Request::ReadHoldingRegisters {
+    address: 10,
+    ammount: 1,
+};
+
+async fn start_faulty_slave(faults: serde_json::Value) -> common::TestSlave {
+    let slave = common::slave(
+        1,
+        vec![
+            common::holding_u16("speed", 10, 100),
+            common::holding_u16("energy", 20, 7),
+        ],
+    );
+
+    common::start_slave(json!([common::with_fields(
+        slave,
+        json!({ "faults": faults })
+    )]))
+    .await
+}
+
+#[tokio::test]
+async fn exception_rules_only_apply_to_their_addresses() {
+    let slave = start_faulty_slave(json!([{
+        "id": "busy",
+        "action": { "type": "exception", "exception_code": 6 },
+        "table": "HoldingRegisters",
+        "addresses": { "start": 20, "end": 29 }
+    }]))
+    .await;
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+
+    let energy = Request::ReadHoldingRegisters {
+        address: 20,
+        ammount: 1,
+    };
+    assert_eq!(master.request(1, &energy).await, Response::Exception(6));
+
+    //Any request reaching into the range triggers it
+    let overlapping = Request::ReadHoldingRegisters {
+        address: 18,
+        ammount: 3,
+    };
+    assert_eq!(
+        master.request(1, &overlapping).await,
+        Response::Exception(6)
+    );
+
+    //Same addresses in another table
+    let inputs = Request::ReadInputRegisters {
+        address: 20,
+        ammount: 1,
+    };
+    assert_eq!(master.request(1, &inputs).await, Response::Exception(2));
+}
+
+#[tokio::test]
+async fn one_shot_rules_are_armed_and_disarmed_through_the_api() {
+    let slave = start_faulty_slave(json!([{
+        "id": "failure",
+        "action": { "type": "exception", "exception_code": 4 },
+        "value": "speed",
+        "one_shot": true,
+        "armed": false
+    }]))
+    .await;
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+
+    let (status, _) = common::http(
+        slave.api_port,
+        "POST",
+        "/api/v1/slaves/1/faults/failure/arm",
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    assert_eq!(master.request(1, &READ_SPEED).await, Response::Exception(4));
+
+    //It disarmed itself after triggering
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+
+    let (status, body) = common::http(slave.api_port, "GET", "/api/v1/slaves/1/faults", None).await;
+    assert_eq!(status, 200);
+
+    let rules: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(rules[0]["armed"], json!(false));
+
+    //Disarming before it triggers keeps it from doing so
+    common::http(
+        slave.api_port,
+        "POST",
+        "/api/v1/slaves/1/faults/failure/arm",
+        None,
+    )
+    .await;
+    let (status, _) = common::http(
+        slave.api_port,
+        "POST",
+        "/api/v1/slaves/1/faults/failure/disarm",
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+
+    let (status, _) = common::http(
+        slave.api_port,
+        "POST",
+        "/api/v1/slaves/1/faults/missing/arm",
+        None,
+    )
+    .await;
+    assert_eq!(status, 404);
+}
+
+//Adds a rule that triggers on the next request
+async fn add_one_shot_fault(slave: &common::TestSlave, action: serde_json::Value) {
+    let (status, body) = common::http(
+        slave.api_port,
+        "PUT",
+        "/api/v1/slaves/1/faults/next",
+        Some(json!({
+            "id": "next",
+            "action": action,
+            "one_shot": true
+        })),
+    )
+    .await;
+
+    assert_eq!(status, 200, "{}", body);
+}
+
+#[tokio::test]
+async fn frame_faults_change_what_the_wire_returns() {
+    let slave = start_faulty_slave(json!([])).await;
+    let timeout = Duration::from_millis(500);
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    add_one_shot_fault(&slave, json!({ "type": "wrong_transaction_id" })).await;
+
+    let transaction_id = master.send(1, &READ_SPEED).await;
+    let frame = master.receive(timeout).await.unwrap().unwrap();
+    assert_eq!(frame.transaction_id, transaction_id.wrapping_add(1));
+    assert_eq!(frame.unit_id, 1);
+    assert_eq!(
+        Response::decode(&READ_SPEED, &frame.pdu).unwrap(),
+        Response::Registers(vec![100])
+    );
+
+    add_one_shot_fault(&slave, json!({ "type": "wrong_unit_id" })).await;
+
+    let transaction_id = master.send(1, &READ_SPEED).await;
+    let frame = master.receive(timeout).await.unwrap().unwrap();
+    assert_eq!(frame.transaction_id, transaction_id);
+    assert_eq!(frame.unit_id, 2);
+
+    add_one_shot_fault(&slave, json!({ "type": "no_response" })).await;
+
+    master.send(1, &READ_SPEED).await;
+    assert!(master.receive(timeout).await.is_none());
+
+    //The connection is still usable
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+
+    add_one_shot_fault(&slave, json!({ "type": "close_connection" })).await;
+
+    master.send(1, &READ_SPEED).await;
+    assert!(master.receive(timeout).await.unwrap().is_err());
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+    assert_eq!(
+        master.request(1, &READ_SPEED).await,
+        Response::Registers(vec![100])
+    );
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::RawMaster;
+use modbus_watch::common::protocol::pdu::{Request, Response};
+use serde_json::json;
+
+const READ_SPEED: Request = Request::ReadHoldingRegisters {
+    address: 10,
+    ammount: 1,
+};
+
+const READ_LEVEL: Request = Request::ReadInputRegisters {
+    address: 20,
+    ammount: 1,
+};
+
+const DELAY: Duration = Duration::from_millis(400);
+
+async fn start_slow_slave(response_delay: Option<&str>) -> common::TestSlave {
+    let slave = common::slave(
+        1,
+        vec![
+            common::holding_u16("speed", 10, 100),
+            common::input_u16("level", 20, 5),
+        ],
+    );
+
+    common::start_slave(json!([common::with_fields(
+        slave,
+        json!({ "response_delay": response_delay })
+    )]))
+    .await
+}
+
+//Time the slave took to answer the request
+async fn time_request(master: &mut RawMaster, request: &Request) -> Duration {
+    let start = Instant::now();
+    let response = master.request(1, request).await;
+
+    assert!(matches!(response, Response::Registers(_)));
+
+    start.elapsed()
+}
+
+#[tokio::test]
+async fn configured_response_delays_are_applied() {
+    let slave = start_slow_slave(Some("400ms")).await;
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    assert!(time_request(&mut master, &READ_SPEED).await >= DELAY);
+    assert!(time_request(&mut master, &READ_LEVEL).await >= DELAY);
+}
+
+#[tokio::test]
+async fn latency_is_changed_through_the_api() {
+    let slave = start_slow_slave(None).await;
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    assert!(time_request(&mut master, &READ_SPEED).await < DELAY);
+
+    //Only reading input registers is slow
+    let latency = json!({
+        "type": "fixed",
+        "delay": "0s",
+        "function_codes": {
+            "4": { "type": "fixed", "delay": "400ms" }
+        }
+    });
+
+    let (status, body) = common::http(
+        slave.api_port,
+        "PUT",
+        "/api/v1/slaves/1/latency",
+        Some(latency.clone()),
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+
+    assert!(time_request(&mut master, &READ_SPEED).await < DELAY);
+    assert!(time_request(&mut master, &READ_LEVEL).await >= DELAY);
+
+    let (status, body) =
+        common::http(slave.api_port, "GET", "/api/v1/slaves/1/latency", None).await;
+    assert_eq!(status, 200);
+
+    let current: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(current["function_codes"]["4"]["type"], json!("fixed"));
+
+    //Rejected changes keep the current latency
+    let (status, _) = common::http(
+        slave.api_port,
+        "PUT",
+        "/api/v1/slaves/1/latency",
+        Some(json!({ "type": "uniform", "min": "1s", "max": "10ms" })),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (status, _) = common::http(
+        slave.api_port,
+        "PUT",
+        "/api/v1/slaves/9/latency",
+        Some(latency),
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    assert!(time_request(&mut master, &READ_LEVEL).await >= DELAY);
+
+    let (status, _) = common::http(
+        slave.api_port,
+        "PUT",
+        "/api/v1/slaves/1/latency",
+        Some(json!(null)),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    assert!(time_request(&mut master, &READ_LEVEL).await < DELAY);
+}
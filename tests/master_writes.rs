@@ -0,0 +1,204 @@
+mod common;
+
+use std::time::Duration;
+
+use common::RawMaster;
+use modbus_watch::client::api::serve_api;
+use modbus_watch::client::comm::ModbusWatcher;
+use modbus_watch::client::data::{DbBatchParams, DbManager};
+use modbus_watch::client::model::PolledConnection;
+use serde_json::json;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+async fn start_test_slave() -> common::TestSlave {
+    let values = vec![
+        common::holding_u16("flags", 40, 65535),
+        common::with_fields(
+            common::holding_u16("setpoint", 41, 10),
+            json!({ "max": 100 }),
+        ),
+        common::with_fields(
+            common::holding_u16("locked", 42, 3),
+            json!({ "read_only": true }),
+        ),
+        common::input_u16("level", 20, 5),
+    ];
+
+    common::start_slave(json!([common::slave(1, values)])).await
+}
+
+//Serves the master API without polling, writes go straight to the slave
+async fn start_master(slave_port: u16, dir: &TempDir) -> u16 {
+    let value =
+        |id: &str, starting_address: u16, table: &str, starting_bit: u8, bit_length: u16| {
+            json!({
+                "id": id,
+                "starting_address": starting_address,
+                "table": table,
+                "starting_bit": starting_bit,
+                "bit_length": bit_length,
+                "data_type": "UnsignedInteger16",
+                "poll_time": "1s"
+            })
+        };
+
+    let config: Vec<PolledConnection> = serde_json::from_value(json!([{
+        "config": {
+            "max_simultaneous_connections": 1,
+            "max_response_time": "1s"
+        },
+        "port": slave_port,
+        "slaves": [{
+            "id": 1,
+            "values": [
+                //Bits 4 to 7 of the flags register
+                value("mode", 40, "HoldingRegisters", 4, 4),
+                value("setpoint", 41, "HoldingRegisters", 0, 16),
+                value("locked", 42, "HoldingRegisters", 0, 16),
+                value("level", 20, "InputRegisters", 0, 16)
+            ]
+        }]
+    }]))
+    .unwrap();
+
+    for connection in &config {
+        connection.validate().unwrap();
+    }
+
+    let (tx, rx) = mpsc::channel(64);
+
+    let db = DbManager::new(
+        dir.path().join("master.db3"),
+        &config,
+        rx,
+        DbBatchParams {
+            max_batch_size: 16,
+            max_batch_delay: Duration::from_millis(100),
+        },
+    )
+    .unwrap();
+
+    let watcher = ModbusWatcher::new(config.clone(), tx);
+    let api_port = common::get_free_port();
+
+    serve_api(
+        config,
+        db.get_db(),
+        watcher.get_writer(),
+        watcher.get_health(),
+        db.get_metrics(),
+        db.get_stream(),
+        api_port,
+    )
+    .await;
+
+    api_port
+}
+
+#[tokio::test]
+async fn bitfield_writes_keep_the_neighbouring_bits() {
+    let dir = TempDir::new().unwrap();
+    let slave = start_test_slave().await;
+    let api_port = start_master(slave.modbus_port, &dir).await;
+
+    let mut reader = RawMaster::connect(slave.modbus_port).await;
+
+    let (status, body) = common::http(
+        api_port,
+        "PUT",
+        "/values/mode",
+        Some(json!({ "Integer": 0 })),
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(reader.read_holding_registers(1, 40, 1).await, vec![0xFF0F]);
+
+    let (status, body) = common::http(
+        api_port,
+        "PUT",
+        "/values/mode",
+        Some(json!({ "Integer": 9 })),
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(reader.read_holding_registers(1, 40, 1).await, vec![0xFF9F]);
+
+    //Doesn't fit in 4 bits, nothing is sent
+    let (status, _) = common::http(
+        api_port,
+        "PUT",
+        "/values/mode",
+        Some(json!({ "Integer": 16 })),
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(reader.read_holding_registers(1, 40, 1).await, vec![0xFF9F]);
+
+    let (status, body) = common::http(
+        api_port,
+        "PUT",
+        "/values/setpoint",
+        Some(json!({ "Integer": 50 })),
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(reader.read_holding_registers(1, 41, 1).await, vec![50]);
+}
+
+#[tokio::test]
+async fn input_tables_are_not_writable() {
+    let dir = TempDir::new().unwrap();
+    let slave = start_test_slave().await;
+    let api_port = start_master(slave.modbus_port, &dir).await;
+
+    let (status, _) = common::http(
+        api_port,
+        "PUT",
+        "/values/level",
+        Some(json!({ "Integer": 7 })),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (status, _) = common::http(
+        api_port,
+        "PUT",
+        "/values/unknown",
+        Some(json!({ "Integer": 7 })),
+    )
+    .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn slave_exceptions_are_passed_through() {
+    let dir = TempDir::new().unwrap();
+    let slave = start_test_slave().await;
+    let api_port = start_master(slave.modbus_port, &dir).await;
+
+    let mut reader = RawMaster::connect(slave.modbus_port).await;
+
+    //Above the maximum the slave accepts
+    let (status, body) = common::http(
+        api_port,
+        "PUT",
+        "/values/setpoint",
+        Some(json!({ "Integer": 200 })),
+    )
+    .await;
+    assert_eq!(status, 502);
+    assert!(body.contains("IllegalDataValue"), "{}", body);
+    assert_eq!(reader.read_holding_registers(1, 41, 1).await, vec![10]);
+
+    let (status, body) = common::http(
+        api_port,
+        "PUT",
+        "/values/locked",
+        Some(json!({ "Integer": 4 })),
+    )
+    .await;
+    assert_eq!(status, 502);
+    assert!(body.contains("IllegalDataAddress"), "{}", body);
+    assert_eq!(reader.read_holding_registers(1, 42, 1).await, vec![3]);
+}
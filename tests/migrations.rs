@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::time::Duration;
+
+use modbus_watch::client::data::{DbBatchParams, DbManager};
+use rusqlite::{params, Connection};
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+//Schema left behind by the releases before the database was versioned
+const UNVERSIONED_SCHEMA: &str = "
+    CREATE TABLE modbus_values (
+        name TEXT PRIMARY KEY,
+        address INTEGER NOT NULL,
+        modbus_table TEXT NOT NULL,
+        slave_id INTEGER NOT NULL,
+        config TEXT
+    );
+    CREATE TABLE modbus_polls (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        value_id TEXT NOT NULL REFERENCES modbus_values(name),
+        timestamp INTEGER NOT NULL,
+        value blob
+    );
+    CREATE TABLE modbus_aggregates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        value_id TEXT NOT NULL REFERENCES modbus_values(name),
+        period INTEGER NOT NULL,
+        start INTEGER NOT NULL,
+        finish INTEGER NOT NULL,
+        average blob,
+        median blob,
+        moda blob,
+        min blob,
+        max blob,
+        ammount INTEGER
+    );";
+
+//Name and declared type of every column
+fn get_columns(conn: &Connection, table: &str) -> Vec<(String, String)> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .unwrap();
+
+    stmt.query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str, column_type: &str) -> bool {
+    get_columns(conn, table)
+        .iter()
+        .any(|(name, declared_type)| name == column && declared_type == column_type)
+}
+
+fn get_applied_versions(conn: &Connection) -> Vec<u32> {
+    let mut stmt = conn
+        .prepare("SELECT version FROM schema_version ORDER BY version")
+        .unwrap();
+
+    stmt.query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+fn assert_latest_schema(path: &Path, latest_version: u32) {
+    let conn = Connection::open(path).unwrap();
+
+    assert_eq!(
+        get_applied_versions(&conn),
+        (1..=latest_version).collect::<Vec<_>>()
+    );
+
+    assert!(has_column(&conn, "modbus_polls", "quality", "INTEGER"));
+    assert!(has_column(&conn, "modbus_polls", "exception_code", "INTEGER"));
+    assert!(has_column(&conn, "modbus_polls", "timestamp_ms", "INTEGER"));
+    assert!(has_column(&conn, "modbus_aggregates", "excluded", "INTEGER"));
+    assert!(has_column(&conn, "modbus_aggregates", "states", "TEXT"));
+}
+
+#[test]
+fn fresh_databases_get_every_migration() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("fresh.db3");
+
+    let (previous_version, latest_version) = DbManager::migrate_only(path.clone()).unwrap();
+
+    assert_eq!(previous_version, 0);
+    assert_latest_schema(&path, latest_version);
+
+    //Running it again has nothing left to do
+    assert_eq!(
+        DbManager::migrate_only(path.clone()).unwrap(),
+        (latest_version, latest_version)
+    );
+    assert_latest_schema(&path, latest_version);
+}
+
+#[test]
+fn unversioned_databases_are_upgraded_keeping_their_polls() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("unversioned.db3");
+
+    {
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(UNVERSIONED_SCHEMA).unwrap();
+
+        conn.execute(
+            "INSERT INTO modbus_values (name, address, modbus_table, slave_id) VALUES (?, ?, ?, ?)",
+            params!["speed", 100, "HoldingRegisters", 1],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO modbus_polls (value_id, timestamp, value) VALUES (?, ?, ?)",
+            params!["speed", 1_700_000_000u64, vec![0xE8u8, 0x03]],
+        )
+        .unwrap();
+    }
+
+    let (previous_version, latest_version) = DbManager::migrate_only(path.clone()).unwrap();
+
+    assert_eq!(previous_version, 0);
+    assert_latest_schema(&path, latest_version);
+
+    let conn = Connection::open(&path).unwrap();
+
+    let (timestamp_ms, value, quality, exception_code): (u64, Vec<u8>, u8, Option<u8>) = conn
+        .query_row(
+            "SELECT timestamp_ms, value, quality, exception_code FROM modbus_polls WHERE value_id = ?",
+            ["speed"],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+
+    assert_eq!(timestamp_ms, 1_700_000_000_000);
+    assert_eq!(value, vec![0xE8, 0x03]);
+    assert_eq!(quality, 0);
+    assert_eq!(exception_code, None);
+}
+
+#[tokio::test]
+async fn newer_databases_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("newer.db3");
+
+    let (_, latest_version) = DbManager::migrate_only(path.clone()).unwrap();
+
+    Connection::open(&path)
+        .unwrap()
+        .execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)",
+            params![latest_version + 1, "From the future", 0],
+        )
+        .unwrap();
+
+    assert!(DbManager::migrate_only(path.clone()).is_err());
+
+    let (_tx, rx) = mpsc::channel(16);
+    let batch_params = DbBatchParams {
+        max_batch_size: 16,
+        max_batch_delay: Duration::from_millis(100),
+    };
+
+    assert!(DbManager::new(path.clone(), &vec![], rx, batch_params).is_err());
+
+    //Nothing was touched
+    let conn = Connection::open(&path).unwrap();
+    assert_eq!(
+        get_applied_versions(&conn),
+        (1..=latest_version + 1).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use common::RawMaster;
+use modbus_watch::common::protocol::pdu::{Request, Response};
+use serde_json::json;
+
+async fn start_register_slave() -> common::TestSlave {
+    let values = vec![
+        common::holding_u16("speed", 10, 100),
+        json!({
+            "id": "energy",
+            "starting_address": 11,
+            "table": "HoldingRegisters",
+            "bit_length": 32,
+            "data_type": "UnsignedInteger32",
+            "byte_order": "ABCD",
+            "default_value": { "Integer": 70000 }
+        }),
+        common::coil("pump", 3, true),
+        common::discrete_input("running", 4, false),
+    ];
+
+    common::start_slave(json!([common::slave(1, values)])).await
+}
+
+async fn get_json(slave: &common::TestSlave, path: &str) -> (u16, serde_json::Value) {
+    let (status, body) = common::http(slave.api_port, "GET", path, None).await;
+
+    (status, serde_json::from_str(&body).unwrap_or_default())
+}
+
+async fn put_registers(slave: &common::TestSlave, path: &str, registers: serde_json::Value) -> u16 {
+    common::http(slave.api_port, "PUT", path, Some(registers))
+        .await
+        .0
+}
+
+//70000 is 0x00011170, the high word goes first
+fn unchanged_holding_registers() -> serde_json::Value {
+    json!([
+        { "address": 9, "value": null, "value_id": null },
+        { "address": 10, "value": 100, "value_id": "speed" },
+        { "address": 11, "value": 1, "value_id": "energy" },
+        { "address": 12, "value": 0x1170, "value_id": "energy" },
+        { "address": 13, "value": null, "value_id": null }
+    ])
+}
+
+#[tokio::test]
+async fn reads_cross_values_and_mark_unmapped_addresses() {
+    let slave = start_register_slave().await;
+
+    let (status, registers) =
+        get_json(&slave, "/api/v1/slaves/1/holding_registers?start=9&count=5").await;
+    assert_eq!(status, 200);
+    assert_eq!(registers, unchanged_holding_registers());
+
+    let (status, registers) = get_json(&slave, "/api/v1/slaves/1/coils?start=3&count=2").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        registers,
+        json!([
+            { "address": 3, "value": true, "value_id": "pump" },
+            { "address": 4, "value": null, "value_id": null }
+        ])
+    );
+
+    let (status, _) = get_json(&slave, "/api/v1/slaves/2/coils?start=3").await;
+    assert_eq!(status, 404);
+
+    let (status, _) = get_json(&slave, "/api/v1/slaves/1/registers?start=3").await;
+    assert_eq!(status, 404);
+
+    let (status, _) = get_json(&slave, "/api/v1/slaves/1/coils?start=65535&count=2").await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn writes_with_unmapped_addresses_change_nothing() {
+    let slave = start_register_slave().await;
+
+    let status = put_registers(
+        &slave,
+        "/api/v1/slaves/1/holding_registers?start=11",
+        json!([0, 5, 9]),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (_, registers) =
+        get_json(&slave, "/api/v1/slaves/1/holding_registers?start=9&count=5").await;
+    assert_eq!(registers, unchanged_holding_registers());
+
+    //Across both values at once
+    let status = put_registers(
+        &slave,
+        "/api/v1/slaves/1/holding_registers?start=10",
+        json!([7, 0, 5]),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+    assert_eq!(master.read_holding_registers(1, 10, 3).await, vec![7, 0, 5]);
+
+    let (status, energy) = get_json(&slave, "/api/v1/values/energy").await;
+    assert_eq!(status, 200);
+    assert_eq!(energy, json!({ "Integer": 5 }));
+}
+
+#[tokio::test]
+async fn registers_must_match_the_table_type() {
+    let slave = start_register_slave().await;
+
+    let status = put_registers(&slave, "/api/v1/slaves/1/coils?start=3", json!([5])).await;
+    assert_eq!(status, 400);
+
+    let status = put_registers(
+        &slave,
+        "/api/v1/slaves/1/holding_registers?start=10",
+        json!([true]),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let status = put_registers(
+        &slave,
+        "/api/v1/slaves/1/holding_registers?start=10&count=2",
+        json!([1]),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let status = put_registers(&slave, "/api/v1/slaves/1/coils?start=3", json!([false])).await;
+    assert_eq!(status, 200);
+
+    //Inputs can't be written by masters, but they can through the API
+    let status = put_registers(
+        &slave,
+        "/api/v1/slaves/1/discrete_inputs?start=4",
+        json!([true]),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    let coils = Request::ReadCoils {
+        address: 3,
+        ammount: 1,
+    };
+    assert_eq!(
+        master.request(1, &coils).await,
+        Response::Coils(vec![false])
+    );
+
+    let inputs = Request::ReadDiscreteInputs {
+        address: 4,
+        ammount: 1,
+    };
+    assert_eq!(
+        master.request(1, &inputs).await,
+        Response::Coils(vec![true])
+    );
+}
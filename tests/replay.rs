@@ -0,0 +1,101 @@
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use modbus_watch::client::data::{write, DbManager, InsertValueMessage, PollQuality};
+use modbus_watch::client::model::PolledValue;
+use modbus_watch::common::model::Value;
+use modbus_watch::server::replay;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde_json::json;
+use tempfile::TempDir;
+
+fn polled_value(id: &str, starting_address: u16, data_type: &str) -> PolledValue {
+    serde_json::from_value(json!({
+        "id": id,
+        "starting_address": starting_address,
+        "table": "HoldingRegisters",
+        "bit_length": 16,
+        "data_type": data_type,
+        "poll_time": "1s"
+    }))
+    .unwrap()
+}
+
+fn poll(name: &str, secs: u64, value: Vec<u8>, quality: PollQuality) -> InsertValueMessage {
+    InsertValueMessage {
+        name: name.to_string(),
+        timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+        value,
+        quality,
+    }
+}
+
+//A capture like the ones Ultrabus leaves behind
+fn record_capture(path: &Path) {
+    DbManager::migrate_only(path.to_path_buf()).unwrap();
+
+    let pool = r2d2::Pool::new(SqliteConnectionManager::file(path)).unwrap();
+    let mut conn = pool.get().unwrap();
+
+    write::insert_modbus_value(&conn, &polled_value("speed", 100, "UnsignedInteger16"), 1).unwrap();
+    write::insert_modbus_value(&conn, &polled_value("offset", 101, "SignedInteger16"), 1).unwrap();
+    write::insert_modbus_value(&conn, &polled_value("status", 10, "UnsignedInteger16"), 2).unwrap();
+
+    write::insert_modbus_polls(
+        &mut conn,
+        &[
+            poll("speed", 1, vec![], PollQuality::Timeout),
+            poll("speed", 2, vec![0xE8, 0x03], PollQuality::Good),
+            poll("speed", 3, vec![0xD0, 0x07], PollQuality::Good),
+            poll("offset", 2, vec![0xF6, 0xFF], PollQuality::Good),
+            poll("status", 2, vec![], PollQuality::Exception(2)),
+        ],
+    )
+    .unwrap();
+}
+
+#[test]
+fn captures_are_served_from_their_first_good_poll() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("capture.db3");
+    record_capture(&path);
+
+    let config = replay::build_config_from_capture(&path, 1502).unwrap();
+
+    assert_eq!(config.len(), 1);
+    assert_eq!(config[0].port, Some(1502));
+
+    let slaves = &config[0].slaves;
+    assert_eq!(
+        slaves.iter().map(|slave| slave.id).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+
+    let default_value = |id: &str| {
+        slaves
+            .iter()
+            .flat_map(|slave| &slave.values)
+            .find(|value| value.id == id)
+            .map(|value| value.default_value.clone())
+            .unwrap()
+    };
+
+    assert_eq!(default_value("speed"), Value::Integer(1000));
+    assert_eq!(default_value("offset"), Value::Integer(-10));
+    //Never answered, so it starts at zero
+    assert_eq!(default_value("status"), Value::Integer(0));
+}
+
+#[test]
+fn broken_captures_are_rejected() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("capture.db3");
+    record_capture(&path);
+
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute("DROP TABLE modbus_polls", [])
+        .unwrap();
+
+    assert!(replay::build_config_from_capture(&path, 1502).is_err());
+}
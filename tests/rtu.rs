@@ -0,0 +1,270 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::time::Duration;
+
+use modbus_watch::client::comm::ModbusWatcher;
+use modbus_watch::client::data::{InsertValueMessage, PollQuality};
+use modbus_watch::client::model::PolledConnection;
+use modbus_watch::common::model::{DataType, Value};
+use modbus_watch::common::protocol::pdu::Request;
+use modbus_watch::common::protocol::rtu;
+use modbus_watch::common::value_processing;
+use modbus_watch::server::comm::ModbusServer;
+use modbus_watch::server::model::ServedConnection;
+use modbus_watch::server::state::{self, audit, AppState, SlaveFaults};
+use nix::pty::openpty;
+use nix::sys::termios::{self, SetArg};
+use nix::unistd::ttyname;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+//A pseudo-terminal whose device is handed to one side of the line
+fn open_raw_pty() -> (OwnedFd, OwnedFd, String) {
+    let pty = openpty(None, None).unwrap();
+
+    //No echo nor line editing before the serial port configures it
+    let mut attributes = termios::tcgetattr(&pty.slave).unwrap();
+    termios::cfmakeraw(&mut attributes);
+    termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &attributes).unwrap();
+
+    let device = ttyname(&pty.slave).unwrap().to_string_lossy().to_string();
+
+    (pty.master, pty.slave, device)
+}
+
+fn relay(from: File, mut to: File) {
+    std::thread::spawn(move || {
+        let mut from = from;
+        let mut buffer = [0u8; 256];
+
+        while let Ok(read) = from.read(&mut buffer) {
+            if read == 0 || to.write_all(&buffer[..read]).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+//Two pseudo-terminals joined through their masters, like `socat pty pty` does
+fn build_serial_line() -> (String, String) {
+    let (first_master, first_slave, first_device) = open_raw_pty();
+    let (second_master, second_slave, second_device) = open_raw_pty();
+
+    let first_master = File::from(first_master);
+    let second_master = File::from(second_master);
+
+    relay(
+        first_master.try_clone().unwrap(),
+        second_master.try_clone().unwrap(),
+    );
+    relay(second_master, first_master);
+
+    //Reading a master fails once no one holds its slave, so they are kept open
+    std::mem::forget(first_slave);
+    std::mem::forget(second_slave);
+
+    (first_device, second_device)
+}
+
+fn serial_transport(device: &str) -> serde_json::Value {
+    json!({
+        "type": "rtu",
+        "device": device,
+        "baud_rate": 19200,
+        "parity": "none",
+        "frame_delay": "20ms"
+    })
+}
+
+fn start_slave(device: &str, faults: serde_json::Value) -> (AppState, SlaveFaults) {
+    let config: Vec<ServedConnection> = serde_json::from_value(json!([{
+        "transport": serial_transport(device),
+        "slaves": [{
+            "id": 7,
+            "faults": faults,
+            "values": [
+                {
+                    "id": "speed",
+                    "starting_address": 100,
+                    "table": "HoldingRegisters",
+                    "bit_length": 16,
+                    "data_type": "UnsignedInteger16",
+                    "default_value": { "Integer": 1234 }
+                },
+                {
+                    "id": "energy",
+                    "starting_address": 200,
+                    "table": "HoldingRegisters",
+                    "bit_length": 32,
+                    "data_type": "SignedInteger32",
+                    "default_value": { "Integer": -70000 }
+                }
+            ]
+        }]
+    }]))
+    .unwrap();
+
+    for connection in &config {
+        connection.validate().unwrap();
+    }
+
+    let app_state = state::build_app_state(&config).unwrap();
+    let faults = state::build_slave_faults(&config);
+    let writes = audit::build_write_audit(10, None).unwrap();
+
+    ModbusServer::new(
+        &config,
+        app_state.clone(),
+        state::build_slave_latencies(&config),
+        faults.clone(),
+        writes,
+    )
+    .serve();
+
+    (app_state, faults)
+}
+
+fn master_config(device: &str) -> Vec<PolledConnection> {
+    let value = |id: &str, starting_address: u16, bit_length: u16, data_type: &str| {
+        json!({
+            "id": id,
+            "starting_address": starting_address,
+            "table": "HoldingRegisters",
+            "bit_length": bit_length,
+            "data_type": data_type,
+            "poll_time": "100ms"
+        })
+    };
+
+    serde_json::from_value(json!([{
+        "config": {
+            "max_simultaneous_connections": 1,
+            "max_response_time": "1s"
+        },
+        "transport": serial_transport(device),
+        "slaves": [{
+            "id": 7,
+            "values": [
+                value("speed", 100, 16, "UnsignedInteger16"),
+                value("energy", 200, 32, "SignedInteger32")
+            ]
+        }]
+    }]))
+    .unwrap()
+}
+
+async fn next_poll(rx: &mut mpsc::Receiver<InsertValueMessage>, name: &str) -> InsertValueMessage {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let poll = rx.recv().await.unwrap();
+
+            if poll.name == name {
+                return poll;
+            }
+        }
+    })
+    .await
+    .expect("No poll was received")
+}
+
+fn decode(poll: &InsertValueMessage, data_type: DataType) -> Value {
+    value_processing::format_value(poll.value.clone(), &data_type).unwrap()
+}
+
+async fn get_value(app_state: &AppState, id: &str) -> Value {
+    let app_state = app_state.lock().await;
+    let value = app_state.get(id).unwrap();
+
+    value_processing::registers_to_value(value.get_all_registers(), &value.config.formatting_params)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn master_polls_and_writes_the_slave_over_a_serial_line() {
+    let (slave_device, master_device) = build_serial_line();
+
+    let (app_state, _) = start_slave(&slave_device, json!([]));
+
+    let config = master_config(&master_device);
+    let (tx, mut rx) = mpsc::channel(64);
+
+    let mut watcher = ModbusWatcher::new(config, tx);
+    watcher.watch().await.unwrap();
+
+    let speed = next_poll(&mut rx, "speed").await;
+    assert_eq!(speed.quality, PollQuality::Good);
+    assert_eq!(
+        decode(&speed, DataType::UnsignedInteger16),
+        Value::Integer(1234)
+    );
+
+    let energy = next_poll(&mut rx, "energy").await;
+    assert_eq!(energy.quality, PollQuality::Good);
+    assert_eq!(
+        decode(&energy, DataType::SignedInteger32),
+        Value::Integer(-70000)
+    );
+
+    watcher
+        .get_writer()
+        .write_value(&"speed".to_string(), Value::Integer(4321))
+        .await
+        .unwrap();
+
+    assert_eq!(get_value(&app_state, "speed").await, Value::Integer(4321));
+
+    //Later polls see the written value
+    loop {
+        let speed = next_poll(&mut rx, "speed").await;
+
+        if decode(&speed, DataType::UnsignedInteger16) == Value::Integer(4321) {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn broadcasts_only_carry_out_writes() {
+    let (slave_device, master_device) = build_serial_line();
+
+    //Any request to the speed handled like an addressed one would disarm it
+    let (app_state, faults) = start_slave(
+        &slave_device,
+        json!([{
+            "id": "busy",
+            "action": { "type": "exception", "exception_code": 6 },
+            "value": "speed",
+            "one_shot": true
+        }]),
+    );
+
+    //Time for the slave to open its end of the line
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut line = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&master_device)
+        .unwrap();
+
+    let read = Request::ReadHoldingRegisters {
+        address: 100,
+        ammount: 1,
+    };
+    let write = Request::WriteSingleRegister {
+        address: 100,
+        value: 55,
+    };
+
+    for request in [read, write] {
+        line.write_all(&rtu::encode_frame(0, &request.encode()))
+            .unwrap();
+
+        //Longer than the silent interval between frames
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    assert_eq!(get_value(&app_state, "speed").await, Value::Integer(55));
+    assert!(faults.lock().await[&7][0].armed);
+}
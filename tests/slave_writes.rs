@@ -0,0 +1,225 @@
+mod common;
+
+use common::RawMaster;
+use modbus_watch::common::protocol::pdu::{self, Request, Response};
+use serde_json::json;
+use tweakable_modbus::ExceptionCode;
+
+fn exception(exception_code: ExceptionCode) -> Response {
+    Response::Exception(pdu::exception_code_to_u8(&exception_code))
+}
+
+#[tokio::test]
+async fn writes_to_input_only_addresses_are_illegal_functions() {
+    let values = vec![
+        common::input_u16("level", 20, 5),
+        common::discrete_input("running", 3, false),
+    ];
+
+    let slave = common::start_slave(json!([common::slave(1, values)])).await;
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    let response = master
+        .request(
+            1,
+            &Request::WriteSingleRegister {
+                address: 20,
+                value: 7,
+            },
+        )
+        .await;
+    assert_eq!(response, exception(ExceptionCode::IllegalFunction));
+
+    let response = master
+        .request(
+            1,
+            &Request::WriteSingleCoil {
+                address: 3,
+                value: true,
+            },
+        )
+        .await;
+    assert_eq!(response, exception(ExceptionCode::IllegalFunction));
+
+    //Nothing is served there in any table
+    let response = master
+        .request(
+            1,
+            &Request::WriteSingleRegister {
+                address: 21,
+                value: 7,
+            },
+        )
+        .await;
+    assert_eq!(response, exception(ExceptionCode::IllegalDataAddress));
+
+    let response = master
+        .request(
+            1,
+            &Request::ReadInputRegisters {
+                address: 20,
+                ammount: 1,
+            },
+        )
+        .await;
+    assert_eq!(response, Response::Registers(vec![5]));
+}
+
+#[tokio::test]
+async fn writes_to_read_only_values_are_illegal_addresses() {
+    let energy = common::with_fields(
+        common::holding_u16("energy", 30, 12),
+        json!({ "read_only": true }),
+    );
+
+    let slave = common::start_slave(json!([common::slave(1, vec![energy])])).await;
+
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    let response = master
+        .request(
+            1,
+            &Request::WriteSingleRegister {
+                address: 30,
+                value: 0,
+            },
+        )
+        .await;
+    assert_eq!(response, exception(ExceptionCode::IllegalDataAddress));
+
+    assert_eq!(master.read_holding_registers(1, 30, 1).await, vec![12]);
+}
+
+//The temperature is ABCD, so the high word comes first
+fn float_registers(value: f32) -> Vec<u16> {
+    let bits = value.to_bits();
+    vec![(bits >> 16) as u16, bits as u16]
+}
+
+async fn start_atomic_slave() -> common::TestSlave {
+    let values = vec![
+        common::with_fields(common::holding_u16("speed", 10, 100), json!({ "max": 500 })),
+        json!({
+            "id": "temperature",
+            "starting_address": 11,
+            "table": "HoldingRegisters",
+            "bit_length": 32,
+            "data_type": "Float",
+            "byte_order": "ABCD",
+            "default_value": { "FloatingPoint": 20.0 },
+            "min": -50,
+            "max": 150
+        }),
+    ];
+
+    common::start_slave(json!([common::slave(1, values)])).await
+}
+
+#[tokio::test]
+async fn invalid_multiple_writes_are_rejected_whole() {
+    let slave = start_atomic_slave().await;
+    let mut master = RawMaster::connect(slave.modbus_port).await;
+
+    let mut initial_registers = vec![100];
+    initial_registers.extend(float_registers(20.0));
+
+    assert_eq!(
+        master.read_holding_registers(1, 10, 3).await,
+        initial_registers
+    );
+
+    //The high word of 1000.0 over the low word of 20.0 decodes as 1000.0, above the maximum
+    let half_float = Request::WriteMultipleRegisters {
+        address: 10,
+        values: vec![200, float_registers(1000.0)[0]],
+    };
+
+    assert_eq!(
+        master.request(1, &half_float).await,
+        exception(ExceptionCode::IllegalDataValue)
+    );
+    assert_eq!(
+        master.read_holding_registers(1, 10, 3).await,
+        initial_registers
+    );
+
+    //The temperature is fine but the speed is out of range
+    let mut values = vec![600];
+    values.extend(float_registers(25.0));
+
+    let out_of_range = Request::WriteMultipleRegisters {
+        address: 10,
+        values,
+    };
+
+    assert_eq!(
+        master.request(1, &out_of_range).await,
+        exception(ExceptionCode::IllegalDataValue)
+    );
+    assert_eq!(
+        master.read_holding_registers(1, 10, 3).await,
+        initial_registers
+    );
+
+    let mut values = vec![200];
+    values.extend(float_registers(25.0));
+
+    let valid = Request::WriteMultipleRegisters {
+        address: 10,
+        values: values.clone(),
+    };
+
+    assert_eq!(master.request(1, &valid).await, Response::Written);
+    assert_eq!(master.read_holding_registers(1, 10, 3).await, values);
+}
+
+#[tokio::test]
+async fn reads_never_see_half_of_a_write() {
+    let slave = start_atomic_slave().await;
+    let port = slave.modbus_port;
+
+    //Both words change between the two values, so any mix of them would be noticed
+    let writer = tokio::spawn(async move {
+        let mut master = RawMaster::connect(port).await;
+
+        for i in 0..200 {
+            let value = if i % 2 == 0 { 0.1 } else { 123.456 };
+
+            let request = Request::WriteMultipleRegisters {
+                address: 11,
+                values: float_registers(value),
+            };
+
+            assert_eq!(master.request(1, &request).await, Response::Written);
+        }
+    });
+
+    let expected = [
+        float_registers(20.0),
+        float_registers(0.1),
+        float_registers(123.456),
+    ];
+
+    let mut reader = RawMaster::connect(port).await;
+    let mut reads = 0;
+
+    while !writer.is_finished() {
+        let registers = reader.read_holding_registers(1, 11, 2).await;
+        assert!(
+            expected.contains(&registers),
+            "Read half of a write: {:?}",
+            registers
+        );
+
+        reads += 1;
+    }
+
+    writer.await.unwrap();
+
+    assert!(reads > 0);
+    assert_eq!(
+        reader.read_holding_registers(1, 11, 2).await,
+        float_registers(123.456)
+    );
+}
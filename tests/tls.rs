@@ -0,0 +1,346 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use modbus_watch::client::comm::{ModbusWatcher, WriteError};
+use modbus_watch::client::data::PollQuality;
+use modbus_watch::client::model::PolledConnection;
+use modbus_watch::common::model::Value;
+use modbus_watch::common::value_processing;
+use modbus_watch::server::comm::ModbusServer;
+use modbus_watch::server::model::ServedConnection;
+use modbus_watch::server::state::{self, audit, AppState};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CustomExtension, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use serde_json::json;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tweakable_modbus::ExceptionCode;
+
+const ROLE_OID: &[u64] = &[1, 3, 6, 1, 4, 1, 50316, 802, 1];
+
+struct Authority {
+    certificate: Certificate,
+    key: KeyPair,
+}
+
+//Certificate and private key files of one side of the connection
+struct Identity {
+    certificate: PathBuf,
+    private_key: PathBuf,
+}
+
+fn build_authority(name: &str, dir: &Path) -> (Authority, PathBuf) {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+
+    let key = KeyPair::generate().unwrap();
+    let certificate = params.self_signed(&key).unwrap();
+
+    let path = dir.join(format!("{}.pem", name));
+    std::fs::write(&path, certificate.pem()).unwrap();
+
+    (Authority { certificate, key }, path)
+}
+
+fn build_identity(
+    name: &str,
+    authority: &Authority,
+    usage: ExtendedKeyUsagePurpose,
+    role: Option<&str>,
+    dir: &Path,
+) -> Identity {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.extended_key_usages = vec![usage];
+
+    //The role is a DER UTF8String
+    if let Some(role) = role {
+        let mut content = vec![0x0C, role.len() as u8];
+        content.extend_from_slice(role.as_bytes());
+        params.custom_extensions = vec![CustomExtension::from_oid_content(ROLE_OID, content)];
+    }
+
+    let key = KeyPair::generate().unwrap();
+    let certificate = params
+        .signed_by(&key, &authority.certificate, &authority.key)
+        .unwrap();
+
+    let identity = Identity {
+        certificate: dir.join(format!("{}.pem", name)),
+        private_key: dir.join(format!("{}.key", name)),
+    };
+
+    std::fs::write(&identity.certificate, certificate.pem()).unwrap();
+    std::fs::write(&identity.private_key, key.serialize_pem()).unwrap();
+
+    identity
+}
+
+async fn start_slave(port: u16, identity: &Identity, client_ca: &Path) -> AppState {
+    let config: Vec<ServedConnection> = serde_json::from_value(json!([{
+        "port": port,
+        "tls": {
+            "certificate": identity.certificate,
+            "private_key": identity.private_key,
+            "client_ca_certificate": client_ca
+        },
+        "slaves": [{
+            "id": 1,
+            "values": [{
+                "id": "setpoint",
+                "starting_address": 10,
+                "table": "HoldingRegisters",
+                "bit_length": 16,
+                "data_type": "UnsignedInteger16",
+                "default_value": { "Integer": 10 },
+                "write_roles": ["operator"]
+            }]
+        }]
+    }]))
+    .unwrap();
+
+    for connection in &config {
+        connection.validate().unwrap();
+    }
+
+    let app_state = state::build_app_state(&config).unwrap();
+
+    ModbusServer::new(
+        &config,
+        app_state.clone(),
+        state::build_slave_latencies(&config),
+        state::build_slave_faults(&config),
+        audit::build_write_audit(10, None).unwrap(),
+    )
+    .serve();
+
+    common::wait_for_port(port).await;
+
+    app_state
+}
+
+fn build_master(port: u16, identity: &Identity, ca: &Path) -> ModbusWatcher {
+    let config: Vec<PolledConnection> = serde_json::from_value(json!([{
+        "config": {
+            "max_simultaneous_connections": 1,
+            "max_response_time": "1s",
+            "min_reconnect_delay": "100ms"
+        },
+        "port": port,
+        "tls": {
+            "certificate": identity.certificate,
+            "private_key": identity.private_key,
+            "ca_certificate": ca,
+            "server_name": "localhost"
+        },
+        "slaves": [{
+            "id": 1,
+            "values": [{
+                "id": "setpoint",
+                "starting_address": 10,
+                "table": "HoldingRegisters",
+                "bit_length": 16,
+                "data_type": "UnsignedInteger16",
+                "poll_time": "100ms"
+            }]
+        }]
+    }]))
+    .unwrap();
+
+    let (tx, _rx) = mpsc::channel(64);
+
+    ModbusWatcher::new(config, tx)
+}
+
+async fn get_setpoint(app_state: &AppState) -> Value {
+    let app_state = app_state.lock().await;
+    let setpoint = app_state.get("setpoint").unwrap();
+
+    value_processing::registers_to_value(
+        setpoint.get_all_registers(),
+        &setpoint.config.formatting_params,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn writes_need_the_role_from_the_master_certificate() {
+    let dir = TempDir::new().unwrap();
+
+    let (authority, ca) = build_authority("ca", dir.path());
+    let slave = build_identity(
+        "slave",
+        &authority,
+        ExtendedKeyUsagePurpose::ServerAuth,
+        None,
+        dir.path(),
+    );
+    let operator = build_identity(
+        "operator",
+        &authority,
+        ExtendedKeyUsagePurpose::ClientAuth,
+        Some("operator"),
+        dir.path(),
+    );
+    let viewer = build_identity(
+        "viewer",
+        &authority,
+        ExtendedKeyUsagePurpose::ClientAuth,
+        None,
+        dir.path(),
+    );
+
+    let port = common::get_free_port();
+    let app_state = start_slave(port, &slave, &ca).await;
+
+    let result = build_master(port, &viewer, &ca)
+        .get_writer()
+        .write_value(&"setpoint".to_string(), Value::Integer(20))
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(WriteError::Exception(ExceptionCode::IllegalFunction))
+    ));
+    assert_eq!(get_setpoint(&app_state).await, Value::Integer(10));
+
+    build_master(port, &operator, &ca)
+        .get_writer()
+        .write_value(&"setpoint".to_string(), Value::Integer(30))
+        .await
+        .unwrap();
+
+    assert_eq!(get_setpoint(&app_state).await, Value::Integer(30));
+}
+
+#[tokio::test]
+async fn masters_without_a_role_can_still_poll() {
+    let dir = TempDir::new().unwrap();
+
+    let (authority, ca) = build_authority("ca", dir.path());
+    let slave = build_identity(
+        "slave",
+        &authority,
+        ExtendedKeyUsagePurpose::ServerAuth,
+        None,
+        dir.path(),
+    );
+    let viewer = build_identity(
+        "viewer",
+        &authority,
+        ExtendedKeyUsagePurpose::ClientAuth,
+        None,
+        dir.path(),
+    );
+
+    let port = common::get_free_port();
+    start_slave(port, &slave, &ca).await;
+
+    let config: Vec<PolledConnection> = serde_json::from_value(json!([{
+        "config": {
+            "max_simultaneous_connections": 1,
+            "max_response_time": "1s"
+        },
+        "port": port,
+        "tls": {
+            "certificate": viewer.certificate,
+            "private_key": viewer.private_key,
+            "ca_certificate": ca,
+            "server_name": "localhost"
+        },
+        "slaves": [{
+            "id": 1,
+            "values": [{
+                "id": "setpoint",
+                "starting_address": 10,
+                "table": "HoldingRegisters",
+                "bit_length": 16,
+                "data_type": "UnsignedInteger16",
+                "poll_time": "100ms"
+            }]
+        }]
+    }]))
+    .unwrap();
+
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut watcher = ModbusWatcher::new(config, tx);
+    watcher.watch().await.unwrap();
+
+    let poll = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(poll.quality, PollQuality::Good);
+    assert_eq!(poll.value, vec![10, 0]);
+}
+
+#[tokio::test]
+async fn masters_must_present_a_certificate_from_the_client_ca() {
+    let dir = TempDir::new().unwrap();
+
+    let (authority, ca) = build_authority("ca", dir.path());
+    let (other_authority, _) = build_authority("other-ca", dir.path());
+
+    let slave = build_identity(
+        "slave",
+        &authority,
+        ExtendedKeyUsagePurpose::ServerAuth,
+        None,
+        dir.path(),
+    );
+    let stranger = build_identity(
+        "stranger",
+        &other_authority,
+        ExtendedKeyUsagePurpose::ClientAuth,
+        Some("operator"),
+        dir.path(),
+    );
+
+    let port = common::get_free_port();
+    let app_state = start_slave(port, &slave, &ca).await;
+
+    let result = build_master(port, &stranger, &ca)
+        .get_writer()
+        .write_value(&"setpoint".to_string(), Value::Integer(40))
+        .await;
+
+    assert!(matches!(result, Err(WriteError::Connection(_))));
+    assert_eq!(get_setpoint(&app_state).await, Value::Integer(10));
+}
+
+#[test]
+fn tls_connections_default_to_the_security_port() {
+    let tls_master: PolledConnection = serde_json::from_value(json!({
+        "tls": {
+            "certificate": "master.pem",
+            "private_key": "master.key",
+            "ca_certificate": "ca.pem"
+        },
+        "slaves": []
+    }))
+    .unwrap();
+    let plain_master: PolledConnection = serde_json::from_value(json!({ "slaves": [] })).unwrap();
+
+    assert_eq!(tls_master.get_port(), 802);
+    assert_eq!(plain_master.get_port(), 502);
+
+    let tls_slave: ServedConnection = serde_json::from_value(json!({
+        "tls": {
+            "certificate": "slave.pem",
+            "private_key": "slave.key"
+        },
+        "slaves": []
+    }))
+    .unwrap();
+    let explicit_slave: ServedConnection =
+        serde_json::from_value(json!({ "port": 1502, "slaves": [] })).unwrap();
+
+    assert_eq!(tls_slave.get_port(), 802);
+    assert_eq!(explicit_slave.get_port(), 1502);
+}
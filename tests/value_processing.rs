@@ -0,0 +1,718 @@
+use modbus_watch::client::model::PolledValue;
+use modbus_watch::common::model::{
+    ByteOrder, DataType, ModbusTable, StringEncoding, Value, ValueFormattingParams,
+};
+use modbus_watch::common::value_processing::{
+    format_aggregate_value, format_value, merge_value_into_registers, registers_to_bytes,
+    registers_to_value, value_to_bytes, value_to_registers,
+};
+use modbus_watch::server::model::ServedValue;
+use proptest::prelude::*;
+use serde_json::json;
+use tweakable_modbus::ModbusDataType;
+
+fn params(
+    data_type: DataType,
+    starting_bit: u8,
+    bit_length: u16,
+    (byte_swap, word_swap, double_word_swap): (bool, bool, bool),
+) -> ValueFormattingParams {
+    ValueFormattingParams {
+        starting_bit,
+        bit_length,
+        data_type,
+        byte_swap,
+        word_swap,
+        double_word_swap,
+        byte_order: None,
+        scale: None,
+        offset: None,
+        raw_range: None,
+        engineering_range: None,
+        unit: None,
+        decimals: None,
+        states: None,
+    }
+}
+
+fn registers(values: &[u16]) -> Vec<ModbusDataType> {
+    values
+        .iter()
+        .map(|value| ModbusDataType::Register(*value))
+        .collect()
+}
+
+fn get_register(register: &ModbusDataType) -> u16 {
+    match register {
+        ModbusDataType::Register(register) => *register,
+        ModbusDataType::Coil(_) => panic!("Expected a register"),
+    }
+}
+
+//What an integer value with the given bits can hold
+fn integer_range(data_type: &DataType, bit_length: u16) -> (i128, i128) {
+    let width = (bit_length as u32).min(data_type.byte_size() as u32 * 8);
+
+    if data_type.is_signed() {
+        (-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1)
+    } else {
+        (0, (1i128 << width) - 1)
+    }
+}
+
+fn swaps() -> impl Strategy<Value = (bool, bool, bool)> {
+    any::<(bool, bool, bool)>()
+}
+
+fn integer_value() -> impl Strategy<Value = (ValueFormattingParams, Value)> {
+    let data_types = prop_oneof![
+        Just(DataType::Byte),
+        Just(DataType::SignedByte),
+        Just(DataType::UnsignedInteger16),
+        Just(DataType::SignedInteger16),
+        Just(DataType::UnsignedInteger32),
+        Just(DataType::SignedInteger32),
+        Just(DataType::UnsignedInteger64),
+        Just(DataType::SignedInteger64),
+    ];
+
+    (data_types, 0..=40u8, 1..=64u16, swaps()).prop_flat_map(
+        |(data_type, starting_bit, bit_length, swaps)| {
+            let (min, max) = integer_range(&data_type, bit_length);
+            let config = params(data_type, starting_bit, bit_length, swaps);
+
+            (min..=max).prop_map(move |value| (config.clone(), Value::Integer(value)))
+        },
+    )
+}
+
+fn bcd_value() -> impl Strategy<Value = (ValueFormattingParams, Value)> {
+    let data_types = prop_oneof![Just(DataType::Bcd16), Just(DataType::Bcd32)];
+
+    (data_types, 0..=40u8, swaps()).prop_flat_map(|(data_type, starting_bit, swaps)| {
+        let digits = data_type.get_bcd_digits().unwrap();
+        let config = params(
+            data_type.clone(),
+            starting_bit,
+            data_type.min_bit_size(),
+            swaps,
+        );
+
+        (0..10i128.pow(digits)).prop_map(move |value| (config.clone(), Value::Integer(value)))
+    })
+}
+
+fn floating_point_value() -> impl Strategy<Value = (ValueFormattingParams, Value)> {
+    let float = (
+        0..=40u8,
+        swaps(),
+        any::<f32>().prop_filter("NaN", |value| !value.is_nan()),
+    )
+        .prop_map(|(starting_bit, swaps, value)| {
+            (
+                params(DataType::Float, starting_bit, 32, swaps),
+                Value::FloatingPoint(value as f64),
+            )
+        });
+
+    let double = (
+        0..=40u8,
+        swaps(),
+        any::<f64>().prop_filter("NaN", |value| !value.is_nan()),
+    )
+        .prop_map(|(starting_bit, swaps, value)| {
+            (
+                params(DataType::Double, starting_bit, 64, swaps),
+                Value::FloatingPoint(value),
+            )
+        });
+
+    prop_oneof![float, double]
+}
+
+fn text_value() -> impl Strategy<Value = (ValueFormattingParams, Value)> {
+    let encodings = prop_oneof![
+        Just(StringEncoding::Ascii),
+        Just(StringEncoding::Latin1),
+        Just(StringEncoding::Utf8),
+    ];
+
+    (1..=24u16, encodings, swaps()).prop_flat_map(|(length, encoding, swaps)| {
+        let data_type = DataType::String { length, encoding };
+        let config = params(data_type.clone(), 0, data_type.min_bit_size(), swaps);
+
+        //Accents for Latin-1, and Greek, CJK and emoji taking several bytes for UTF-8
+        let characters = match encoding {
+            StringEncoding::Ascii => "[ -~]",
+            StringEncoding::Latin1 => "[ -~\u{a0}-\u{ff}]",
+            StringEncoding::Utf8 => {
+                "[ -~\u{a0}-\u{17f}\u{391}-\u{3c9}\u{4e00}-\u{4e3f}\u{1f600}-\u{1f64f}]"
+            }
+        };
+
+        proptest::string::string_regex(&format!("{}{{0,{}}}", characters, length))
+            .unwrap()
+            .prop_map(move |text| (config.clone(), Value::Text(fit_text(text, length))))
+    })
+}
+
+//Multi-byte characters may not fit in the value, and trailing spaces are trimmed when decoding
+fn fit_text(text: String, length: u16) -> String {
+    let mut fitted = String::new();
+
+    for character in text.chars() {
+        if fitted.len() + character.len_utf8() > length as usize {
+            break;
+        }
+
+        fitted.push(character);
+    }
+
+    fitted.trim_end_matches(' ').to_string()
+}
+
+fn register_value() -> impl Strategy<Value = (ValueFormattingParams, Value)> {
+    prop_oneof![
+        integer_value(),
+        bcd_value(),
+        floating_point_value(),
+        text_value(),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn register_values_round_trip((config, value) in register_value()) {
+        prop_assume!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+        let encoded = value_to_registers(value.clone(), &config).unwrap();
+
+        let ending_bit = config.starting_bit as usize + config.bit_length as usize;
+        prop_assert_eq!(encoded.len(), ending_bit.div_ceil(16));
+
+        //Slave side, registers written by the API and read back
+        prop_assert_eq!(registers_to_value(encoded.clone(), &config).unwrap(), value.clone());
+
+        //Master side, bytes stored on every poll and decoded when reading the history
+        let stored = registers_to_bytes(encoded, &config);
+        prop_assert_eq!(format_value(stored, &config.data_type).unwrap(), value);
+    }
+
+    #[test]
+    fn coils_round_trip(value in any::<bool>()) {
+        let config = params(DataType::Boolean, 0, 1, (false, false, false));
+        prop_assert!(config.validate(ModbusTable::Coils).is_ok());
+
+        let encoded = value_to_registers(Value::Boolean(value), &config).unwrap();
+
+        prop_assert_eq!(encoded.clone(), vec![ModbusDataType::Coil(value)]);
+        prop_assert_eq!(registers_to_value(encoded, &config).unwrap(), Value::Boolean(value));
+    }
+
+    #[test]
+    fn merged_writes_keep_the_neighbouring_bits(
+        (config, value) in register_value(),
+        current in proptest::collection::vec(any::<u16>(), 16),
+    ) {
+        prop_assume!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+        let register_ammount =
+            (config.starting_bit as usize + config.bit_length as usize).div_ceil(16);
+        let current = registers(&current[..register_ammount]);
+
+        //Master writes of values that don't fill their registers
+        let merged = merge_value_into_registers(current.clone(), value.clone(), &config).unwrap();
+        prop_assert_eq!(registers_to_value(merged.clone(), &config).unwrap(), value.clone());
+
+        //Bits that change between merging into zeros and into ones are the ones outside the value
+        let zeros = merge_value_into_registers(registers(&vec![0; register_ammount]), value.clone(), &config).unwrap();
+        let ones = merge_value_into_registers(registers(&vec![0xFFFF; register_ammount]), value, &config).unwrap();
+
+        for i in 0..register_ammount {
+            let outside = get_register(&zeros[i]) ^ get_register(&ones[i]);
+            let expected = (get_register(&current[i]) & outside) | (get_register(&zeros[i]) & !outside);
+
+            prop_assert_eq!(get_register(&merged[i]), expected);
+        }
+    }
+
+    #[test]
+    fn aggregates_round_trip((config, value) in register_value()) {
+        prop_assert_eq!(format_aggregate_value(value_to_bytes(value.clone()), &config).unwrap(), value);
+    }
+}
+
+#[test]
+fn float_is_decoded_from_two_registers() {
+    //1.5 is 0x3FC00000, sent high word first
+    let config = params(DataType::Float, 0, 32, (false, true, false));
+
+    let value = registers_to_value(registers(&[0x3FC0, 0x0000]), &config).unwrap();
+
+    assert_eq!(value, Value::FloatingPoint(1.5));
+    assert_eq!(
+        value_to_registers(value, &config).unwrap(),
+        registers(&[0x3FC0, 0x0000])
+    );
+}
+
+#[test]
+fn narrow_signed_values_keep_their_sign() {
+    let config = params(DataType::SignedInteger16, 4, 12, (false, false, false));
+
+    let encoded = value_to_registers(Value::Integer(-3), &config).unwrap();
+
+    assert_eq!(encoded, registers(&[0xFFD0]));
+    assert_eq!(
+        registers_to_value(encoded, &config).unwrap(),
+        Value::Integer(-3)
+    );
+}
+
+#[test]
+fn odd_byte_values_fill_a_register() {
+    let config = params(DataType::SignedByte, 0, 8, (false, false, false));
+
+    let encoded = value_to_registers(Value::Integer(-1), &config).unwrap();
+
+    assert_eq!(encoded, registers(&[0x00FF]));
+}
+
+#[test]
+fn strings_start_from_the_high_byte() {
+    let data_type = DataType::String {
+        length: 5,
+        encoding: StringEncoding::Ascii,
+    };
+    let config = params(data_type, 0, 48, (false, false, false));
+
+    let encoded = value_to_registers(Value::Text("V1.2".to_string()), &config).unwrap();
+
+    assert_eq!(encoded, registers(&[0x5631, 0x2E32, 0x0000]));
+    assert_eq!(
+        registers_to_value(registers(&[0x5631, 0x2E32, 0x2000]), &config).unwrap(),
+        Value::Text("V1.2".to_string())
+    );
+}
+
+#[test]
+fn bcd_rejects_invalid_digits() {
+    let config = params(DataType::Bcd16, 0, 16, (false, false, false));
+
+    assert_eq!(
+        registers_to_value(registers(&[0x1234]), &config).unwrap(),
+        Value::Integer(1234)
+    );
+    assert!(registers_to_value(registers(&[0x12A4]), &config).is_err());
+    assert!(value_to_registers(Value::Integer(10000), &config).is_err());
+}
+
+fn with_byte_order(
+    data_type: DataType,
+    bit_length: u16,
+    byte_order: ByteOrder,
+) -> ValueFormattingParams {
+    ValueFormattingParams {
+        byte_order: Some(byte_order),
+        ..params(data_type, 0, bit_length, (false, false, false))
+    }
+}
+
+//Registers as a master sees them on the wire, first register first
+fn assert_byte_order(config: &ValueFormattingParams, value: i128, expected: &[u16]) {
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+    let encoded = value_to_registers(Value::Integer(value), config).unwrap();
+
+    assert_eq!(encoded, registers(expected), "{:?}", config.byte_order);
+    assert_eq!(
+        registers_to_value(encoded, config).unwrap(),
+        Value::Integer(value)
+    );
+}
+
+#[test]
+fn byte_orders_16_bits() {
+    let value = 0x1122;
+
+    assert_byte_order(
+        &with_byte_order(DataType::UnsignedInteger16, 16, ByteOrder::Ab),
+        value,
+        &[0x1122],
+    );
+    assert_byte_order(
+        &with_byte_order(DataType::UnsignedInteger16, 16, ByteOrder::Ba),
+        value,
+        &[0x2211],
+    );
+}
+
+#[test]
+fn byte_orders_32_bits() {
+    let value = 0x11223344;
+    let config = |byte_order| with_byte_order(DataType::UnsignedInteger32, 32, byte_order);
+
+    assert_byte_order(&config(ByteOrder::Abcd), value, &[0x1122, 0x3344]);
+    assert_byte_order(&config(ByteOrder::Cdab), value, &[0x3344, 0x1122]);
+    assert_byte_order(&config(ByteOrder::Badc), value, &[0x2211, 0x4433]);
+    assert_byte_order(&config(ByteOrder::Dcba), value, &[0x4433, 0x2211]);
+}
+
+#[test]
+fn byte_orders_64_bits() {
+    let value = 0x1122334455667788;
+    let config = |byte_order| with_byte_order(DataType::UnsignedInteger64, 64, byte_order);
+
+    assert_byte_order(
+        &config(ByteOrder::Abcdefgh),
+        value,
+        &[0x1122, 0x3344, 0x5566, 0x7788],
+    );
+    assert_byte_order(
+        &config(ByteOrder::Ghefcdab),
+        value,
+        &[0x7788, 0x5566, 0x3344, 0x1122],
+    );
+    assert_byte_order(
+        &config(ByteOrder::Badcfehg),
+        value,
+        &[0x2211, 0x4433, 0x6655, 0x8877],
+    );
+    assert_byte_order(
+        &config(ByteOrder::Hgfedcba),
+        value,
+        &[0x8877, 0x6655, 0x4433, 0x2211],
+    );
+}
+
+#[test]
+fn byte_orders_are_the_legacy_swaps() {
+    let orders = [
+        (ByteOrder::Abcd, (false, true, false)),
+        (ByteOrder::Cdab, (false, false, false)),
+        (ByteOrder::Badc, (true, true, false)),
+        (ByteOrder::Dcba, (true, false, false)),
+    ];
+
+    for (byte_order, swaps) in orders {
+        let preset = with_byte_order(DataType::Float, 32, byte_order);
+        let legacy = params(DataType::Float, 0, 32, swaps);
+
+        assert_eq!(
+            value_to_registers(Value::FloatingPoint(1.5), &preset).unwrap(),
+            value_to_registers(Value::FloatingPoint(1.5), &legacy).unwrap()
+        );
+    }
+}
+
+#[test]
+fn byte_order_must_match_the_data_type() {
+    let config = with_byte_order(DataType::UnsignedInteger32, 32, ByteOrder::Abcdefgh);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+
+    let config = with_byte_order(DataType::Byte, 8, ByteOrder::Ab);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+
+    let data_type = DataType::String {
+        length: 4,
+        encoding: StringEncoding::Ascii,
+    };
+    let config = with_byte_order(data_type, 32, ByteOrder::Ba);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+    assert_eq!(
+        value_to_registers(Value::Text("V1.2".to_string()), &config).unwrap(),
+        registers(&[0x3156, 0x322E])
+    );
+}
+
+#[test]
+fn byte_order_excludes_the_legacy_swaps() {
+    let config = ValueFormattingParams {
+        word_swap: true,
+        ..with_byte_order(DataType::Double, 64, ByteOrder::Abcdefgh)
+    };
+
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+}
+
+fn with_states(data_type: DataType, bit_length: u16, states: &[(i64, &str)]) -> ValueFormattingParams {
+    ValueFormattingParams {
+        states: Some(
+            states
+                .iter()
+                .map(|(value, label)| (*value, label.to_string()))
+                .collect(),
+        ),
+        ..params(data_type, 0, bit_length, (false, false, false))
+    }
+}
+
+#[test]
+fn states_label_their_values() {
+    let config = with_states(
+        DataType::UnsignedInteger16,
+        16,
+        &[(0, "Stopped"), (1, "Running"), (2, "Fault")],
+    );
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+    let value = registers_to_value(registers(&[1]), &config).unwrap();
+    assert_eq!(config.get_state_label(&value), Some("Running".to_string()));
+    assert_eq!(config.get_state_label(&Value::Integer(7)), None);
+    assert_eq!(config.get_state_value("Fault"), Some(2));
+    assert_eq!(config.get_state_value("Idle"), None);
+}
+
+#[test]
+fn states_need_unique_labels_on_integers() {
+    let config = with_states(DataType::UnsignedInteger16, 16, &[(0, "Off"), (1, "Off")]);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+
+    let config = with_states(DataType::Float, 32, &[(0, "Off")]);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+
+    let config = ValueFormattingParams {
+        scale: Some(0.1),
+        ..with_states(DataType::UnsignedInteger16, 16, &[(0, "Off")])
+    };
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_err());
+}
+
+//The params are flattened into the values, which buffers the keys of the states as strings
+#[test]
+fn states_are_read_from_value_configs() {
+    let polled: PolledValue = serde_json::from_value(json!({
+        "id": "status",
+        "starting_address": 10,
+        "table": "HoldingRegisters",
+        "bit_length": 16,
+        "data_type": "UnsignedInteger16",
+        "poll_time": "1s",
+        "states": { "0": "Stopped", "1": "Running", "-1": "Fault" }
+    }))
+    .unwrap();
+
+    let states = polled.formatting_params.states.as_ref().unwrap();
+    assert_eq!(states.get(&0), Some(&"Stopped".to_string()));
+    assert_eq!(states.get(&-1), Some(&"Fault".to_string()));
+    assert!(polled.validate(125).is_ok());
+
+    let served: ServedValue = serde_json::from_value(json!({
+        "id": "status",
+        "starting_address": 10,
+        "table": "HoldingRegisters",
+        "bit_length": 16,
+        "data_type": "UnsignedInteger16",
+        "default_value": { "Integer": 1 },
+        "states": { "0": "Stopped", "1": "Running" }
+    }))
+    .unwrap();
+
+    assert_eq!(
+        served
+            .formatting_params
+            .get_state_label(&served.default_value),
+        Some("Running".to_string())
+    );
+
+    let not_a_number = serde_json::from_value::<ServedValue>(json!({
+        "id": "status",
+        "starting_address": 10,
+        "table": "HoldingRegisters",
+        "bit_length": 16,
+        "data_type": "UnsignedInteger16",
+        "default_value": { "Integer": 1 },
+        "states": { "on": "Running" }
+    }));
+    assert!(not_a_number.is_err());
+}
+
+fn served_value(
+    data_type: serde_json::Value,
+    bit_length: u16,
+    default_value: serde_json::Value,
+) -> ServedValue {
+    serde_json::from_value(json!({
+        "id": "name",
+        "starting_address": 10,
+        "table": "HoldingRegisters",
+        "bit_length": bit_length,
+        "data_type": data_type,
+        "default_value": default_value
+    }))
+    .unwrap()
+}
+
+#[test]
+fn default_values_must_be_encodable() {
+    let ascii = json!({ "String": { "length": 4 } });
+    let latin1 = json!({ "String": { "length": 4, "encoding": "Latin1" } });
+
+    assert!(served_value(ascii.clone(), 32, json!({ "Text": "pump" }))
+        .validate(100)
+        .is_ok());
+    assert!(served_value(latin1.clone(), 32, json!({ "Text": "niño" }))
+        .validate(100)
+        .is_ok());
+
+    //Too long, not ASCII and not Latin-1
+    assert!(served_value(ascii.clone(), 32, json!({ "Text": "pumps" }))
+        .validate(100)
+        .is_err());
+    assert!(served_value(ascii, 32, json!({ "Text": "niño" }))
+        .validate(100)
+        .is_err());
+    assert!(served_value(latin1, 32, json!({ "Text": "5 €" }))
+        .validate(100)
+        .is_err());
+
+    assert!(
+        served_value(json!("UnsignedInteger16"), 16, json!({ "Text": "pump" }))
+            .validate(100)
+            .is_err()
+    );
+}
+
+fn with_scaling(
+    data_type: DataType,
+    bit_length: u16,
+    scale: f64,
+    offset: f64,
+) -> ValueFormattingParams {
+    ValueFormattingParams {
+        scale: Some(scale),
+        offset: Some(offset),
+        ..params(data_type, 0, bit_length, (false, false, false))
+    }
+}
+
+fn with_ranges(raw_range: [f64; 2], engineering_range: [f64; 2]) -> ValueFormattingParams {
+    ValueFormattingParams {
+        raw_range: Some(raw_range),
+        engineering_range: Some(engineering_range),
+        ..params(DataType::UnsignedInteger16, 0, 16, (false, false, false))
+    }
+}
+
+fn assert_close(value: Value, expected: f64) {
+    match value {
+        Value::FloatingPoint(value) => {
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected)
+        }
+        value => panic!("Expected a floating point value, found {:?}", value),
+    }
+}
+
+#[test]
+fn gain_and_offset_round_trip() {
+    //Tenths of a degree with a -40 offset
+    let config = with_scaling(DataType::SignedInteger16, 16, 0.1, -40.0);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+    assert_close(registers_to_value(registers(&[650]), &config).unwrap(), 25.0);
+    assert_close(registers_to_value(registers(&[0xFFF6]), &config).unwrap(), -41.0);
+
+    assert_eq!(
+        value_to_registers(Value::FloatingPoint(25.0), &config).unwrap(),
+        registers(&[650])
+    );
+    //Integers are taken as engineering values too
+    assert_eq!(
+        value_to_registers(Value::Integer(-41), &config).unwrap(),
+        registers(&[0xFFF6])
+    );
+}
+
+#[test]
+fn ranges_map_raw_to_engineering_values() {
+    //A 4-20mA transmitter read in microamps covering 0 to 100 bar
+    let config = with_ranges([4000.0, 20000.0], [0.0, 100.0]);
+    assert!(config.validate(ModbusTable::HoldingRegisters).is_ok());
+
+    assert_close(registers_to_value(registers(&[4000]), &config).unwrap(), 0.0);
+    assert_close(registers_to_value(registers(&[12000]), &config).unwrap(), 50.0);
+    assert_close(registers_to_value(registers(&[20000]), &config).unwrap(), 100.0);
+
+    assert_eq!(
+        value_to_registers(Value::FloatingPoint(75.0), &config).unwrap(),
+        registers(&[16000])
+    );
+
+    //Inverted ranges work the same way
+    let config = with_ranges([0.0, 1000.0], [100.0, 0.0]);
+    assert_close(registers_to_value(registers(&[250]), &config).unwrap(), 75.0);
+    assert_eq!(
+        value_to_registers(Value::FloatingPoint(75.0), &config).unwrap(),
+        registers(&[250])
+    );
+}
+
+#[test]
+fn engineering_values_are_rounded_to_their_decimals() {
+    let config = ValueFormattingParams {
+        decimals: Some(2),
+        ..with_scaling(DataType::UnsignedInteger16, 16, 0.001, 0.0)
+    };
+
+    assert_eq!(
+        registers_to_value(registers(&[12346]), &config).unwrap(),
+        Value::FloatingPoint(12.35)
+    );
+
+    //Unscaled floats are rounded too
+    let config = ValueFormattingParams {
+        decimals: Some(1),
+        ..params(DataType::Float, 0, 32, (false, true, false))
+    };
+
+    //1.25 is 0x3FA00000
+    assert_eq!(
+        registers_to_value(registers(&[0x3FA0, 0x0000]), &config).unwrap(),
+        Value::FloatingPoint(1.3)
+    );
+}
+
+#[test]
+fn scaled_aggregates_are_engineering_values() {
+    let config = with_scaling(DataType::UnsignedInteger16, 16, 0.1, 0.0);
+
+    assert_eq!(
+        format_aggregate_value(value_to_bytes(Value::FloatingPoint(25.45)), &config).unwrap(),
+        Value::FloatingPoint(25.45)
+    );
+
+    let config = with_ranges([4000.0, 20000.0], [0.0, 100.0]);
+
+    assert_eq!(
+        format_aggregate_value(value_to_bytes(Value::FloatingPoint(-3.5)), &config).unwrap(),
+        Value::FloatingPoint(-3.5)
+    );
+}
+
+#[test]
+fn raw_values_must_fit_in_the_value_bits() {
+    let config = with_scaling(DataType::UnsignedInteger16, 16, 0.1, 0.0);
+
+    assert!(value_to_registers(Value::FloatingPoint(6553.5), &config).is_ok());
+    assert!(value_to_registers(Value::FloatingPoint(6553.6), &config).is_err());
+    assert!(value_to_registers(Value::FloatingPoint(-0.1), &config).is_err());
+
+    //Only 12 bits of the register belong to the value
+    let config = with_scaling(DataType::SignedInteger16, 12, 1.0, 0.0);
+
+    assert!(value_to_registers(Value::FloatingPoint(2047.0), &config).is_ok());
+    assert!(value_to_registers(Value::FloatingPoint(2048.0), &config).is_err());
+    assert!(value_to_registers(Value::FloatingPoint(-2049.0), &config).is_err());
+
+    //Master writes merged into the current registers too
+    assert!(
+        merge_value_into_registers(registers(&[0xF000]), Value::FloatingPoint(4000.0), &config)
+            .is_err()
+    );
+
+    //And unscaled integers
+    let config = params(DataType::UnsignedInteger16, 0, 16, (false, false, false));
+
+    assert!(value_to_registers(Value::Integer(65536), &config).is_err());
+    assert!(value_to_registers(Value::Integer(-1), &config).is_err());
+}